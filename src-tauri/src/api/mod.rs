@@ -1,13 +1,29 @@
 //! 请描述文件用途。
+use std::error::Error;
 use std::path::PathBuf;
+use std::time::Duration;
 
-use api_resp::{ApiResp, TransformResult};
+use api_resp::{ApiResp, DaoResult, TransformResult};
 use log::error;
 use serde_json::json;
 
 use crate::get_config_dir;
-use crate::support::history::{add_open_history, get_open_history, read_template_record, remove_open_history, save_template_record};
-use crate::support::load_db::{edit_data, exec_sql, fetch_rows, fetch_table_sql, load_tables, remove_db_connection};
+use crate::support::blob::BlobEncoding;
+use crate::support::cipher::CipherProfile;
+use crate::support::csv::CsvFormat;
+use crate::support::cursor::{close_cursor as close_open_cursor, fetch_next as fetch_cursor_batch};
+use crate::support::db_info::PragmaUpdate;
+use crate::support::ddl::TableSpec;
+use crate::support::dump::DumpOptions;
+use crate::support::executor::{cancel_query as cancel_running_query, set_query_timeout};
+use crate::support::export::{ExportOptions, ExportSource};
+use crate::support::health::CheckKind;
+use crate::support::history::{His, add_open_history, forget_open_history_key, get_open_history, get_open_history_entry, migrate_history_keys, read_template_record, remove_open_history, resolve_history_key, save_template_record, update_open_history_cipher, update_open_history_key, update_open_history_query_timeout};
+use crate::support::import::{CsvImportOptions, preview_csv};
+use crate::support::index::IndexSpec;
+use crate::support::load_db::{alter_db_table, analyze_db_index_usage, backup_to_file, check_db_file, create_db_file, create_db_index, create_db_table, describe_db_object, detect_cipher, drop_db_index, dump_to_file, edit_data, error_code, exec_sql, export_rows_to_file, export_to_file, fetch_blob_cell, fetch_indexes, fetch_rows, fetch_table_spec, fetch_table_sql, fetch_triggers, FilterSpec, import_csv_file, load_blob_cell, load_tables, maintain_db, migrate_db, open_sql_cursor, open_table_cursor, read_db_properties, rekey_db, remove_db_connection, replace_db_object, restore_from_backup, restore_from_file, run_blocking, save_blob_cell, SortSpec, TableQuery, update_blob_cell, update_db_pragma};
+use crate::support::maintenance::MaintenanceOp;
+use crate::support::vault::{create_vault, ensure_unlocked, lock_vault, unlock_vault, vault_status};
 
 #[tauri::command]
 pub async fn load_history(path: Option<String>) -> String {
@@ -20,14 +36,20 @@ pub async fn load_history(path: Option<String>) -> String {
 }
 
 #[tauri::command]
-pub async fn add_history(path: String, cache_file: Option<String>, key: Option<String>) -> String {
+pub async fn add_history(path: String, cache_file: Option<String>, key: Option<String>, cipher: Option<CipherProfile>) -> String {
+    let key = key.filter(|k| !k.is_empty());
     let new_path = PathBuf::from(&path);
     let name = new_path.file_name().unwrap().to_str().unwrap().to_string();
     let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
-    let add_result = add_open_history(data_path, name, path, key);
+    // 新建数据库时，历史记录指向的文件尚不存在。
+    if let Err(e) = create_db_file(&path, &key, &cipher) {
+        error!("新建数据库文件时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    let add_result = add_open_history(data_path, name, path, key, cipher);
     if let Err(e) = add_result {
         error!("缓存时出错 {:?}", e);
-        ApiResp::error(-1, e.to_string()).to_json()
+        ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
     } else {
         ApiResp::suc().to_json()
     }
@@ -43,51 +65,527 @@ pub async fn remove_history_entry(index: usize, cache_file: Option<String>) -> S
                 let remove_db_pool_result = remove_db_connection(&path);
                 if let Err(e) = remove_db_pool_result {
                     error!("移除数据库连接池时出错 {:?}", e);
-                    return ApiResp::error(-1, e.to_string()).to_json();
+                    return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
                 }
             }
             ApiResp::suc().to_json()
         }
         Err(e) => {
             error!("移除缓存时出错 {:?}", e);
-            ApiResp::error(-1, e.to_string()).to_json()
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
+}
+
+/// 按历史记录打开数据库，密钥从密钥库读取，不经过前端。
+#[tauri::command]
+pub async fn open_history_entry(index: usize, cache_file: Option<String>) -> String {
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    let (his, key) = match history_entry_with_key(data_path, index) {
+        Ok(entry) => entry,
+        Err(e) => {
+            error!("读取历史记录时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    };
+    if let Err(e) = set_query_timeout(&his.path, his.query_timeout_ms.map(Duration::from_millis)) {
+        error!("设置查询超时时间时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    open_db(his.path, key, his.cipher).await
+}
+
+/// 读取历史记录及其密钥，记录不存在或密钥库未解锁时返回错误。
+fn history_entry_with_key(data_path: PathBuf, index: usize) -> Result<(His, Option<String>), Box<dyn Error>> {
+    let his = get_open_history_entry(data_path, index)?.ok_or_else(|| format!("历史记录 {} 不存在", index))?;
+    let key = resolve_history_key(&his)?;
+    Ok((his, key))
+}
+
+#[tauri::command]
+pub async fn get_vault_status(cache_file: Option<String>) -> String {
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    match vault_status(data_path) {
+        Ok(status) => ApiResp::success(json!(status)).to_json(),
+        Err(e) => {
+            error!("查询密钥库状态时出错 {:?}", e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
+}
+
+/// 首次使用时创建密钥库，未提供主密码时使用本地密钥文件加密，并将历史记录中遗留的明文密钥迁移到密钥库。
+#[tauri::command]
+pub async fn create_key_vault(master_password: Option<String>, cache_file: Option<String>) -> String {
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    let master_password = master_password.filter(|p| !p.is_empty());
+    if let Err(e) = create_vault(data_path.clone(), master_password) {
+        error!("创建密钥库时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    to_coded_json(migrate_history_keys(data_path).map(|migrated| ApiResp::success(json!(migrated))), "迁移明文密钥时出错")
+}
+
+/// 解锁密钥库，并将历史记录中遗留的明文密钥迁移到密钥库。
+#[tauri::command]
+pub async fn unlock_key_vault(master_password: Option<String>, cache_file: Option<String>) -> String {
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    let master_password = master_password.filter(|p| !p.is_empty());
+    if let Err(e) = unlock_vault(data_path.clone(), master_password) {
+        error!("解锁密钥库时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    match migrate_history_keys(data_path) {
+        Ok(migrated) => ApiResp::success(json!(migrated)).to_json(),
+        Err(e) => {
+            error!("迁移明文密钥时出错 {:?}", e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
         }
     }
 }
 
 #[tauri::command]
-pub async fn open_db(db_path: String, key: Option<String>) -> String {
-    let load_result = load_tables(db_path, key).await;
+pub async fn lock_key_vault() -> String {
+    to_coded_json(lock_vault().map(|_| ApiResp::suc()), "锁定密钥库时出错")
+}
+
+#[tauri::command]
+pub async fn forget_history_key(index: usize, cache_file: Option<String>) -> String {
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    to_coded_json(forget_open_history_key(data_path, index).map(|_| ApiResp::suc()), "删除已保存的密钥时出错")
+}
+
+#[tauri::command]
+pub async fn open_db(db_path: String, key: Option<String>, cipher: Option<CipherProfile>) -> String {
+    let load_result = load_tables(db_path, key, cipher).await;
     match load_result {
         Ok(metas) => {
             ApiResp::success(json!(metas)).to_json()
         }
         Err(e) => {
             error!("查询表名列表出错 {:?}", e);
-            ApiResp::error(-1, e.to_string()).to_json()
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
         }
     }
 }
 
+#[tauri::command]
+pub async fn change_db_key(db_path: String, key: Option<String>, new_key: Option<String>, cache_file: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    let new_key = new_key.filter(|k| !k.is_empty());
+    // 修改密钥后须同步更新密钥库，先确认密钥库已解锁，避免数据库已使用新密钥而历史记录仍保存旧密钥。
+    if let Err(e) = ensure_unlocked() {
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    let (rekey_path, rekey_key, rekey_new_key) = (db_path.clone(), key, new_key.clone());
+    if let Err(e) = run_blocking(move || rekey_db(&rekey_path, &rekey_key, &rekey_new_key)).await {
+        error!("修改数据库密钥时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    if let Err(e) = update_open_history_key(data_path, &db_path, new_key) {
+        error!("更新缓存的密钥时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    ApiResp::suc().to_json()
+}
 
+/// 导出数据库为新文件，导出期间通过`export-progress`事件报告进度。
 #[tauri::command]
-pub async fn fetch_table_data(db_path: String, table_name: String, limit: u64, key: Option<String>) -> String {
-    fetch_rows(db_path, table_name.clone(), limit, key).await.to_json_str(format!("加载表 {} 的数据时出错", table_name))
+pub async fn export_db(window: tauri::Window, db_path: String, key: Option<String>, target_path: String, target_key: Option<String>, target_cipher: Option<CipherProfile>, add_to_history: Option<bool>, cache_file: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    let target_key = target_key.filter(|k| !k.is_empty());
+    if add_to_history.unwrap_or(false) {
+        if let Err(e) = ensure_unlocked() {
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    }
+    let progress = Box::new(move |p| {
+        if let Err(e) = window.emit("export-progress", p) {
+            error!("发送导出进度时出错 {:?}", e);
+        }
+    });
+    let exported = match export_to_file(db_path, key, target_path, target_key, target_cipher, Some(progress)).await {
+        Ok(exported) => exported,
+        Err(e) => {
+            error!("导出数据库时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    };
+
+    if add_to_history.unwrap_or(false) {
+        let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+        let add_result = add_open_history(data_path, exported.name.clone(), exported.path.clone(), exported.key.clone(), exported.cipher.clone());
+        if let Err(e) = add_result {
+            error!("缓存时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    }
+    ApiResp::success(json!(exported)).to_json()
+}
+
+/// 使用SQLITE备份API在线备份数据库，备份期间通过`backup-progress`事件报告进度。
+#[tauri::command]
+pub async fn backup_db(window: tauri::Window, db_path: String, key: Option<String>, target_path: String, target_key: Option<String>, add_to_history: Option<bool>, cache_file: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    let target_key = target_key.filter(|k| !k.is_empty());
+    if add_to_history.unwrap_or(false) {
+        if let Err(e) = ensure_unlocked() {
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    }
+    let progress = Box::new(move |p| {
+        if let Err(e) = window.emit("backup-progress", p) {
+            error!("发送备份进度时出错 {:?}", e);
+        }
+    });
+    let backup = match backup_to_file(db_path, key, target_path, target_key, Some(progress)).await {
+        Ok(backup) => backup,
+        Err(e) => {
+            error!("备份数据库时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    };
+
+    if add_to_history.unwrap_or(false) {
+        let data_path = cache_file.map_or_else(get_config_dir, PathBuf::from);
+        let add_result = add_open_history(data_path, backup.name.clone(), backup.path.clone(), backup.key.clone(), backup.cipher.clone());
+        if let Err(e) = add_result {
+            error!("缓存时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    }
+    ApiResp::success(json!(backup)).to_json()
 }
 
+/// 以备份文件覆盖正在使用的数据库，前端须先向用户确认。恢复期间通过`backup-progress`事件报告进度。
 #[tauri::command]
-pub async fn exec_custom_sql(db_path: String, sql: String, key: Option<String>) -> String {
-    exec_sql(db_path, sql.as_str(), key).await.to_json_str(format!("执行自定义SQL: {} 时出错", sql))
+pub async fn restore_db_backup(window: tauri::Window, db_path: String, key: Option<String>, backup_path: String, backup_key: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    let backup_key = backup_key.filter(|k| !k.is_empty());
+    let progress = Box::new(move |p| {
+        if let Err(e) = window.emit("backup-progress", p) {
+            error!("发送恢复进度时出错 {:?}", e);
+        }
+    });
+    match restore_from_backup(db_path, key, backup_path, backup_key, Some(progress)).await {
+        Ok(_) => ApiResp::suc().to_json(),
+        Err(e) => {
+            error!("从备份恢复数据库时出错 {:?}", e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
+}
+
+/// 对数据库执行`integrity_check`等健康检查，返回结构化的检查结果。
+#[tauri::command]
+pub async fn check_db_health(db_path: String, key: Option<String>, checks: Option<Vec<CheckKind>>, max_errors: Option<u32>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(check_db_file(db_path, key, None, checks, max_errors).await, "检查数据库时出错")
+}
+
+/// 对历史记录中的数据库执行健康检查，使用历史记录保存的密钥和加密参数。
+#[tauri::command]
+pub async fn check_history_db_health(index: usize, cache_file: Option<String>, checks: Option<Vec<CheckKind>>, max_errors: Option<u32>) -> String {
+    let data_path = cache_file.map_or_else(get_config_dir, PathBuf::from);
+    let (his, key) = match history_entry_with_key(data_path, index) {
+        Ok(entry) => entry,
+        Err(e) => {
+            error!("读取历史记录时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    };
+    to_coded_json(check_db_file(his.path, key, his.cipher, checks, max_errors).await, "检查数据库时出错")
+}
+
+/// 执行VACUUM、VACUUM INTO、ANALYZE、`PRAGMA optimize`或增量VACUUM。
+#[tauri::command]
+pub async fn run_db_maintenance(db_path: String, key: Option<String>, op: MaintenanceOp) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    let op = match op {
+        MaintenanceOp::VacuumInto { target_path, target_key } => MaintenanceOp::VacuumInto { target_path, target_key: target_key.filter(|k| !k.is_empty()) },
+        op => op,
+    };
+    to_coded_json(maintain_db(db_path, key, op).await, "维护数据库时出错")
+}
+
+/// 查询数据库的页大小、日志模式、SQLCipher版本等属性。
+#[tauri::command]
+pub async fn get_db_info(db_path: String, key: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(read_db_properties(db_path, key).await, "查询数据库属性时出错")
+}
+
+/// 修改可写的PRAGMA，返回修改后的数据库属性。
+#[tauri::command]
+pub async fn set_db_pragma(db_path: String, key: Option<String>, update: PragmaUpdate) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(update_db_pragma(db_path, key, update).await, "修改数据库属性时出错")
+}
+
+/// 检测加密数据库的SQLCipher格式，通常在打开失败后调用，以确定是否需要迁移。
+#[tauri::command]
+pub async fn detect_db_cipher(db_path: String, key: String) -> String {
+    match detect_cipher(db_path, key).await {
+        Ok(detected) => ApiResp::success(json!(detected)).to_json(),
+        Err(e) => {
+            error!("检测数据库加密格式时出错 {:?}", e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
+}
+
+/// 将旧版SQLCipher格式的数据库迁移为当前格式，默认迁移副本并将副本加入历史记录。
+#[tauri::command]
+pub async fn migrate_legacy_db(db_path: String, key: String, target_path: Option<String>, in_place: Option<bool>, cache_file: Option<String>) -> String {
+    let in_place = in_place.unwrap_or(false);
+    if !in_place {
+        if let Err(e) = ensure_unlocked() {
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    }
+    let migrated = match migrate_db(db_path.clone(), key, target_path, in_place).await {
+        Ok(migrated) => migrated,
+        Err(e) => {
+            error!("迁移数据库时出错 {:?}", e);
+            return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+        }
+    };
+
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    let his_result = if in_place {
+        update_open_history_cipher(data_path, &db_path, None)
+    } else {
+        let target = &migrated.target;
+        add_open_history(data_path, target.name.clone(), target.path.clone(), target.key.clone(), None)
+    };
+    if let Err(e) = his_result {
+        error!("缓存时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    ApiResp::success(json!(migrated)).to_json()
+}
+
+#[tauri::command]
+pub async fn fetch_table_data(db_path: String, table_name: String, limit: u64, key: Option<String>, offset: Option<u64>, after_rowid: Option<i64>,
+                              order_by: Option<Vec<SortSpec>>, filters: Option<Vec<FilterSpec>>, with_total: Option<bool>) -> String {
+    let query = TableQuery { limit, offset, after_rowid, order_by, filters, with_total: with_total.unwrap_or(false) };
+    to_coded_json(fetch_rows(db_path, table_name.clone(), query, key).await, format!("加载表 {} 的数据时出错", table_name))
+}
+
+#[tauri::command]
+pub async fn exec_custom_sql(db_path: String, sql: String, key: Option<String>, params: Option<serde_json::Value>, query_id: Option<String>) -> String {
+    to_coded_json(exec_sql(db_path, sql.as_str(), key, params, query_id).await, format!("执行自定义SQL: {} 时出错", sql))
+}
+
+/// 以游标方式浏览表数据，排序和过滤条件同`fetch_table_data`，之后通过`fetch_next`逐批读取。
+#[tauri::command]
+pub async fn open_table_data_cursor(db_path: String, table_name: String, key: Option<String>, order_by: Option<Vec<SortSpec>>, filters: Option<Vec<FilterSpec>>) -> String {
+    let query = TableQuery { order_by, filters, ..Default::default() };
+    to_coded_json(open_table_cursor(db_path, table_name.clone(), query, key).await, format!("打开表 {} 的游标时出错", table_name))
+}
+
+/// 以游标方式执行单条自定义查询语句，之后通过`fetch_next`逐批读取。
+#[tauri::command]
+pub async fn open_query_cursor(db_path: String, sql: String, key: Option<String>, params: Option<serde_json::Value>) -> String {
+    to_coded_json(open_sql_cursor(db_path, sql.clone(), key, params).await, format!("打开查询: {} 的游标时出错", sql))
+}
+
+/// 读取游标的下一批数据，前端处理完当前批次后再请求下一批。
+#[tauri::command]
+pub async fn fetch_next(cursor_id: String, batch_size: usize) -> String {
+    let batch = run_blocking(move || fetch_cursor_batch(&cursor_id, batch_size)).await;
+    to_coded_json(batch.map(|batch| ApiResp::success(json!(batch))), "读取游标数据时出错")
+}
+
+/// 提前关闭游标，返回是否找到了该游标。
+#[tauri::command]
+pub async fn close_cursor(cursor_id: String) -> String {
+    match close_open_cursor(&cursor_id) {
+        Ok(found) => ApiResp::success(json!(found)).to_json(),
+        Err(e) => {
+            error!("关闭游标时出错 {:?}", e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
+}
+
+/// 取消正在执行的自定义SQL，返回是否找到了该查询。
+#[tauri::command]
+pub async fn cancel_query(query_id: String) -> String {
+    match cancel_running_query(&query_id) {
+        Ok(found) => ApiResp::success(json!(found)).to_json(),
+        Err(e) => {
+            error!("取消查询时出错 {:?}", e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
+}
+
+/// 设置数据库的查询超时时间并保存到历史记录，`timeout_ms`为空或0时不限制。
+#[tauri::command]
+pub async fn set_db_query_timeout(db_path: String, timeout_ms: Option<u64>, cache_file: Option<String>) -> String {
+    let timeout_ms = timeout_ms.filter(|ms| *ms > 0);
+    if let Err(e) = set_query_timeout(&db_path, timeout_ms.map(Duration::from_millis)) {
+        error!("设置查询超时时间时出错 {:?}", e);
+        return ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json();
+    }
+    let data_path = if cache_file.is_none() { get_config_dir() } else { PathBuf::from(cache_file.unwrap()) };
+    to_coded_json(update_open_history_query_timeout(data_path, &db_path, timeout_ms).map(|_| ApiResp::suc()), "保存查询超时时间时出错")
 }
 
 #[tauri::command]
 pub async fn update_table_data(db_path: String, table_name: String, key: Option<String>, del_rows: Option<Vec<String>>, new_rows: Option<serde_json::Value>, edit_rows: Option<serde_json::Value>) -> String {
-    edit_data(db_path, table_name, key, new_rows, edit_rows, del_rows).await.to_json_str("更新数据时出错")
+    to_coded_json(edit_data(db_path, table_name, key, new_rows, edit_rows, del_rows).await, "更新数据时出错")
+}
+
+/// 读取BLOB单元格，`encoding`为`hex`或`base64`。
+#[tauri::command]
+pub async fn fetch_blob(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>,
+                        encoding: Option<BlobEncoding>, max_bytes: Option<usize>) -> String {
+    to_coded_json(fetch_blob_cell(db_path, table_name, column, rowid, key, encoding, max_bytes).await, "读取BLOB数据时出错")
+}
+
+/// 以编码后的数据替换BLOB单元格的值。
+#[tauri::command]
+pub async fn update_blob(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>, data: String, encoding: BlobEncoding) -> String {
+    to_coded_json(update_blob_cell(db_path, table_name, column, rowid, key, data, encoding).await, "修改BLOB数据时出错")
+}
+
+/// 将BLOB单元格的内容保存到文件。
+#[tauri::command]
+pub async fn export_blob_file(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>, target_path: String) -> String {
+    to_coded_json(save_blob_cell(db_path, table_name, column, rowid, key, target_path).await, "保存BLOB数据到文件时出错")
+}
+
+/// 以文件内容替换BLOB单元格的值。
+#[tauri::command]
+pub async fn import_blob_file(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>, source_path: String) -> String {
+    to_coded_json(load_blob_cell(db_path, table_name, column, rowid, key, source_path).await, "从文件导入BLOB数据时出错")
+}
+
+/// 预览CSV文件的字段和开头的若干条记录，用于设置导入时的字段映射。
+#[tauri::command]
+pub async fn preview_csv_file(path: String, format: Option<CsvFormat>, sample_rows: Option<usize>) -> String {
+    run_blocking(move || preview_csv(&path, format, sample_rows.unwrap_or(20))).await
+        .map(|preview| ApiResp::success(json!(preview))).to_json_str("读取CSV文件时出错")
+}
+
+/// 将CSV或TSV文件导入到表中。
+#[tauri::command]
+pub async fn import_csv_data(db_path: String, table_name: String, path: String, key: Option<String>, options: Option<CsvImportOptions>) -> String {
+    to_coded_json(import_csv_file(db_path, table_name.clone(), path, key, options.unwrap_or_default()).await, format!("导入CSV数据到表 {} 时出错", table_name))
+}
+
+/// 将表、视图或查询结果导出为CSV、JSON或NDJSON文件，导出期间通过`data-export-progress`事件报告进度。
+#[tauri::command]
+pub async fn export_data(window: tauri::Window, db_path: String, key: Option<String>, source: ExportSource, target_path: String, options: Option<ExportOptions>) -> String {
+    let progress = Box::new(move |p| {
+        if let Err(e) = window.emit("data-export-progress", p) {
+            error!("发送导出进度时出错 {:?}", e);
+        }
+    });
+    to_coded_json(export_rows_to_file(db_path, key, source, target_path, options.unwrap_or_default(), Some(progress)).await, "导出数据时出错")
+}
+
+/// 将数据库的结构和数据导出为SQL脚本。
+#[tauri::command]
+pub async fn dump_db_sql(db_path: String, key: Option<String>, target_path: String, options: Option<DumpOptions>) -> String {
+    to_coded_json(dump_to_file(db_path, key, target_path, options.unwrap_or_default()).await, "导出SQL脚本时出错")
+}
+
+/// 在一个事务中执行SQL脚本文件，目标数据库不存在时新建。
+#[tauri::command]
+pub async fn restore_db_sql(db_path: String, key: Option<String>, cipher: Option<CipherProfile>, source_path: String) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(restore_from_file(db_path, key, cipher, source_path).await, "执行SQL脚本时出错")
 }
 
 #[tauri::command]
 pub async fn get_table_sql(db_path: String, table_name: String, key: Option<String>) -> String {
-    fetch_table_sql(db_path, key, table_name).await.to_json_str("查询目标SQL语句时出错")
+    to_coded_json(fetch_table_sql(db_path, key, table_name).await, "查询目标SQL语句时出错")
+}
+
+/// 查询表或视图的完整结构，供对象树展示字段、索引、外键和触发器。
+#[tauri::command]
+pub async fn describe_object(db_path: String, name: String, key: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(describe_db_object(db_path, key, name).await, "查询表结构时出错")
+}
+
+/// 读取表定义，供修改表结构的对话框编辑。
+#[tauri::command]
+pub async fn get_table_spec(db_path: String, table_name: String, key: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(fetch_table_spec(db_path, key, table_name).await, "读取表定义时出错")
+}
+
+/// 根据建表表单生成`CREATE TABLE`语句，`execute`为`true`时同时建表。
+#[tauri::command]
+pub async fn create_table(db_path: String, key: Option<String>, spec: TableSpec, execute: bool) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(create_db_table(db_path, key, spec, execute).await, "生成建表语句时出错")
+}
+
+/// 按目标定义修改表结构。`apply`为`false`时只返回将要执行的SQL语句。
+#[tauri::command]
+pub async fn alter_table(db_path: String, table_name: String, key: Option<String>, spec: TableSpec, apply: bool) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(alter_db_table(db_path, key, table_name, spec, apply).await, "修改表结构时出错")
+}
+
+/// 列出索引，`table_name`为空时列出所有表的索引。
+#[tauri::command]
+pub async fn list_indexes(db_path: String, key: Option<String>, table_name: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(fetch_indexes(db_path, key, table_name.filter(|t| !t.is_empty())).await, "查询索引时出错")
+}
+
+/// 创建索引，`execute`为`false`时只返回`CREATE INDEX`语句。
+#[tauri::command]
+pub async fn create_index(db_path: String, key: Option<String>, spec: IndexSpec, execute: bool) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(create_db_index(db_path, key, spec, execute).await, "创建索引时出错")
+}
+
+/// 删除索引，主键和UNIQUE约束自动创建的索引不能删除。
+#[tauri::command]
+pub async fn drop_index(db_path: String, key: Option<String>, index_name: String) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(drop_db_index(db_path, key, index_name).await, "删除索引时出错")
+}
+
+/// 分析索引的使用情况，`queries`为空时分析该数据库最近执行过的语句。
+#[tauri::command]
+pub async fn analyze_index_usage(db_path: String, key: Option<String>, queries: Option<Vec<String>>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(analyze_db_index_usage(db_path, key, queries).await, "分析索引使用情况时出错")
+}
+
+/// 列出触发器，`table_name`为空时列出全部。
+#[tauri::command]
+pub async fn list_triggers(db_path: String, key: Option<String>, table_name: Option<String>) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(fetch_triggers(db_path, key, table_name.filter(|t| !t.is_empty())).await, "查询触发器时出错")
+}
+
+/// 替换视图或触发器。有对象因此失效且`force`为`false`时不提交，只返回失效的对象。
+#[tauri::command]
+pub async fn replace_object(db_path: String, key: Option<String>, name: String, sql: String, force: bool) -> String {
+    let key = key.filter(|k| !k.is_empty());
+    to_coded_json(replace_db_object(db_path, key, name, sql, force).await, "替换视图或触发器时出错")
+}
+
+/// 与`to_json_str`相同，但打开数据库失败或密钥库未解锁时返回`error_code`对应的错误代码，而非-1。
+fn to_coded_json<T>(result: DaoResult, err_log: T) -> String where T: std::fmt::Debug + std::fmt::Display {
+    match result {
+        Ok(resp) => resp.to_json(),
+        Err(e) => {
+            error!("{} {:?}", err_log, e);
+            ApiResp::error(error_code(e.as_ref()), e.to_string()).to_json()
+        }
+    }
 }
 
 
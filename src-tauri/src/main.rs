@@ -26,7 +26,10 @@ fn main() {
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             load_history,add_history,open_db,fetch_table_data,exec_custom_sql,update_table_data,remove_history_entry,get_table_sql,
-            save_temp_notes,load_temp_notes
+            save_temp_notes,load_temp_notes,change_db_key,export_db,detect_db_cipher,migrate_legacy_db,
+            open_history_entry,get_vault_status,create_key_vault,unlock_key_vault,lock_key_vault,forget_history_key,cancel_query,set_db_query_timeout,
+            open_table_data_cursor,open_query_cursor,fetch_next,close_cursor,fetch_blob,update_blob,export_blob_file,import_blob_file,
+            preview_csv_file,import_csv_data,export_data,dump_db_sql,restore_db_sql,backup_db,restore_db_backup,check_db_health,check_history_db_health,run_db_maintenance,get_db_info,set_db_pragma,describe_object,get_table_spec,alter_table,create_table,list_indexes,create_index,drop_index,analyze_index_usage,list_triggers,replace_object
         ])
         .setup(|app| {
             let cache_dir = app.path_resolver().app_cache_dir();
@@ -0,0 +1,346 @@
+//! 修改表结构：比较目标定义与当前定义，能用`ALTER TABLE`完成时直接执行，否则按SQLITE文档中的12步流程重建表。
+//!
+//! 参见<https://www.sqlite.org/lang_altertable.html#otheralter>。
+use std::collections::HashSet;
+use std::error::Error;
+
+use rusqlite::{Connection, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::support::ddl::{column_sql, create_table_sql, normalize_table_spec, read_table_spec, restore_double_quoted_strings, set_double_quoted_strings, TableSpec, unsupported_table_clauses};
+use crate::support::executor::execute_single;
+use crate::support::ident::quote_ident;
+
+/// 修改表结构的方式。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlterStrategy {
+    /// 只用`ALTER TABLE`修改表名、字段名，新增或删除字段。
+    Native,
+    /// 新建表、复制数据、删除原表并改名，再重建索引和触发器。
+    Rebuild,
+}
+
+/// 修改表结构的SQL语句。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AlterPlan {
+    pub strategy: AlterStrategy,
+    /// 按执行顺序排列的语句，不含`BEGIN`和`COMMIT`。定义没有变化时为空。
+    pub statements: Vec<String>,
+    /// 是否已执行。
+    pub applied: bool,
+}
+
+/// 依次执行并记录SQL语句，每次只执行一条语句。
+struct Recorder<'a> {
+    tx: &'a Transaction<'a>,
+    statements: Vec<String>,
+}
+
+impl Recorder<'_> {
+    fn run(&mut self, sql: String) -> Result<(), Box<dyn Error>> {
+        execute_single(self.tx, &sql).map_err(|e| format!("执行 {} 时出错: {}", sql, e))?;
+        self.statements.push(sql);
+        Ok(())
+    }
+}
+
+/// 校验目标定义中的字段原名，返回被删除的字段。
+fn dropped_columns(current: &TableSpec, desired: &TableSpec) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut kept = HashSet::new();
+    for original in desired.columns.iter().filter_map(|c| c.original_name.as_ref()) {
+        let column = current.columns.iter().find(|c| c.name.eq_ignore_ascii_case(original))
+            .ok_or_else(|| format!("表 {} 中不存在字段 {}", current.name, original))?;
+        if !kept.insert(column.name.to_lowercase()) {
+            return Err(format!("字段 {} 被多次引用", original).into());
+        }
+    }
+    Ok(current.columns.iter().filter(|c| !kept.contains(&c.name.to_lowercase())).map(|c| c.name.clone()).collect())
+}
+
+/// 修改表名和字段名，重命名会同步修改引用它们的索引、触发器、视图和外键。
+fn rename(recorder: &mut Recorder, current: &TableSpec, desired: &TableSpec) -> Result<(), Box<dyn Error>> {
+    if current.name != desired.name {
+        recorder.run(format!("ALTER TABLE {} RENAME TO {}", quote_ident(&current.name), quote_ident(&desired.name)))?;
+    }
+    for column in desired.columns.iter() {
+        if let Some(original) = column.original_name.as_ref().filter(|o| **o != column.name) {
+            recorder.run(format!("ALTER TABLE {} RENAME COLUMN {} TO {}", quote_ident(&desired.name), quote_ident(original), quote_ident(&column.name)))?;
+        }
+    }
+    Ok(())
+}
+
+/// 尝试只用`ALTER TABLE`完成修改，执行后的定义与目标定义等价时返回`true`。
+fn try_native(recorder: &mut Recorder, current: &TableSpec, desired: &TableSpec, dropped: &[String]) -> Result<bool, Box<dyn Error>> {
+    rename(recorder, current, desired)?;
+    for column in dropped {
+        recorder.run(format!("ALTER TABLE {} DROP COLUMN {}", quote_ident(&desired.name), quote_ident(column)))?;
+    }
+    for column in desired.columns.iter().filter(|c| c.original_name.is_none()) {
+        recorder.run(format!("ALTER TABLE {} ADD COLUMN {}", quote_ident(&desired.name), column_sql(column, None)))?;
+    }
+    let altered = read_table_spec(recorder.tx, &desired.name)?;
+    Ok(normalize_table_spec(&altered) == normalize_table_spec(desired))
+}
+
+/// 能够编译的视图。
+fn valid_views(tx: &Transaction) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut stmt = tx.prepare("select name from sqlite_master where type = 'view' order by name")?;
+    let views = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(views.into_iter().filter(|v| tx.prepare(&format!("select * from {}", quote_ident(v))).is_ok()).collect())
+}
+
+/// 重建表。调用前须关闭外键约束。
+///
+/// 预览时不复制数据，只检查复制数据的语句能否编译。
+fn rebuild(recorder: &mut Recorder, current: &TableSpec, desired: &TableSpec, apply: bool, foreign_keys: bool) -> Result<(), Box<dyn Error>> {
+    // 只检查修改前能够编译的视图，使用了双引号字符串或原本就无效的视图不影响重建。
+    let views = valid_views(recorder.tx)?;
+    // 先原地改名，使索引、触发器和视图中的引用随之更新。
+    rename(recorder, current, desired)?;
+    let table = quote_ident(&desired.name);
+    let mut stmt = recorder.tx.prepare("select sql from sqlite_master where type in ('index', 'trigger') and tbl_name = ?1 and sql is not null order by type, name")?;
+    let dependents = stmt.query_map([&desired.name], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut temp_name = format!("{}_new", desired.name);
+    while recorder.tx.query_row("select count(*) from sqlite_master where name = ?1 collate nocase", [&temp_name], |r| r.get::<_, i64>(0))? > 0 {
+        temp_name.push('_');
+    }
+    recorder.run(create_table_sql(&TableSpec { name: temp_name.clone(), ..desired.clone() })?)?;
+
+    // 生成列的值由表达式计算，不能插入。
+    let copied: Vec<String> = desired.columns.iter()
+        .filter(|c| c.original_name.is_some() && c.generated.is_none())
+        .map(|c| quote_ident(&c.name))
+        .collect();
+    if !copied.is_empty() {
+        let columns = copied.join(", ");
+        let sql = format!("INSERT INTO {} ({}) SELECT {} FROM {}", quote_ident(&temp_name), columns, columns, table);
+        if apply {
+            recorder.run(sql)?;
+        } else {
+            recorder.tx.prepare(&sql).map_err(|e| format!("执行 {} 时出错: {}", sql, e))?;
+            recorder.statements.push(sql);
+        }
+    }
+    recorder.run(format!("DROP TABLE {}", table))?;
+    // 原表已删除，引用它的视图暂时无效，须按旧版语义改名，避免SQLITE检查整个数据库结构。
+    let legacy: bool = recorder.tx.pragma_query_value(None, "legacy_alter_table", |r| r.get(0))?;
+    if !legacy {
+        recorder.run("PRAGMA legacy_alter_table = ON".to_string())?;
+    }
+    recorder.run(format!("ALTER TABLE {} RENAME TO {}", quote_ident(&temp_name), table))?;
+    if !legacy {
+        recorder.run("PRAGMA legacy_alter_table = OFF".to_string())?;
+    }
+    for sql in dependents {
+        recorder.run(sql)?;
+    }
+
+    let broken: Vec<String> = views.iter()
+        .filter_map(|v| recorder.tx.prepare(&format!("select * from {}", quote_ident(v))).err().map(|e| format!("{}: {}", v, e)))
+        .collect();
+    if !broken.is_empty() {
+        return Err(format!("修改后以下视图将失效，请先修改或删除这些视图: {}", broken.join("; ")).into());
+    }
+
+    if foreign_keys && apply {
+        let violations: i64 = recorder.tx.query_row("select count(*) from pragma_foreign_key_check", [], |r| r.get(0))?;
+        if violations > 0 {
+            return Err(format!("修改后有 {} 行数据违反外键约束", violations).into());
+        }
+    }
+    Ok(())
+}
+
+/// 在事务中重建表，出错或预览时回滚。已执行的语句追加到`statements`中。
+fn rebuild_in_transaction(conn: &mut Connection, current: &TableSpec, desired: &TableSpec, apply: bool, foreign_keys: bool, statements: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    let tx = conn.transaction()?;
+    let mut recorder = Recorder { tx: &tx, statements: vec![] };
+    let result = rebuild(&mut recorder, current, desired, apply, foreign_keys);
+    statements.append(&mut recorder.statements);
+    result?;
+    if apply {
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+/// 按目标定义修改表结构。
+///
+/// 先尝试只用`ALTER TABLE`修改表名、字段名，删除和新增字段；修改后的定义与目标定义不一致，或SQLITE不允许这样修改时，回滚并按12步流程重建表。
+/// 重建时暂时关闭外键约束，提交前检查外键。所有修改在一个事务中执行，出错时全部回滚。
+/// 建表语句中含有约束名、`ON CONFLICT`、`DEFERRABLE`或`MATCH`子句的表无法重建，以免丢失这些子句。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接，不能处于事务中。
+/// * `table_name`: 要修改的表。
+/// * `desired`: 目标定义，通常由`read_table_spec`读取后修改而来。保留的字段须设置`original_name`，新增的字段为`None`，未列出的字段将被删除。
+/// * `apply`: 为`false`时只生成SQL供预览，执行后回滚；重建表时不复制数据。
+///
+/// returns: Result<AlterPlan, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let mut conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let mut spec = read_table_spec(&conn, "users").unwrap();
+/// spec.columns[1].name = "nick_name".to_string();
+/// let plan = alter_table(&mut conn, "users", &spec, false).unwrap();
+/// println!("{}", plan.statements.join(";\n"));
+/// ```
+pub fn alter_table(conn: &mut Connection, table_name: &str, desired: &TableSpec, apply: bool) -> Result<AlterPlan, Box<dyn Error>> {
+    create_table_sql(desired)?;
+    let current = read_table_spec(conn, table_name)?;
+    let dropped = dropped_columns(&current, desired)?;
+
+    let tx = conn.transaction()?;
+    let mut recorder = Recorder { tx: &tx, statements: vec![] };
+    if let Ok(true) = try_native(&mut recorder, &current, desired, &dropped) {
+        let statements = recorder.statements;
+        if apply {
+            tx.commit()?;
+        }
+        return Ok(AlterPlan { strategy: AlterStrategy::Native, statements, applied: apply });
+    }
+    drop(recorder);
+    tx.rollback()?;
+
+    // 重建时按`TableSpec`建表，无法表示的子句会丢失。
+    let sql: Option<String> = conn.query_row("select sql from sqlite_master where type = 'table' and name = ?1 collate nocase", [table_name], |r| r.get(0))?;
+    let clauses = unsupported_table_clauses(sql.as_deref().unwrap_or_default());
+    if !clauses.is_empty() {
+        return Err(format!("表 {} 的定义包含 {}，重建表时无法保留，请手动修改表结构", table_name, clauses.join("、")).into());
+    }
+
+    let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+    let mut statements = vec![];
+    if foreign_keys {
+        conn.pragma_update(None, "foreign_keys", false)?;
+        statements.push("PRAGMA foreign_keys = OFF".to_string());
+    }
+    let dqs = set_double_quoted_strings(conn, false)?;
+    let result = rebuild_in_transaction(conn, &current, desired, apply, foreign_keys, &mut statements);
+    let restored = restore_double_quoted_strings(conn, dqs);
+    if foreign_keys {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        statements.push("PRAGMA foreign_keys = ON".to_string());
+    }
+    result?;
+    restored?;
+    Ok(AlterPlan { strategy: AlterStrategy::Rebuild, statements, applied: apply })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::support::ddl::ColumnSpec;
+
+    use super::*;
+
+    fn open_test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            pragma foreign_keys = 1;
+            create table users (id integer primary key, name text, age int);
+            create table orders (id integer primary key, user_id integer references users(id), note text);
+            create index idx_users_name on users (name);
+            create trigger trg_users after update on users begin update orders set note = new.name where user_id = new.id; end;
+            create view v_users as select id, name from users;
+            insert into users values (1, 'a', 20), (2, 'b', 30);
+            insert into orders values (10, 1, null);
+        ").unwrap();
+        conn
+    }
+
+    fn column(original: Option<&str>, name: &str, decl_type: &str) -> ColumnSpec {
+        ColumnSpec { original_name: original.map(|o| o.to_string()), name: name.to_string(), decl_type: decl_type.to_string(), ..ColumnSpec::default() }
+    }
+
+    #[test]
+    fn test_alter_table_native() {
+        let mut conn = open_test_db();
+        let mut spec = read_table_spec(&conn, "users").unwrap();
+        spec.name = "people".to_string();
+        spec.columns[1].name = "full_name".to_string();
+        spec.columns.remove(2);
+        spec.columns.push(ColumnSpec { default_value: Some("'x'".to_string()), ..column(None, "email", "TEXT") });
+
+        let preview = alter_table(&mut conn, "users", &spec, false).unwrap();
+        assert_eq!((preview.strategy, preview.applied), (AlterStrategy::Native, false));
+        assert_eq!(preview.statements.len(), 4, "{:?}", preview.statements);
+        assert!(read_table_spec(&conn, "users").is_ok(), "预览不应修改数据库");
+
+        alter_table(&mut conn, "users", &spec, true).unwrap();
+        let names: Vec<String> = conn.prepare("select full_name || email from people order by id").unwrap()
+            .query_map([], |r| r.get(0)).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(names, vec!["ax", "bx"]);
+        let view_sql: String = conn.query_row("select sql from sqlite_master where name = 'v_users'", [], |r| r.get(0)).unwrap();
+        assert!(view_sql.contains("people") && view_sql.contains("full_name"), "{}", view_sql);
+
+        let unchanged = read_table_spec(&conn, "people").unwrap();
+        assert!(alter_table(&mut conn, "people", &unchanged, true).unwrap().statements.is_empty());
+    }
+
+    #[test]
+    fn test_alter_table_rebuild() {
+        let mut conn = open_test_db();
+        let mut spec = read_table_spec(&conn, "users").unwrap();
+        spec.columns[1].name = "nick".to_string();
+        spec.columns[1].not_null = true;
+        spec.columns[2].decl_type = "REAL".to_string();
+        spec.columns.insert(1, ColumnSpec { default_value: Some("0".to_string()), ..column(None, "level", "INTEGER") });
+        spec.checks.push("age >= 0".to_string());
+
+        let preview = alter_table(&mut conn, "users", &spec, false).unwrap();
+        assert_eq!(preview.strategy, AlterStrategy::Rebuild);
+        assert_eq!(preview.statements.first().map(|s| s.as_str()), Some("PRAGMA foreign_keys = OFF"));
+        assert_eq!(preview.statements.last().map(|s| s.as_str()), Some("PRAGMA foreign_keys = ON"));
+        assert!(preview.statements.iter().any(|s| s.starts_with("INSERT INTO")));
+        assert_eq!(read_table_spec(&conn, "users").unwrap().columns[1].name, "name", "预览不应修改数据库");
+
+        let plan = alter_table(&mut conn, "users", &spec, true).unwrap();
+        assert!(plan.applied);
+        let altered = read_table_spec(&conn, "users").unwrap();
+        assert_eq!(normalize_table_spec(&altered), normalize_table_spec(&spec));
+        let row: (i64, String, f64) = conn.query_row("select level, nick, age from users where id = 2", [], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?))).unwrap();
+        assert_eq!(row, (0, "b".to_string(), 30.0));
+        let dependents: i64 = conn.query_row("select count(*) from sqlite_master where name in ('idx_users_name', 'trg_users')", [], |r| r.get(0)).unwrap();
+        assert_eq!(dependents, 2, "索引和触发器应被重建");
+        conn.execute("update users set nick = 'c' where id = 1", []).unwrap();
+        let note: String = conn.query_row("select note from orders where id = 10", [], |r| r.get(0)).unwrap();
+        assert_eq!(note, "c");
+        let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |r| r.get(0)).unwrap();
+        assert!(foreign_keys);
+
+        // 删除视图引用的字段时应整体回滚。
+        let mut spec = read_table_spec(&conn, "users").unwrap();
+        spec.columns.retain(|c| c.name != "nick");
+        spec.checks.clear();
+        assert!(alter_table(&mut conn, "users", &spec, true).is_err());
+        assert_eq!(read_table_spec(&conn, "users").unwrap().columns.len(), 4);
+
+        // 违反外键约束时回滚。
+        let mut spec = read_table_spec(&conn, "orders").unwrap();
+        spec.foreign_keys[0].ref_columns = vec!["level".to_string()];
+        conn.execute_batch("update users set level = id + 100; create unique index idx_users_level on users (level)").unwrap();
+        assert!(alter_table(&mut conn, "orders", &spec, true).unwrap_err().to_string().contains("外键"));
+        assert_eq!(read_table_spec(&conn, "orders").unwrap().foreign_keys[0].ref_columns, vec!["id"]);
+
+        // 使用双引号字符串或原本就无效的视图不影响重建。
+        conn.execute_batch("drop index idx_users_level; create view v_legacy as select \"literal\" as x from orders; create table gone (a); create view v_gone as select a from gone; drop table gone;").unwrap();
+        let mut spec = read_table_spec(&conn, "orders").unwrap();
+        spec.checks.push("id > 0".to_string());
+        assert_eq!(alter_table(&mut conn, "orders", &spec, true).unwrap().strategy, AlterStrategy::Rebuild);
+
+        // 重建会丢失约束名等子句，不允许重建。
+        conn.execute_batch("create table named (id integer constraint pk_named primary key, v int)").unwrap();
+        let mut spec = read_table_spec(&conn, "named").unwrap();
+        spec.columns[1].not_null = true;
+        assert!(alter_table(&mut conn, "named", &spec, true).unwrap_err().to_string().contains("CONSTRAINT"));
+        spec.columns[1].not_null = false;
+        spec.columns.push(column(None, "w", "TEXT"));
+        assert_eq!(alter_table(&mut conn, "named", &spec, true).unwrap().strategy, AlterStrategy::Native, "无需重建时仍可修改");
+    }
+}
@@ -0,0 +1,399 @@
+//! BLOB字段的查看、编辑和文件导入导出。读写文件时使用增量BLOB I/O，大字段不经过JSON传输。
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rusqlite::{Connection, DatabaseName, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::support::ident::quote_ident;
+
+/// 默认返回的最大字节数。
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+/// BLOB数据的文本编码方式。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlobEncoding {
+    /// 大写十六进制，与SQLITE的`hex()`一致。
+    Hex,
+    Base64,
+}
+
+/// BLOB内容的预览。
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlobPreview {
+    /// 可识别的图片格式，前端可按`data`和`mime`直接展示。
+    Image { mime: String },
+    /// UTF-8文本，数据被截断时只包含截断前的部分。
+    Text { text: String },
+    Json { value: serde_json::Value },
+    /// MessagePack数据，已转换为JSON。
+    Msgpack { value: serde_json::Value },
+    Binary,
+}
+
+/// BLOB单元格的内容。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlobCell {
+    /// BLOB的总字节数。
+    size: u64,
+    encoding: BlobEncoding,
+    /// 按`encoding`编码的数据，超过请求的最大字节数时只包含开头的部分。
+    data: String,
+    truncated: bool,
+    preview: BlobPreview,
+}
+
+/// 将字节编码为文本。
+pub fn encode_blob(bytes: &[u8], encoding: BlobEncoding) -> String {
+    match encoding {
+        BlobEncoding::Hex => bytes.iter().map(|b| format!("{:02X}", b)).collect(),
+        BlobEncoding::Base64 => BASE64.encode(bytes),
+    }
+}
+
+/// 将文本解码为字节，十六进制文本忽略其中的空白。
+pub fn decode_blob(data: &str, encoding: BlobEncoding) -> Result<Vec<u8>, Box<dyn Error>> {
+    match encoding {
+        BlobEncoding::Hex => {
+            let digits: Vec<u8> = data.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+            if digits.len() % 2 != 0 {
+                return Err("十六进制数据的长度应为偶数".into());
+            }
+            digits.chunks(2).map(|pair| {
+                let pair = std::str::from_utf8(pair)?;
+                Ok(u8::from_str_radix(pair, 16).map_err(|_| format!("无效的十六进制数据: {}", pair))?)
+            }).collect()
+        }
+        BlobEncoding::Base64 => Ok(BASE64.decode(data.trim())?),
+    }
+}
+
+/// 按文件头识别常见的图片格式。
+fn detect_image(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.len() >= 14 && bytes.starts_with(b"BM") && bytes[6..10] == [0, 0, 0, 0] {
+        Some("image/bmp")
+    } else if bytes.starts_with(b"\x00\x00\x01\x00") {
+        Some("image/x-icon")
+    } else {
+        None
+    }
+}
+
+/// 解析UTF-8文本，包含换行和制表符以外的控制字符时不视为文本。数据被截断时允许末尾有不完整的字符。
+fn detect_text(bytes: &[u8], truncated: bool) -> Option<&str> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) if truncated && e.error_len().is_none() => std::str::from_utf8(&bytes[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    if text.chars().any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t')) {
+        return None;
+    }
+    Some(text)
+}
+
+/// 识别BLOB内容。JSON和MessagePack须完整读取才能识别。
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(preview_blob(b"{\"a\":1}", false), BlobPreview::Json { value: json!({"a": 1}) });
+/// assert_eq!(preview_blob(&[0x81, 0xa1, b'a', 0x01], false), BlobPreview::Msgpack { value: json!({"a": 1}) });
+/// ```
+pub fn preview_blob(bytes: &[u8], truncated: bool) -> BlobPreview {
+    if let Some(mime) = detect_image(bytes) {
+        return BlobPreview::Image { mime: mime.to_string() };
+    }
+    if let Some(text) = detect_text(bytes, truncated) {
+        let trimmed = text.trim_start();
+        if !truncated && (trimmed.starts_with('{') || trimmed.starts_with('[')) {
+            if let Ok(value) = serde_json::from_str(text) {
+                return BlobPreview::Json { value };
+            }
+        }
+        if !text.is_empty() {
+            return BlobPreview::Text { text: text.to_string() };
+        }
+    }
+    if !truncated {
+        if let Some(value) = decode_msgpack(bytes) {
+            return BlobPreview::Msgpack { value };
+        }
+    }
+    BlobPreview::Binary
+}
+
+/// MessagePack解码允许的最大嵌套层数。
+const MSGPACK_MAX_DEPTH: usize = 64;
+
+/// 将MessagePack数据转换为JSON。只接受恰好由一个映射或数组组成的数据，以免把任意字节误判为MessagePack。
+fn decode_msgpack(bytes: &[u8]) -> Option<serde_json::Value> {
+    if !matches!(bytes.first(), Some(0x80..=0x9f) | Some(0xdc..=0xdf)) {
+        return None;
+    }
+    let mut reader = MsgpackReader { data: bytes, pos: 0 };
+    let value = reader.read_value(0)?;
+    if reader.pos == bytes.len() { Some(value) } else { None }
+}
+
+struct MsgpackReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> MsgpackReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len())?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Some(bytes)
+    }
+
+    fn read_uint(&mut self, len: usize) -> Option<u64> {
+        Some(self.take(len)?.iter().fold(0u64, |n, b| (n << 8) | *b as u64))
+    }
+
+    fn read_str(&mut self, len: usize) -> Option<serde_json::Value> {
+        Some(serde_json::Value::String(std::str::from_utf8(self.take(len)?).ok()?.to_string()))
+    }
+
+    fn read_array(&mut self, len: usize, depth: usize) -> Option<serde_json::Value> {
+        let mut values = vec![];
+        for _ in 0..len {
+            values.push(self.read_value(depth + 1)?);
+        }
+        Some(serde_json::Value::Array(values))
+    }
+
+    fn read_map(&mut self, len: usize, depth: usize) -> Option<serde_json::Value> {
+        let mut map = serde_json::Map::new();
+        for _ in 0..len {
+            let key = match self.read_value(depth + 1)? {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            map.insert(key, self.read_value(depth + 1)?);
+        }
+        Some(serde_json::Value::Object(map))
+    }
+
+    fn read_ext(&mut self, len: usize) -> Option<serde_json::Value> {
+        let ext_type = self.take(1)?[0] as i8;
+        let data = self.take(len)?;
+        Some(serde_json::json!({"ext": ext_type, "data": BASE64.encode(data)}))
+    }
+
+    fn read_value(&mut self, depth: usize) -> Option<serde_json::Value> {
+        if depth > MSGPACK_MAX_DEPTH {
+            return None;
+        }
+        let marker = self.take(1)?[0];
+        let value = match marker {
+            0x00..=0x7f => serde_json::Value::from(marker),
+            0x80..=0x8f => self.read_map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.read_array((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => self.read_str((marker & 0x1f) as usize)?,
+            0xc0 => serde_json::Value::Null,
+            0xc2 => serde_json::Value::Bool(false),
+            0xc3 => serde_json::Value::Bool(true),
+            0xc4..=0xc6 => {
+                let len = self.read_uint(1 << (marker - 0xc4))? as usize;
+                serde_json::Value::String(BASE64.encode(self.take(len)?))
+            }
+            0xc7..=0xc9 => {
+                let len = self.read_uint(1 << (marker - 0xc7))? as usize;
+                self.read_ext(len)?
+            }
+            0xca => serde_json::Value::from(f32::from_bits(self.read_uint(4)? as u32) as f64),
+            0xcb => serde_json::Value::from(f64::from_bits(self.read_uint(8)?)),
+            0xcc..=0xcf => serde_json::Value::from(self.read_uint(1 << (marker - 0xcc))?),
+            0xd0..=0xd3 => {
+                let len = 1 << (marker - 0xd0);
+                let n = self.read_uint(len)?;
+                // 按字节数做符号扩展。
+                let shift = 64 - len * 8;
+                serde_json::Value::from(((n << shift) as i64) >> shift)
+            }
+            0xd4..=0xd8 => self.read_ext(1 << (marker - 0xd4))?,
+            0xd9..=0xdb => {
+                let len = self.read_uint(1 << (marker - 0xd9))? as usize;
+                self.read_str(len)?
+            }
+            0xdc | 0xdd => {
+                let len = self.read_uint(if marker == 0xdc { 2 } else { 4 })? as usize;
+                self.read_array(len, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.read_uint(if marker == 0xde { 2 } else { 4 })? as usize;
+                self.read_map(len, depth)?
+            }
+            0xe0..=0xff => serde_json::Value::from(marker as i8),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// 确认目标行存在且单元格不为NULL，返回单元格的字节数。
+fn blob_size(conn: &Connection, table_name: &str, column: &str, rowid: i64) -> Result<u64, Box<dyn Error>> {
+    let sql = format!("select typeof({0}), length(cast({0} as blob)) from {1} where rowid = ?1", quote_ident(column), quote_ident(table_name));
+    let cell: Option<(String, Option<i64>)> = conn.query_row(sql.as_str(), [rowid], |r| Ok((r.get(0)?, r.get(1)?))).optional()?;
+    match cell {
+        None => Err(format!("表 {} 中不存在rowid为 {} 的行", table_name, rowid).into()),
+        Some((t, _)) if t == "null" => Err(format!("字段 {} 的值为NULL", column).into()),
+        Some((_, size)) => Ok(size.unwrap_or(0) as u64),
+    }
+}
+
+/// 读取BLOB单元格的内容。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的rusqlite连接。
+/// * `table_name`: 表名，须为有rowid的表。
+/// * `column`: 字段名。
+/// * `rowid`: 目标行的rowid。
+/// * `encoding`: 返回数据的编码方式。
+/// * `max_bytes`: 最多返回的字节数，超出部分不返回，`truncated`为`true`。
+///
+/// returns: Result<BlobCell, Box<dyn Error, Global>> 单元格为NULL或整数、浮点数时返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let cell = read_blob(&conn, "files", "content", 1, BlobEncoding::Base64, DEFAULT_MAX_BYTES).unwrap();
+/// ```
+pub fn read_blob(conn: &Connection, table_name: &str, column: &str, rowid: i64, encoding: BlobEncoding, max_bytes: usize) -> Result<BlobCell, Box<dyn Error>> {
+    let size = blob_size(conn, table_name, column, rowid)?;
+    let blob = conn.blob_open(DatabaseName::Main, table_name, column, rowid, true)?;
+    let mut bytes = vec![];
+    blob.take(max_bytes as u64).read_to_end(&mut bytes)?;
+    let truncated = (bytes.len() as u64) < size;
+    let preview = preview_blob(&bytes, truncated);
+    Ok(BlobCell { size, encoding, data: encode_blob(&bytes, encoding), truncated, preview })
+}
+
+/// 以编码后的文本替换单元格的值，适用于较小的数据。
+///
+/// returns: Result<(), Box<dyn Error, Global>> 目标行不存在时返回错误。
+pub fn write_blob(conn: &Connection, table_name: &str, column: &str, rowid: i64, data: &str, encoding: BlobEncoding) -> Result<(), Box<dyn Error>> {
+    let bytes = decode_blob(data, encoding)?;
+    let sql = format!("update {} set {} = ?1 where rowid = ?2", quote_ident(table_name), quote_ident(column));
+    if conn.execute(sql.as_str(), rusqlite::params![bytes, rowid])? == 0 {
+        return Err(format!("表 {} 中不存在rowid为 {} 的行", table_name, rowid).into());
+    }
+    Ok(())
+}
+
+/// 将BLOB单元格的内容保存到文件。
+///
+/// returns: Result<u64, Box<dyn Error, Global>> 返回写入的字节数。
+pub fn export_blob(conn: &Connection, table_name: &str, column: &str, rowid: i64, target_path: &Path) -> Result<u64, Box<dyn Error>> {
+    blob_size(conn, table_name, column, rowid)?;
+    let mut blob = conn.blob_open(DatabaseName::Main, table_name, column, rowid, true)?;
+    let mut target = File::create(target_path)?;
+    Ok(io::copy(&mut blob, &mut target)?)
+}
+
+/// 以文件内容替换单元格的值。
+///
+/// 先在事务中将单元格更新为与文件等长的`zeroblob`，再通过增量BLOB I/O逐块写入文件内容，失败时回滚。
+///
+/// returns: Result<u64, Box<dyn Error, Global>> 返回写入的字节数。
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let written = import_blob(&conn, "files", "content", 1, Path::new("/home/foo/tmp/photo.png")).unwrap();
+/// ```
+pub fn import_blob(conn: &Connection, table_name: &str, column: &str, rowid: i64, source_path: &Path) -> Result<u64, Box<dyn Error>> {
+    let mut source = File::open(source_path)?;
+    let size = source.metadata()?.len();
+    if size > i32::MAX as u64 {
+        return Err(format!("文件过大，BLOB最大为 {} 字节", i32::MAX).into());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let sql = format!("update {} set {} = zeroblob(?1) where rowid = ?2", quote_ident(table_name), quote_ident(column));
+    if tx.execute(sql.as_str(), [size as i64, rowid])? == 0 {
+        return Err(format!("表 {} 中不存在rowid为 {} 的行", table_name, rowid).into());
+    }
+    let written = {
+        let mut blob = tx.blob_open(DatabaseName::Main, table_name, column, rowid, false)?;
+        io::copy(&mut source, &mut blob)?
+    };
+    tx.commit()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_preview_blob() {
+        assert_eq!(preview_blob(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", false), BlobPreview::Image { mime: "image/png".to_string() });
+        assert_eq!(preview_blob(" [1, \"a\"]".as_bytes(), false), BlobPreview::Json { value: json!([1, "a"]) });
+        assert_eq!(preview_blob("{\"a\":".as_bytes(), true), BlobPreview::Text { text: "{\"a\":".to_string() });
+        // 截断位置落在多字节字符中间时仍按文本处理。
+        assert_eq!(preview_blob(&"中文".as_bytes()[..4], true), BlobPreview::Text { text: "中".to_string() });
+        let packed = [0x82, 0xa1, b'a', 0x93, 0x01, 0xff, 0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0, 0xa1, b'b', 0xc0];
+        assert_eq!(preview_blob(&packed, false), BlobPreview::Msgpack { value: json!({"a": [1, -1, 1.5], "b": null}) });
+        assert_eq!(preview_blob(&packed[..10], false), BlobPreview::Binary);
+        assert_eq!(preview_blob(&[0x01, 0x02, 0x00], false), BlobPreview::Binary);
+
+        assert_eq!(encode_blob(&[0x0a, 0xff], BlobEncoding::Hex), "0AFF");
+        assert_eq!(decode_blob("0a ff", BlobEncoding::Hex).unwrap(), vec![0x0a, 0xff]);
+        assert!(decode_blob("0af", BlobEncoding::Hex).is_err());
+        assert_eq!(decode_blob(&encode_blob(b"abc", BlobEncoding::Base64), BlobEncoding::Base64).unwrap(), b"abc".to_vec());
+    }
+
+    #[test]
+    fn test_blob_io() {
+        let mut dir = std::env::temp_dir();
+        dir.push("sqlcipher-front-blob");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("create table \"my files\" (name text, \"data\" blob); insert into \"my files\" values ('a', null);").unwrap();
+        assert!(read_blob(&conn, "my files", "data", 1, BlobEncoding::Hex, 10).is_err(), "NULL值不能读取");
+
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let source = dir.join("source.bin");
+        std::fs::write(&source, &content).unwrap();
+        assert_eq!(import_blob(&conn, "my files", "data", 1, &source).unwrap(), content.len() as u64);
+        assert!(import_blob(&conn, "my files", "data", 2, &source).is_err());
+
+        let cell = read_blob(&conn, "my files", "data", 1, BlobEncoding::Hex, 4).unwrap();
+        assert_eq!((cell.size, cell.data.as_str(), cell.truncated), (100_000, "00010203", true));
+
+        let target = dir.join("target.bin");
+        assert_eq!(export_blob(&conn, "my files", "data", 1, &target).unwrap(), content.len() as u64);
+        assert_eq!(std::fs::read(&target).unwrap(), content);
+
+        write_blob(&conn, "my files", "data", 1, &encode_blob(b"{\"k\": true}", BlobEncoding::Base64), BlobEncoding::Base64).unwrap();
+        let cell = read_blob(&conn, "my files", "data", 1, BlobEncoding::Base64, DEFAULT_MAX_BYTES).unwrap();
+        assert_eq!((cell.size, cell.truncated), (11, false));
+        assert_eq!(cell.preview, BlobPreview::Json { value: json!({"k": true}) });
+    }
+}
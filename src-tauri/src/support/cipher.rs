@@ -0,0 +1,205 @@
+//! SQLCipher加密参数配置，用于打开非默认加密设置(如SQLCipher 3兼容格式)的数据库文件。
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use rusqlite::{Connection, DatabaseName};
+use serde::{Deserialize, Serialize};
+
+/// 加密参数预设方案。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CipherPreset {
+    /// 使用当前SQLCipher库的默认设置，不附加任何参数。
+    Default,
+    Sqlcipher1,
+    Sqlcipher2,
+    Sqlcipher3,
+    Sqlcipher4,
+    /// 自定义模式，仅应用显式给出的参数。
+    Custom,
+}
+
+impl Default for CipherPreset {
+    fn default() -> Self { CipherPreset::Default }
+}
+
+impl CipherPreset {
+    /// 预设方案对应的`cipher_compatibility`取值，默认和自定义模式返回`None`。
+    pub fn compatibility(&self) -> Option<u8> {
+        match self {
+            CipherPreset::Sqlcipher1 => Some(1),
+            CipherPreset::Sqlcipher2 => Some(2),
+            CipherPreset::Sqlcipher3 => Some(3),
+            CipherPreset::Sqlcipher4 => Some(4),
+            _ => None,
+        }
+    }
+}
+
+/// 完整的加密参数配置。
+///
+/// 先按预设方案设置`cipher_compatibility`，再依次应用显式给出的参数，因此显式参数可以覆盖预设值。
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CipherProfile {
+    #[serde(default)]
+    pub preset: CipherPreset,
+    pub kdf_iter: Option<u32>,
+    pub cipher_page_size: Option<u32>,
+    /// 取值为`HMAC_SHA1`、`HMAC_SHA256`或`HMAC_SHA512`。
+    pub hmac_algorithm: Option<String>,
+    /// 取值为`PBKDF2_HMAC_SHA1`、`PBKDF2_HMAC_SHA256`或`PBKDF2_HMAC_SHA512`。
+    pub kdf_algorithm: Option<String>,
+    pub use_hmac: Option<bool>,
+    pub plaintext_header_size: Option<u32>,
+    /// 32位十六进制字符串表示的盐值，使用明文文件头时必须提供。
+    pub salt: Option<String>,
+}
+
+const HMAC_ALGORITHMS: [&str; 3] = ["HMAC_SHA1", "HMAC_SHA256", "HMAC_SHA512"];
+const KDF_ALGORITHMS: [&str; 3] = ["PBKDF2_HMAC_SHA1", "PBKDF2_HMAC_SHA256", "PBKDF2_HMAC_SHA512"];
+
+#[derive(Debug)]
+pub struct InvalidCipherProfile(String);
+
+impl Display for InvalidCipherProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "无效的加密参数: {}", self.0)
+    }
+}
+
+impl Error for InvalidCipherProfile {}
+
+impl CipherProfile {
+    /// 是否与库的默认设置一致，即无需附加任何加密参数。
+    pub fn is_default(&self) -> bool {
+        self.pragmas().is_empty()
+    }
+
+    /// 校验参数取值。参数会被拼接进连接池的初始化语句，因此所有字符串参数都必须来自固定的取值范围。
+    pub fn validate(&self) -> Result<(), InvalidCipherProfile> {
+        if let Some(size) = self.cipher_page_size {
+            if !(512..=65536).contains(&size) || !size.is_power_of_two() {
+                return Err(InvalidCipherProfile(format!("cipher_page_size 应为512到65536之间2的幂，实际为 {}", size)));
+            }
+        }
+        if let Some(iter) = self.kdf_iter {
+            if iter == 0 {
+                return Err(InvalidCipherProfile("kdf_iter 应大于0".to_string()));
+            }
+        }
+        if let Some(alg) = &self.hmac_algorithm {
+            if !HMAC_ALGORITHMS.contains(&alg.as_str()) {
+                return Err(InvalidCipherProfile(format!("不支持的 cipher_hmac_algorithm: {}", alg)));
+            }
+        }
+        if let Some(alg) = &self.kdf_algorithm {
+            if !KDF_ALGORITHMS.contains(&alg.as_str()) {
+                return Err(InvalidCipherProfile(format!("不支持的 cipher_kdf_algorithm: {}", alg)));
+            }
+        }
+        if let Some(size) = self.plaintext_header_size {
+            if size % 16 != 0 || self.cipher_page_size.map_or(false, |page| size > page) {
+                return Err(InvalidCipherProfile(format!("plaintext_header_size 应为16的倍数且不超过页大小，实际为 {}", size)));
+            }
+        }
+        if let Some(salt) = &self.salt {
+            if salt.len() != 32 || !salt.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(InvalidCipherProfile("salt 应为32位十六进制字符串".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// 按执行顺序生成需要附加的加密参数语句，每一项为`(pragma名称, 取值)`。
+    pub fn pragmas(&self) -> Vec<(&'static str, String)> {
+        let mut pragmas = vec![];
+        if let Some(compat) = self.preset.compatibility() {
+            pragmas.push(("cipher_compatibility", compat.to_string()));
+        }
+        if let Some(size) = self.cipher_page_size {
+            pragmas.push(("cipher_page_size", size.to_string()));
+        }
+        if let Some(iter) = self.kdf_iter {
+            pragmas.push(("kdf_iter", iter.to_string()));
+        }
+        if let Some(alg) = &self.hmac_algorithm {
+            pragmas.push(("cipher_hmac_algorithm", alg.clone()));
+        }
+        if let Some(alg) = &self.kdf_algorithm {
+            pragmas.push(("cipher_kdf_algorithm", alg.clone()));
+        }
+        if let Some(use_hmac) = self.use_hmac {
+            pragmas.push(("cipher_use_hmac", if use_hmac { "ON" } else { "OFF" }.to_string()));
+        }
+        if let Some(salt) = &self.salt {
+            pragmas.push(("cipher_salt", format!("x'{}'", salt)));
+        }
+        if let Some(size) = self.plaintext_header_size {
+            pragmas.push(("cipher_plaintext_header_size", size.to_string()));
+        }
+        pragmas
+    }
+
+    /// 在已设置密钥的rusqlite连接上应用加密参数，必须在首次读取数据库之前调用。
+    ///
+    /// # Arguments
+    ///
+    /// * `conn`: rusqlite连接。
+    /// * `schema`: 目标数据库，为`None`时作用于主库；对附加库使用时须在`ATTACH ... KEY`之后调用。
+    pub fn apply(&self, conn: &Connection, schema: Option<DatabaseName<'_>>) -> Result<(), Box<dyn Error>> {
+        self.validate()?;
+        for (name, value) in self.pragmas() {
+            conn.pragma_update(schema, name, value)?;
+        }
+        Ok(())
+    }
+
+    /// 生成供rbdc连接池使用的`key`参数值。
+    ///
+    /// rbdc总是最先执行`PRAGMA key = <值>`，其余pragma则排在`journal_mode`等默认设置之后，
+    /// 而加密参数必须在首次读取数据库之前设置，因此将加密参数语句拼接在密钥之后一并执行。
+    pub fn pool_key_value(&self, key: &str) -> String {
+        let mut value = quote_literal(key);
+        for (name, v) in self.pragmas() {
+            if name.eq("cipher_salt") {
+                value.push_str(format!("; PRAGMA {} = \"{}\"", name, v).as_str());
+            } else {
+                value.push_str(format!("; PRAGMA {} = {}", name, v).as_str());
+            }
+        }
+        value
+    }
+}
+
+/// 将字符串转换为SQL字符串字面量。
+pub fn quote_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let mut profile = CipherProfile { preset: CipherPreset::Custom, cipher_page_size: Some(4096), kdf_iter: Some(64000), ..Default::default() };
+        assert!(profile.validate().is_ok());
+
+        profile.hmac_algorithm = Some("HMAC_SHA1; drop table t".to_string());
+        assert!(profile.validate().is_err(), "非法算法名称应被拒绝");
+
+        profile.hmac_algorithm = Some("HMAC_SHA1".to_string());
+        profile.cipher_page_size = Some(3000);
+        assert!(profile.validate().is_err(), "非2的幂页大小应被拒绝");
+    }
+
+    #[test]
+    fn test_pool_key_value() {
+        let profile = CipherProfile::default();
+        assert!(profile.is_default());
+        assert_eq!(profile.pool_key_value("it's"), "'it''s'");
+
+        let profile = CipherProfile { preset: CipherPreset::Sqlcipher3, kdf_iter: Some(10000), ..Default::default() };
+        assert_eq!(profile.pool_key_value("123456"), "'123456'; PRAGMA cipher_compatibility = 3; PRAGMA kdf_iter = 10000");
+    }
+}
@@ -0,0 +1,253 @@
+//! CSV/TSV文件的读写，遵循RFC 4180：字段可用引号包围，引号内可包含分隔符和换行，两个连续的引号表示一个引号。
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// CSV格式选项。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvFormat {
+    /// 字段分隔符，TSV文件为`\t`。
+    pub delimiter: char,
+    pub quote: char,
+    /// 第一行是否为字段名。
+    pub has_header: bool,
+    /// 写入时何时为字段加引号，读取时忽略。
+    pub quote_style: QuoteStyle,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        CsvFormat { delimiter: ',', quote: '"', has_header: true, quote_style: QuoteStyle::Necessary }
+    }
+}
+
+/// 写入字段时加引号的规则。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuoteStyle {
+    /// 仅在字段包含分隔符、引号或换行时加引号。
+    Necessary,
+    Always,
+    /// 数值以外的字段都加引号。
+    NonNumeric,
+}
+
+impl CsvFormat {
+    /// 按文件扩展名选择默认格式，`.tsv`和`.tab`文件使用制表符分隔。
+    pub fn for_path(path: &str) -> CsvFormat {
+        let lower = path.to_lowercase();
+        let delimiter = if lower.ends_with(".tsv") || lower.ends_with(".tab") { '\t' } else { ',' };
+        CsvFormat { delimiter, ..Default::default() }
+    }
+
+    /// 校验分隔符和引号。两者相同或为换行符时无法区分字段边界，读写的结果都会出错。
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.delimiter == self.quote {
+            return Err(format!("分隔符和引号不能相同: {:?}", self.delimiter).into());
+        }
+        for c in [self.delimiter, self.quote] {
+            if c == '\r' || c == '\n' {
+                return Err("分隔符和引号不能是换行符".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 一条CSV记录。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvRecord {
+    /// 记录在文件中的起始行号，从1开始。
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+/// 逐条读取CSV记录，不一次性加载整个文件。
+pub struct CsvReader<R: BufRead> {
+    reader: R,
+    format: CsvFormat,
+    /// 已读取的行数。
+    line: usize,
+}
+
+impl<R: BufRead> CsvReader<R> {
+    pub fn new(reader: R, format: CsvFormat) -> CsvReader<R> {
+        CsvReader { reader, format, line: 0 }
+    }
+
+    /// 读取一行，返回`false`表示已到文件末尾。
+    fn read_line(&mut self, buf: &mut String) -> Result<bool, Box<dyn Error>> {
+        buf.clear();
+        if self.reader.read_line(buf)? == 0 {
+            return Ok(false);
+        }
+        self.line += 1;
+        if self.line == 1 && buf.starts_with('\u{feff}') {
+            buf.remove(0);
+        }
+        Ok(true)
+    }
+
+    /// 读取下一条记录，跳过空行。引号内的换行会使一条记录跨越多行。
+    ///
+    /// returns: Result<Option<CsvRecord>, Box<dyn Error, Global>> 文件结束时返回`None`，引号未闭合时返回错误。
+    pub fn next_record(&mut self) -> Result<Option<CsvRecord>, Box<dyn Error>> {
+        let mut buf = String::new();
+        loop {
+            if !self.read_line(&mut buf)? {
+                return Ok(None);
+            }
+            if !buf.trim_end_matches(&['\r', '\n'][..]).is_empty() {
+                break;
+            }
+        }
+
+        let start_line = self.line;
+        let (delimiter, quote) = (self.format.delimiter, self.format.quote);
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut in_quotes = false;
+        loop {
+            let mut chars = buf.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    if c == quote {
+                        if chars.peek() == Some(&quote) {
+                            field.push(quote);
+                            chars.next();
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(c);
+                    }
+                } else if c == quote && field.is_empty() {
+                    in_quotes = true;
+                } else if c == delimiter {
+                    fields.push(std::mem::take(&mut field));
+                } else if c == '\n' || (c == '\r' && chars.peek() == Some(&'\n')) {
+                    // 记录结束。
+                } else {
+                    field.push(c);
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            if !self.read_line(&mut buf)? {
+                return Err(format!("第 {} 行开始的引号未闭合", start_line).into());
+            }
+        }
+        fields.push(field);
+        Ok(Some(CsvRecord { line: start_line, fields }))
+    }
+}
+
+/// 逐条写入CSV记录，记录以`\r\n`结束。
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    format: CsvFormat,
+    /// 当前记录已写入的字段数。
+    fields: usize,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W, format: CsvFormat) -> CsvWriter<W> {
+        CsvWriter { writer, format, fields: 0 }
+    }
+
+    /// 写入当前记录的一个字段。
+    ///
+    /// # Arguments
+    ///
+    /// * `value`: 字段值。
+    /// * `numeric`: 字段是否为数值，`QuoteStyle::NonNumeric`时数值不加引号。
+    pub fn write_field(&mut self, value: &str, numeric: bool) -> std::io::Result<()> {
+        if self.fields > 0 {
+            write!(self.writer, "{}", self.format.delimiter)?;
+        }
+        self.fields += 1;
+        let quote = self.format.quote;
+        let quoted = match self.format.quote_style {
+            QuoteStyle::Always => true,
+            QuoteStyle::NonNumeric => !numeric,
+            QuoteStyle::Necessary => value.contains(|c| c == self.format.delimiter || c == quote || c == '\r' || c == '\n'),
+        };
+        if quoted {
+            let escaped = value.replace(quote, &format!("{}{}", quote, quote));
+            write!(self.writer, "{}{}{}", quote, escaped, quote)
+        } else {
+            self.writer.write_all(value.as_bytes())
+        }
+    }
+
+    /// 结束当前记录。
+    pub fn end_record(&mut self) -> std::io::Result<()> {
+        self.fields = 0;
+        self.writer.write_all(b"\r\n")
+    }
+
+    /// 写入一条全部为文本的记录，如标题行。
+    pub fn write_record<S: AsRef<str>>(&mut self, fields: &[S]) -> std::io::Result<()> {
+        for field in fields {
+            self.write_field(field.as_ref(), false)?;
+        }
+        self.end_record()
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_reader() {
+        let data = "\u{feff}id,name,note\r\n1,\"Tom, Jr.\",\"say \"\"hi\"\"\"\n\n2,Jerry,\"line1\nline2\"\n3,,\n";
+        let mut reader = CsvReader::new(data.as_bytes(), CsvFormat::default());
+        let mut records = vec![];
+        while let Some(record) = reader.next_record().unwrap() {
+            records.push(record);
+        }
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].fields, vec!["id", "name", "note"]);
+        assert_eq!(records[1].fields, vec!["1", "Tom, Jr.", "say \"hi\""]);
+        assert_eq!((records[2].line, records[2].fields[2].as_str()), (4, "line1\nline2"));
+        assert_eq!((records[3].line, records[3].fields.clone()), (6, vec!["3".to_string(), "".to_string(), "".to_string()]));
+
+        let mut reader = CsvReader::new("a\tb\n\"x".as_bytes(), CsvFormat::for_path("data.TSV"));
+        assert_eq!(reader.next_record().unwrap().unwrap().fields, vec!["a", "b"]);
+        assert!(reader.next_record().is_err(), "引号未闭合时应报错");
+
+        assert!(CsvFormat { delimiter: '"', ..Default::default() }.validate().is_err(), "分隔符与引号相同时应报错");
+        assert!(CsvFormat { quote: '\n', ..Default::default() }.validate().is_err());
+        assert!(CsvFormat::for_path("data.tsv").validate().is_ok());
+    }
+
+    #[test]
+    fn test_csv_writer() {
+        let mut writer = CsvWriter::new(vec![], CsvFormat::default());
+        writer.write_record(&["id", "name"]).unwrap();
+        writer.write_field("1", true).unwrap();
+        writer.write_field("say \"hi\", bye\nnow", false).unwrap();
+        writer.end_record().unwrap();
+        let written = String::from_utf8(writer.writer).unwrap();
+        assert_eq!(written, "id,name\r\n1,\"say \"\"hi\"\", bye\nnow\"\r\n");
+
+        let mut reader = CsvReader::new(written.as_bytes(), CsvFormat::default());
+        reader.next_record().unwrap();
+        assert_eq!(reader.next_record().unwrap().unwrap().fields, vec!["1", "say \"hi\", bye\nnow"]);
+
+        let format = CsvFormat { delimiter: ';', quote_style: QuoteStyle::NonNumeric, ..Default::default() };
+        let mut writer = CsvWriter::new(vec![], format);
+        writer.write_field("2.5", true).unwrap();
+        writer.write_field("", false).unwrap();
+        writer.end_record().unwrap();
+        assert_eq!(String::from_utf8(writer.writer).unwrap(), "2.5;\"\"\r\n");
+    }
+}
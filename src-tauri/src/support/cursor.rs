@@ -0,0 +1,267 @@
+//! 结果集游标。查询在单独的线程中执行，前端每次调用`fetch_next`读取一批数据，读取多少执行多少，不会一次性加载整个结果集。
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use rusqlite::{Connection, InterruptHandle, Rows};
+use serde::{Deserialize, Serialize};
+
+use crate::support::executor::{bind_json_params, ColumnDesc, describe_columns, is_interrupted, prepare_single, record_recent_query, sql_to_json};
+
+/// 已打开的游标，以游标编号为键。
+static CURSORS: Lazy<Mutex<HashMap<String, Cursor>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_CURSOR_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 单批最多读取的行数。
+const MAX_BATCH_ROWS: usize = 10000;
+/// 游标空闲超过该时间未读取时自动关闭，释放连接及其读锁。
+const CURSOR_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 游标线程的读取请求，附带用于回传结果的通道。
+struct FetchRequest {
+    max_rows: usize,
+    reply: Sender<Result<RowBatch, String>>,
+}
+
+struct Cursor {
+    db_path: String,
+    requests: Sender<FetchRequest>,
+    interrupt: InterruptHandle,
+}
+
+/// 游标的基本信息。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CursorInfo {
+    cursor_id: String,
+    columns: Vec<ColumnDesc>,
+}
+
+/// 一批结果数据。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RowBatch {
+    /// 每行按`CursorInfo.columns`的顺序排列。
+    rows: Vec<Vec<serde_json::Value>>,
+    /// 结果集是否已读取完毕，为`true`时游标已自动关闭。
+    done: bool,
+}
+
+/// 打开游标。
+///
+/// 游标独占传入的连接，连接被设置为只读，语句在游标线程中逐批执行；读取完毕、出错、调用`close_cursor`或空闲超过10分钟后释放连接。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的rusqlite连接，不能是连接池中的连接。
+/// * `db_path`: 数据库文件路径，用于在丢弃连接池时关闭该库的游标。
+/// * `sql`: 单条返回结果集的查询语句。
+/// * `params`: 可选的参数，规则同`execute_script`。
+///
+/// returns: Result<CursorInfo, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let info = open_cursor(conn, "/home/foo/tmp/sqlite/my.db", "select * from my_table where id > ?", Some(json!([100]))).unwrap();
+/// let batch = fetch_next(&info.cursor_id, 500).unwrap();
+/// if !batch.done {
+///     close_cursor(&info.cursor_id).unwrap();
+/// }
+/// ```
+pub fn open_cursor(conn: Connection, db_path: &str, sql: &str, params: Option<serde_json::Value>) -> Result<CursorInfo, Box<dyn Error>> {
+    open_cursor_with_timeout(conn, db_path, sql, params, CURSOR_IDLE_TIMEOUT)
+}
+
+/// 打开游标，游标空闲超过`idle_timeout`未读取时自动关闭。
+fn open_cursor_with_timeout(conn: Connection, db_path: &str, sql: &str, params: Option<serde_json::Value>, idle_timeout: Duration) -> Result<CursorInfo, Box<dyn Error>> {
+    let sql = sql.to_string();
+    conn.pragma_update(None, "query_only", true)?;
+    let interrupt = conn.get_interrupt_handle();
+
+    // 在启动游标线程之前登记，之后任何一步出错都能通过`close_cursor`释放连接，线程空闲超时后的移除也不会早于登记。
+    let cursor_id = format!("c{}", NEXT_CURSOR_ID.fetch_add(1, Ordering::SeqCst));
+    // 游标线程编译语句后回传字段描述和语句文本。
+    let (ready_tx, ready_rx) = channel::<Result<(Vec<ColumnDesc>, String), String>>();
+    let (request_tx, request_rx) = channel::<FetchRequest>();
+    CURSORS.lock()?.insert(cursor_id.clone(), Cursor { db_path: db_path.to_string(), requests: request_tx, interrupt });
+    let thread_cursor_id = cursor_id.clone();
+    thread::spawn(move || {
+        let mut prepared = match prepare_single(&conn, &sql) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                let _ = ready_tx.send(Err(e.to_string()));
+                return;
+            }
+        };
+        if prepared.stmt.column_count() == 0 {
+            let _ = ready_tx.send(Err("游标只能执行返回结果集的语句".to_string()));
+            return;
+        }
+        if let Err(e) = bind_json_params(&mut prepared.stmt, &params) {
+            let _ = ready_tx.send(Err(e.to_string()));
+            return;
+        }
+        let columns = describe_columns(&prepared);
+        let column_count = columns.len();
+        let _ = ready_tx.send(Ok((columns, prepared.piece.sql.clone())));
+
+        // 请求通道关闭即表示游标已被关闭。
+        let mut rows = prepared.stmt.raw_query();
+        while let Ok(request) = request_rx.recv_timeout(idle_timeout) {
+            let batch = read_batch(&mut rows, column_count, request.max_rows);
+            let finished = batch.as_ref().map_or(true, |b| b.done);
+            let _ = request.reply.send(batch);
+            if finished {
+                break;
+            }
+        }
+        // 空闲超时后从游标列表中移除，之后的读取将返回游标已关闭。
+        if let Ok(mut cursors) = CURSORS.lock() {
+            cursors.remove(&thread_cursor_id);
+        }
+    });
+
+    let opened = (|| -> Result<Vec<ColumnDesc>, Box<dyn Error>> {
+        let (columns, sql) = ready_rx.recv()??;
+        record_recent_query(db_path, &sql)?;
+        Ok(columns)
+    })();
+    match opened {
+        Ok(columns) => Ok(CursorInfo { cursor_id, columns }),
+        Err(e) => {
+            close_cursor(&cursor_id)?;
+            Err(e)
+        }
+    }
+}
+
+/// 从结果集中读取至多`max_rows`行。
+fn read_batch(rows: &mut Rows<'_>, column_count: usize, max_rows: usize) -> Result<RowBatch, String> {
+    let mut batch = RowBatch { rows: vec![], done: false };
+    while batch.rows.len() < max_rows {
+        match rows.next() {
+            Ok(Some(row)) => {
+                let values = (0..column_count).map(|i| row.get_ref(i).map(sql_to_json)).collect::<rusqlite::Result<Vec<_>>>();
+                batch.rows.push(values.map_err(|e| e.to_string())?);
+            }
+            Ok(None) => {
+                batch.done = true;
+                break;
+            }
+            Err(e) if is_interrupted(&e) => return Err("游标已关闭".to_string()),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(batch)
+}
+
+/// 读取游标的下一批数据。读取完毕或出错时游标自动关闭。
+///
+/// # Arguments
+///
+/// * `cursor_id`: 游标编号。
+/// * `max_rows`: 本批最多读取的行数，取值范围为1到10000。
+///
+/// returns: Result<RowBatch, Box<dyn Error, Global>> 游标不存在时返回错误。
+pub fn fetch_next(cursor_id: &str, max_rows: usize) -> Result<RowBatch, Box<dyn Error>> {
+    let requests = CURSORS.lock()?.get(cursor_id).map(|c| c.requests.clone()).ok_or_else(|| format!("游标 {} 不存在或已关闭", cursor_id))?;
+    let (reply_tx, reply_rx) = channel();
+    let max_rows = max_rows.clamp(1, MAX_BATCH_ROWS);
+    let batch = requests.send(FetchRequest { max_rows, reply: reply_tx }).ok().and_then(|_| reply_rx.recv().ok());
+    match batch {
+        Some(Ok(batch)) if !batch.done => Ok(batch),
+        Some(result) => {
+            CURSORS.lock()?.remove(cursor_id);
+            Ok(result?)
+        }
+        None => {
+            CURSORS.lock()?.remove(cursor_id);
+            Err(format!("游标 {} 已关闭", cursor_id).into())
+        }
+    }
+}
+
+/// 关闭游标，正在读取的批次将被中断。
+///
+/// returns: Result<bool, Box<dyn Error, Global>> 游标已读取完毕或编号不存在时返回`false`。
+pub fn close_cursor(cursor_id: &str) -> Result<bool, Box<dyn Error>> {
+    match CURSORS.lock()?.remove(cursor_id) {
+        Some(cursor) => {
+            cursor.interrupt.interrupt();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// 关闭数据库文件上的所有游标，在修改密钥、迁移等需要独占文件的操作前调用。
+pub fn close_db_cursors(db_path: &str) -> Result<(), Box<dyn Error>> {
+    CURSORS.lock()?.retain(|_, cursor| {
+        if cursor.db_path == db_path {
+            cursor.interrupt.interrupt();
+            return false;
+        }
+        true
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::support::executor::recent_queries;
+
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        let conn = Connection::open_in_memory().unwrap();
+        let sql = "with recursive c(x) as (select 1 union all select x + 1 from c where x < ?) select x, 'r' || x as name from c;";
+        let info = open_cursor(conn, ":memory:", sql, Some(json!([25]))).unwrap();
+        let names: Vec<&str> = info.columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["x", "name"]);
+        assert_eq!(recent_queries(":memory:").unwrap(), vec![sql], "游标的查询应记入最近执行的语句");
+
+        let first = fetch_next(&info.cursor_id, 10).unwrap();
+        assert_eq!(first.rows.len(), 10);
+        assert_eq!(first.rows[0], vec![json!(1), json!("r1")]);
+        assert!(!first.done);
+        let second = fetch_next(&info.cursor_id, 20).unwrap();
+        assert_eq!(second.rows.len(), 15);
+        assert_eq!(second.rows[14][0], json!(25));
+        assert!(second.done);
+        assert!(fetch_next(&info.cursor_id, 10).is_err(), "读取完毕后游标应自动关闭");
+
+        // 提前关闭无限结果集的游标。
+        let conn = Connection::open_in_memory().unwrap();
+        let info = open_cursor(conn, ":memory:", "with recursive c(x) as (select 1 union all select x + 1 from c) select x from c", None).unwrap();
+        assert_eq!(fetch_next(&info.cursor_id, 100).unwrap().rows.len(), 100);
+        assert!(close_cursor(&info.cursor_id).unwrap());
+        assert!(!close_cursor(&info.cursor_id).unwrap());
+        assert!(fetch_next(&info.cursor_id, 100).is_err());
+
+        for sql in ["select 1; select 2", "create table t (v)", "select * from missing"] {
+            assert!(open_cursor(Connection::open_in_memory().unwrap(), ":memory:", sql, None).is_err(), "{}", sql);
+        }
+
+        assert!(CURSORS.lock().unwrap().values().all(|c| c.db_path != ":memory:"), "打开失败的游标不应保留");
+
+        // 空闲超时后游标自动关闭，超时为0时游标线程立即退出。
+        let conn = Connection::open_in_memory().unwrap();
+        let info = open_cursor_with_timeout(conn, "idle.db", "select 1 union all select 2", None, Duration::ZERO).unwrap();
+        for _ in 0..1000 {
+            if !CURSORS.lock().unwrap().contains_key(&info.cursor_id) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!close_cursor(&info.cursor_id).unwrap(), "空闲的游标应已被移除");
+        assert!(fetch_next(&info.cursor_id, 1).is_err());
+    }
+}
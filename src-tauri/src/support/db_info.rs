@@ -0,0 +1,158 @@
+//! 数据库属性：读取页大小、编码、日志模式、SQLCipher版本等PRAGMA和文件信息，以及修改其中可写的PRAGMA。
+use std::error::Error;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::support::maintenance::{space_usage, SpaceUsage};
+
+/// 数据库属性。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DbInfo {
+    #[serde(flatten)]
+    pub space: SpaceUsage,
+    pub encoding: String,
+    pub journal_mode: String,
+    /// `none`、`full`或`incremental`。
+    pub auto_vacuum: String,
+    pub user_version: i32,
+    pub application_id: i32,
+    pub schema_version: i64,
+    /// 当前连接是否启用外键约束。
+    pub foreign_keys: bool,
+    pub cipher_version: Option<String>,
+    /// 加密库名称，仅在设置了密钥时有值。
+    pub cipher_provider: Option<String>,
+    /// 完整的加密参数，每项为一条PRAGMA语句，未加密时为空。
+    pub cipher_settings: Vec<String>,
+}
+
+/// 可通过接口修改的日志模式。WAL模式保存在数据库文件中，其它模式只对单个连接有效，因此只支持在DELETE和WAL之间切换。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalMode {
+    Delete,
+    Wal,
+}
+
+/// 可写的PRAGMA及其新值。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "pragma", content = "value", rename_all = "snake_case")]
+pub enum PragmaUpdate {
+    UserVersion(i32),
+    ApplicationId(i32),
+    JournalMode(JournalMode),
+    /// 外键约束只对单个连接有效，调用方须自行保存该设置并应用到之后打开的连接。
+    ForeignKeys(bool),
+}
+
+/// 读取返回多行文本的PRAGMA，如`cipher_settings`。
+fn pragma_rows(conn: &Connection, name: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}", name))?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows)
+}
+
+/// 读取数据库属性。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+///
+/// returns: Result<DbInfo, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let info = read_db_info(&conn).unwrap();
+/// println!("{} {}", info.journal_mode, info.space.page_size);
+/// ```
+pub fn read_db_info(conn: &Connection) -> Result<DbInfo, Box<dyn Error>> {
+    let text = |name: &str| conn.pragma_query_value(None, name, |r| r.get::<_, String>(0));
+    let number = |name: &str| conn.pragma_query_value(None, name, |r| r.get::<_, i64>(0));
+    let auto_vacuum = match number("auto_vacuum")? {
+        0 => "none",
+        1 => "full",
+        _ => "incremental",
+    };
+    Ok(DbInfo {
+        space: space_usage(conn)?,
+        encoding: text("encoding")?,
+        journal_mode: text("journal_mode")?,
+        auto_vacuum: auto_vacuum.to_string(),
+        user_version: number("user_version")? as i32,
+        application_id: number("application_id")? as i32,
+        schema_version: number("schema_version")?,
+        foreign_keys: number("foreign_keys")? != 0,
+        cipher_version: pragma_rows(conn, "cipher_version")?.into_iter().next(),
+        cipher_provider: pragma_rows(conn, "cipher_provider")?.into_iter().next(),
+        cipher_settings: pragma_rows(conn, "cipher_settings")?,
+    })
+}
+
+/// 修改可写的PRAGMA。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。修改日志模式时不能有其他连接正在读写该数据库。
+/// * `update`: 要修改的PRAGMA及其新值。
+///
+/// returns: Result<(), Box<dyn Error, Global>> 日志模式未能切换时返回错误。
+pub fn apply_pragma(conn: &Connection, update: &PragmaUpdate) -> Result<(), Box<dyn Error>> {
+    match update {
+        PragmaUpdate::UserVersion(v) => conn.pragma_update(None, "user_version", v)?,
+        PragmaUpdate::ApplicationId(v) => conn.pragma_update(None, "application_id", v)?,
+        PragmaUpdate::ForeignKeys(enabled) => conn.pragma_update(None, "foreign_keys", enabled)?,
+        PragmaUpdate::JournalMode(mode) => {
+            let mode = match mode {
+                JournalMode::Delete => "delete",
+                JournalMode::Wal => "wal",
+            };
+            // 切换失败时SQLITE不报错，而是返回原来的日志模式。
+            let actual: String = conn.pragma_update_and_check(None, "journal_mode", mode, |r| r.get(0))?;
+            if !actual.eq_ignore_ascii_case(mode) {
+                return Err(format!("无法将日志模式切换为 {}，当前为 {}", mode, actual).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_db_info() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-info.db");
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "key", "123456").unwrap();
+        conn.execute_batch("create table t (v)").unwrap();
+
+        let info = read_db_info(&conn).unwrap();
+        assert_eq!((info.encoding.as_str(), info.journal_mode.as_str(), info.auto_vacuum.as_str()), ("UTF-8", "delete", "none"));
+        assert_eq!((info.user_version, info.schema_version), (0, 1));
+        assert!(info.space.page_count > 0 && info.space.file_size > 0);
+        assert!(info.cipher_version.is_some() && info.cipher_provider.is_some());
+        assert!(info.cipher_settings.iter().any(|s| s.contains("kdf_iter")), "{:?}", info.cipher_settings);
+
+        apply_pragma(&conn, &PragmaUpdate::UserVersion(42)).unwrap();
+        apply_pragma(&conn, &PragmaUpdate::ApplicationId(-7)).unwrap();
+        apply_pragma(&conn, &PragmaUpdate::JournalMode(JournalMode::Wal)).unwrap();
+        apply_pragma(&conn, &PragmaUpdate::ForeignKeys(false)).unwrap();
+        let info = read_db_info(&conn).unwrap();
+        assert_eq!((info.user_version, info.application_id, info.journal_mode.as_str(), info.foreign_keys), (42, -7, "wal", false));
+
+        let update: PragmaUpdate = serde_json::from_str(r#"{"pragma": "journal_mode", "value": "delete"}"#).unwrap();
+        assert_eq!(update, PragmaUpdate::JournalMode(JournalMode::Delete));
+        apply_pragma(&conn, &update).unwrap();
+
+        let plain = Connection::open_in_memory().unwrap();
+        let info = read_db_info(&plain).unwrap();
+        assert!(info.cipher_settings.is_empty() && info.cipher_provider.is_none());
+        assert!(apply_pragma(&plain, &PragmaUpdate::JournalMode(JournalMode::Wal)).is_err(), "内存数据库不支持WAL");
+    }
+}
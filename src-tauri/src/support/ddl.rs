@@ -0,0 +1,957 @@
+//! 表定义：用结构化的`TableSpec`描述一张表，并在它与`CREATE TABLE`语句之间相互转换。
+//!
+//! 字段类型、默认值、主键、唯一约束和外键通过PRAGMA读取，CHECK约束、排序规则、生成列的表达式和AUTOINCREMENT无法通过PRAGMA取得，从建表语句中解析。
+use std::collections::HashSet;
+use std::error::Error;
+use std::os::raw::c_int;
+
+use rusqlite::{Connection, ffi, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::support::executor::execute_single;
+use crate::support::ident::{is_type_name, quote_ident};
+
+/// 字段定义。
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnSpec {
+    /// 修改表结构时字段的原名，新增的字段为`None`。建表时忽略。
+    pub original_name: Option<String>,
+    pub name: String,
+    /// 声明的类型，可以为空。
+    pub decl_type: String,
+    pub not_null: bool,
+    /// 默认值的SQL表达式，如`'abc'`、`0`或`CURRENT_TIMESTAMP`。
+    pub default_value: Option<String>,
+    pub collation: Option<String>,
+    /// 生成列的SQL表达式。
+    pub generated: Option<String>,
+    /// 生成列是否为STORED，否则为VIRTUAL。
+    pub stored: bool,
+}
+
+/// 外键定义。
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ForeignKeySpec {
+    pub columns: Vec<String>,
+    pub ref_table: String,
+    /// 被引用的字段，为空时引用被引用表的主键。
+    pub ref_columns: Vec<String>,
+    /// 如`CASCADE`、`SET NULL`，为`None`时即`NO ACTION`。
+    pub on_update: Option<String>,
+    pub on_delete: Option<String>,
+}
+
+/// 表定义。字段上的UNIQUE和CHECK约束统一作为表约束保存。
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TableSpec {
+    pub name: String,
+    pub columns: Vec<ColumnSpec>,
+    /// 主键字段，按主键中的顺序排列。
+    pub primary_key: Vec<String>,
+    /// 仅适用于由单个`INTEGER`字段组成的主键。
+    pub autoincrement: bool,
+    /// 每项为一个UNIQUE约束包含的字段。
+    pub unique: Vec<Vec<String>>,
+    /// CHECK约束的SQL表达式。
+    pub checks: Vec<String>,
+    pub foreign_keys: Vec<ForeignKeySpec>,
+    pub without_rowid: bool,
+    pub strict: bool,
+}
+
+/// STRICT表允许的字段类型。
+const STRICT_TYPES: [&str; 6] = ["INT", "INTEGER", "REAL", "TEXT", "BLOB", "ANY"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Quoted,
+    Str,
+    Open,
+    Close,
+    Comma,
+    Symbol,
+}
+
+/// SQL词法单元，`start`和`end`为在原语句中的字节位置。
+#[derive(Clone, Copy, Debug)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+/// 将SQL语句切分为词法单元，跳过空白和注释。
+fn tokenize(sql: &str) -> Vec<Token> {
+    let bytes = sql.as_bytes();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let kind = match c {
+            b' ' | b'\t' | b'\r' | b'\n' => {
+                i += 1;
+                continue;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i = (i + 2).min(bytes.len());
+                continue;
+            }
+            b'\'' | b'"' | b'`' | b'[' => {
+                let close = if c == b'[' { b']' } else { c };
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == close {
+                        // 引号内连续两个引号表示一个引号。
+                        if close != b']' && bytes.get(i + 1) == Some(&close) {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+                tokens.push(Token { kind: if c == b'\'' { TokenKind::Str } else { TokenKind::Quoted }, start, end: i });
+                continue;
+            }
+            b'(' => TokenKind::Open,
+            b')' => TokenKind::Close,
+            b',' => TokenKind::Comma,
+            c if c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80 => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'$' || bytes[i] >= 0x80) {
+                    i += 1;
+                }
+                tokens.push(Token { kind: TokenKind::Word, start, end: i });
+                continue;
+            }
+            _ => TokenKind::Symbol,
+        };
+        i += 1;
+        tokens.push(Token { kind, start, end: i });
+    }
+    tokens
+}
+
+/// 词法单元的文本，带引号的标识符去掉引号。
+fn token_text(sql: &str, token: &Token) -> String {
+    let text = &sql[token.start..token.end];
+    if token.kind != TokenKind::Quoted || text.len() < 2 {
+        return text.to_string();
+    }
+    let inner = &text[1..text.len() - 1];
+    match text.as_bytes()[0] {
+        b'"' => inner.replace("\"\"", "\""),
+        b'`' => inner.replace("``", "`"),
+        _ => inner.to_string(),
+    }
+}
+
+fn is_keyword(sql: &str, token: Option<&Token>, keyword: &str) -> bool {
+    token.map_or(false, |t| t.kind == TokenKind::Word && sql[t.start..t.end].eq_ignore_ascii_case(keyword))
+}
+
+/// 查找与`tokens[open]`配对的右括号的下标。
+fn matching_close(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.kind {
+            TokenKind::Open => depth += 1,
+            TokenKind::Close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 将SQL表达式规范化，用于比较两个表达式是否相同：忽略空白、标识符的引号和大小写。
+pub fn normalize_expr(expr: &str) -> String {
+    tokenize(expr).iter().map(|t| match t.kind {
+        TokenKind::Word | TokenKind::Quoted => token_text(expr, t).to_lowercase(),
+        _ => expr[t.start..t.end].to_string(),
+    }).collect::<Vec<_>>().join(" ")
+}
+
+/// 检查拼接进SQL语句的表达式片段，如默认值、CHECK约束和生成列的表达式：括号须配对，字符串和带引号的标识符须闭合，
+/// 且不能包含分号或注释，以免改变所在语句的结构。
+pub(crate) fn is_expr_fragment(expr: &str) -> bool {
+    let mut depth = 0;
+    let mut pos = 0;
+    for token in tokenize(expr) {
+        // 词法单元之间只能有空白，否则是被跳过的注释。
+        if !expr[pos..token.start].trim().is_empty() {
+            return false;
+        }
+        pos = token.end;
+        let text = &expr[token.start..token.end];
+        match token.kind {
+            TokenKind::Open => depth += 1,
+            TokenKind::Close if depth == 0 => return false,
+            TokenKind::Close => depth -= 1,
+            TokenKind::Symbol if text == ";" => return false,
+            // 闭合的引号内，引号字符总是成对出现。
+            TokenKind::Str | TokenKind::Quoted => {
+                let closed = match text.as_bytes()[0] {
+                    b'[' => text.len() > 1 && text.ends_with(']'),
+                    quote => text.bytes().filter(|b| *b == quote).count() % 2 == 0,
+                };
+                if !closed {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0 && expr[pos..].trim().is_empty()
+}
+
+/// 表定义中`TableSpec`无法表示的子句：约束名、`ON CONFLICT`、`DEFERRABLE`、`MATCH`等，按出现顺序返回对应的关键字。
+pub(crate) fn unsupported_table_clauses(sql: &str) -> Vec<String> {
+    let mut found: Vec<String> = vec![];
+    for token in tokenize(sql).iter().filter(|t| t.kind == TokenKind::Word) {
+        let word = sql[token.start..token.end].to_uppercase();
+        if ["CONSTRAINT", "CONFLICT", "DEFERRABLE", "MATCH"].contains(&word.as_str()) && !found.contains(&word) {
+            found.push(word);
+        }
+    }
+    found
+}
+
+/// SQL语句中的关键字和标识符，按出现顺序排列，带引号的标识符去掉引号，不含字符串字面量和注释。
+pub(crate) fn sql_words(sql: &str) -> Vec<String> {
+    tokenize(sql).iter().filter(|t| t.kind == TokenKind::Word || t.kind == TokenKind::Quoted).map(|t| token_text(sql, t)).collect()
+}
+
+/// `CREATE`语句的头部。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CreateHeader {
+    pub temp: bool,
+    /// 小写的对象类型，如`view`、`trigger`。
+    pub obj_type: String,
+    /// 对象名，不含模式名。
+    pub name: String,
+    /// 对象名之后第一个词法单元的下标。
+    next: usize,
+}
+
+fn parse_create_header(sql: &str, tokens: &[Token]) -> Option<CreateHeader> {
+    if !is_keyword(sql, tokens.first(), "CREATE") {
+        return None;
+    }
+    let mut i = 1;
+    let temp = is_keyword(sql, tokens.get(i), "TEMP") || is_keyword(sql, tokens.get(i), "TEMPORARY");
+    if temp {
+        i += 1;
+    }
+    if is_keyword(sql, tokens.get(i), "UNIQUE") || is_keyword(sql, tokens.get(i), "VIRTUAL") {
+        i += 1;
+    }
+    let obj_type = token_text(sql, tokens.get(i)?).to_lowercase();
+    i += 1;
+    if is_keyword(sql, tokens.get(i), "IF") {
+        i += 3;
+    }
+    // 带模式名时取最后一段。
+    while tokens.get(i + 1).map_or(false, |t| t.kind == TokenKind::Symbol && &sql[t.start..t.end] == ".") {
+        i += 2;
+    }
+    let name = token_text(sql, tokens.get(i)?);
+    Some(CreateHeader { temp, obj_type, name, next: i + 1 })
+}
+
+/// 解析`CREATE`语句的对象类型和名称，不是`CREATE`语句时返回`None`。
+pub(crate) fn create_header(sql: &str) -> Option<CreateHeader> {
+    parse_create_header(sql, &tokenize(sql))
+}
+
+/// 触发器的触发时机、事件和`UPDATE OF`的字段。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TriggerHeader {
+    /// `BEFORE`、`AFTER`或`INSTEAD OF`。
+    pub timing: String,
+    /// `INSERT`、`UPDATE`或`DELETE`。
+    pub event: String,
+    pub columns: Vec<String>,
+}
+
+/// 解析`CREATE TRIGGER`语句的头部，未声明触发时机时为`AFTER`。
+pub(crate) fn trigger_header(sql: &str) -> Option<TriggerHeader> {
+    let tokens = tokenize(sql);
+    let header = parse_create_header(sql, &tokens).filter(|h| h.obj_type == "trigger")?;
+    let mut trigger = TriggerHeader { timing: "AFTER".to_string(), event: String::new(), columns: vec![] };
+    let mut in_columns = false;
+    for token in tokens[header.next..].iter() {
+        let word = Some(token);
+        if is_keyword(sql, word, "ON") {
+            break;
+        } else if in_columns {
+            if token.kind != TokenKind::Comma {
+                trigger.columns.push(token_text(sql, token));
+            }
+        } else if is_keyword(sql, word, "BEFORE") || is_keyword(sql, word, "AFTER") {
+            trigger.timing = sql[token.start..token.end].to_uppercase();
+        } else if is_keyword(sql, word, "INSTEAD") {
+            trigger.timing = "INSTEAD OF".to_string();
+        } else if ["INSERT", "UPDATE", "DELETE"].iter().any(|k| is_keyword(sql, word, k)) {
+            trigger.event = sql[token.start..token.end].to_uppercase();
+        } else if is_keyword(sql, word, "OF") && trigger.event == "UPDATE" {
+            in_columns = true;
+        }
+    }
+    Some(trigger)
+}
+
+/// 从建表语句中解析出的、无法通过PRAGMA取得的定义。
+#[derive(Default)]
+struct ParsedTable {
+    checks: Vec<String>,
+    /// 字段名、排序规则、生成列表达式和是否为STORED。
+    columns: Vec<(String, Option<String>, Option<String>, bool)>,
+    autoincrement: bool,
+}
+
+fn parse_create_table(sql: &str) -> ParsedTable {
+    let tokens = tokenize(sql);
+    let mut parsed = ParsedTable::default();
+    let open = match tokens.iter().position(|t| t.kind == TokenKind::Open) {
+        Some(open) => open,
+        None => return parsed,
+    };
+    let close = matching_close(&tokens, open).unwrap_or(tokens.len());
+
+    // 按第一层的逗号切分字段定义和表约束。
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut part_start = open + 1;
+    for (i, token) in tokens.iter().enumerate().take(close).skip(open + 1) {
+        match token.kind {
+            TokenKind::Open => depth += 1,
+            TokenKind::Close => depth -= 1,
+            TokenKind::Comma if depth == 0 => {
+                parts.push(&tokens[part_start..i]);
+                part_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&tokens[part_start..close]);
+
+    for part in parts.into_iter().filter(|p| !p.is_empty()) {
+        let is_constraint = ["CONSTRAINT", "PRIMARY", "UNIQUE", "CHECK", "FOREIGN"].iter().any(|k| is_keyword(sql, part.first(), k));
+        let mut collation = None;
+        let mut generated = None;
+        let mut stored = false;
+        let mut i = if is_constraint { 0 } else { 1 };
+        while i < part.len() {
+            let token = part.get(i);
+            let next = part.get(i + 1);
+            let paren_expr = |i: usize| -> Option<(String, usize)> {
+                let close = matching_close(part, i)?;
+                Some((sql[part[i].end..part[close].start].trim().to_string(), close))
+            };
+            if is_keyword(sql, token, "CHECK") && next.map_or(false, |t| t.kind == TokenKind::Open) {
+                if let Some((expr, end)) = paren_expr(i + 1) {
+                    parsed.checks.push(expr);
+                    i = end;
+                }
+            } else if is_keyword(sql, token, "AS") && next.map_or(false, |t| t.kind == TokenKind::Open) && !is_constraint {
+                if let Some((expr, end)) = paren_expr(i + 1) {
+                    generated = Some(expr);
+                    stored = is_keyword(sql, part.get(end + 1), "STORED");
+                    i = end;
+                }
+            } else if is_keyword(sql, token, "COLLATE") && next.is_some() {
+                collation = next.map(|t| token_text(sql, t));
+                i += 1;
+            } else if is_keyword(sql, token, "AUTOINCREMENT") {
+                parsed.autoincrement = true;
+            } else if token.map_or(false, |t| t.kind == TokenKind::Open) {
+                // 跳过默认值、外键等括号内的内容。
+                i = matching_close(part, i).unwrap_or(part.len());
+            }
+            i += 1;
+        }
+        if !is_constraint {
+            parsed.columns.push((token_text(sql, &part[0]), collation, generated, stored));
+        }
+    }
+    parsed
+}
+
+/// 读取一张普通表的定义。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `name`: 表名，不区分大小写。
+///
+/// returns: Result<TableSpec, Box<dyn Error, Global>> 每个字段的`original_name`均为当前的字段名。表不存在或不是普通表时返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let spec = read_table_spec(&conn, "users").unwrap();
+/// println!("{}", create_table_sql(&spec).unwrap());
+/// ```
+pub fn read_table_spec(conn: &Connection, name: &str) -> Result<TableSpec, Box<dyn Error>> {
+    let listed: Option<(String, String, bool, bool)> = conn.query_row(
+        "select name, type, wr, strict from pragma_table_list where schema = 'main' and name = ?1 collate nocase",
+        [name], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))).optional()?;
+    let (name, obj_type, without_rowid, strict) = listed.ok_or_else(|| format!("表 {} 不存在", name))?;
+    if obj_type != "table" || name.to_lowercase().starts_with("sqlite_") {
+        return Err(format!("{} 不是普通表", name).into());
+    }
+    let sql: String = conn.query_row("select sql from sqlite_master where type = 'table' and name = ?1", [&name], |r| r.get(0))?;
+    let parsed = parse_create_table(&sql);
+
+    let mut spec = TableSpec { name: name.clone(), autoincrement: parsed.autoincrement, checks: parsed.checks, without_rowid, strict, ..TableSpec::default() };
+    let mut primary_key = vec![];
+    let mut stmt = conn.prepare("select name, type, \"notnull\", dflt_value, pk from pragma_table_xinfo(?1) where hidden != 1 order by cid")?;
+    let mut rows = stmt.query([&name])?;
+    while let Some(r) = rows.next()? {
+        let column_name: String = r.get(0)?;
+        let pk: i64 = r.get(4)?;
+        if pk > 0 {
+            primary_key.push((pk, column_name.clone()));
+        }
+        let mut column = ColumnSpec {
+            original_name: Some(column_name.clone()),
+            name: column_name,
+            decl_type: r.get(1)?,
+            not_null: r.get(2)?,
+            default_value: r.get(3)?,
+            ..ColumnSpec::default()
+        };
+        if let Some((_, collation, generated, stored)) = parsed.columns.iter().find(|c| c.0.eq_ignore_ascii_case(&column.name)) {
+            column.collation = collation.clone();
+            column.generated = generated.clone();
+            column.stored = *stored;
+        }
+        spec.columns.push(column);
+    }
+    primary_key.sort();
+    spec.primary_key = primary_key.into_iter().map(|(_, name)| name).collect();
+
+    let mut stmt = conn.prepare("select name from pragma_index_list(?1) where origin = 'u' order by seq desc")?;
+    let indexes = stmt.query_map([&name], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut stmt = conn.prepare("select name from pragma_index_xinfo(?1) where key = 1 order by seqno")?;
+    for index in indexes {
+        let columns = stmt.query_map([&index], |r| r.get::<_, Option<String>>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+        spec.unique.push(columns.into_iter().flatten().collect());
+    }
+
+    let mut stmt = conn.prepare("select id, \"table\", \"from\", \"to\", on_update, on_delete from pragma_foreign_key_list(?1) order by id, seq")?;
+    let mut rows = stmt.query([&name])?;
+    let mut last_id = None;
+    let action = |action: String| if action.eq_ignore_ascii_case("NO ACTION") { None } else { Some(action) };
+    while let Some(r) = rows.next()? {
+        let id: i64 = r.get(0)?;
+        if last_id != Some(id) {
+            last_id = Some(id);
+            spec.foreign_keys.push(ForeignKeySpec { ref_table: r.get(1)?, on_update: action(r.get(4)?), on_delete: action(r.get(5)?), ..ForeignKeySpec::default() });
+        }
+        if let Some(fk) = spec.foreign_keys.last_mut() {
+            fk.columns.push(r.get(2)?);
+            if let Some(to) = r.get::<_, Option<String>>(3)? {
+                fk.ref_columns.push(to);
+            }
+        }
+    }
+    Ok(spec)
+}
+
+/// 排序规则通常是简单的名称，无需加引号。
+pub(crate) fn collation_name(name: &str) -> String {
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        name.to_string()
+    } else {
+        quote_ident(name)
+    }
+}
+
+fn quote_list(names: &[String]) -> String {
+    names.iter().map(|n| quote_ident(n)).collect::<Vec<_>>().join(", ")
+}
+
+/// 外键动作。
+const FK_ACTIONS: [&str; 5] = ["CASCADE", "SET NULL", "SET DEFAULT", "RESTRICT", "NO ACTION"];
+
+/// 规范化外键的`ON UPDATE`或`ON DELETE`动作，不在`FK_ACTIONS`中时返回错误，为空时返回`None`。
+fn fk_action(action: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let action = action.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase();
+    if action.is_empty() {
+        Ok(None)
+    } else if FK_ACTIONS.contains(&action.as_str()) {
+        Ok(Some(action))
+    } else {
+        Err(format!("外键动作 {} 无效，须为 {} 之一", action, FK_ACTIONS.join("、")).into())
+    }
+}
+
+/// 检查表定义中的名称是否完整、是否重复，以及约束引用的字段是否存在。
+fn validate_table_spec(spec: &TableSpec) -> Result<(), Box<dyn Error>> {
+    if spec.name.trim().is_empty() {
+        return Err("表名不能为空".into());
+    }
+    if spec.columns.is_empty() {
+        return Err("至少需要一个字段".into());
+    }
+    let mut names = HashSet::new();
+    for column in spec.columns.iter() {
+        if column.name.trim().is_empty() {
+            return Err("字段名不能为空".into());
+        }
+        if !names.insert(column.name.to_lowercase()) {
+            return Err(format!("字段 {} 重复", column.name).into());
+        }
+    }
+    let check_columns = |columns: &[String], what: &str| -> Result<(), Box<dyn Error>> {
+        if columns.is_empty() {
+            return Err(format!("{}至少需要一个字段", what).into());
+        }
+        match columns.iter().find(|c| !names.contains(&c.to_lowercase())) {
+            Some(c) => Err(format!("{}中的字段 {} 不存在", what, c).into()),
+            None => Ok(()),
+        }
+    };
+    if !spec.primary_key.is_empty() {
+        check_columns(&spec.primary_key, "主键")?;
+    }
+    for unique in spec.unique.iter() {
+        check_columns(unique, "UNIQUE约束")?;
+    }
+    for fk in spec.foreign_keys.iter() {
+        check_columns(&fk.columns, "外键")?;
+        if fk.ref_table.trim().is_empty() {
+            return Err("外键引用的表不能为空".into());
+        }
+        if !fk.ref_columns.is_empty() && fk.ref_columns.len() != fk.columns.len() {
+            return Err(format!("外键引用 {} 的字段数与外键字段数不一致", fk.ref_table).into());
+        }
+    }
+    if let Some(column) = spec.columns.iter().find(|c| !c.decl_type.trim().is_empty() && !is_type_name(c.decl_type.trim())) {
+        return Err(format!("字段 {} 的类型 {} 无效", column.name, column.decl_type).into());
+    }
+    for column in spec.columns.iter() {
+        for (what, expr) in [("默认值", &column.default_value), ("生成列表达式", &column.generated)] {
+            if let Some(expr) = expr.as_ref().filter(|e| !is_expr_fragment(e)) {
+                return Err(format!("字段 {} 的{} {} 无效", column.name, what, expr).into());
+            }
+        }
+    }
+    if let Some(check) = spec.checks.iter().find(|c| !is_expr_fragment(c)) {
+        return Err(format!("CHECK约束 {} 无效", check).into());
+    }
+    for fk in spec.foreign_keys.iter() {
+        for action in [&fk.on_update, &fk.on_delete].iter().filter_map(|a| a.as_ref()) {
+            fk_action(action)?;
+        }
+    }
+    if spec.without_rowid && spec.primary_key.is_empty() {
+        return Err("WITHOUT ROWID表必须有主键".into());
+    }
+    for column in spec.columns.iter().filter(|c| c.generated.is_some()) {
+        if column.default_value.is_some() || spec.primary_key.iter().any(|pk| pk.eq_ignore_ascii_case(&column.name)) {
+            return Err(format!("生成列 {} 不能有默认值，也不能作为主键", column.name).into());
+        }
+    }
+    if spec.strict {
+        if let Some(column) = spec.columns.iter().find(|c| !STRICT_TYPES.contains(&c.decl_type.trim().to_uppercase().as_str())) {
+            return Err(format!("STRICT表的字段 {} 的类型须为 {} 之一", column.name, STRICT_TYPES.join("、")).into());
+        }
+    }
+    if spec.autoincrement {
+        let integer_pk = spec.primary_key.len() == 1 && spec.columns.iter()
+            .any(|c| c.name.eq_ignore_ascii_case(&spec.primary_key[0]) && c.decl_type.trim().eq_ignore_ascii_case("INTEGER"));
+        if !integer_pk || spec.without_rowid {
+            return Err("AUTOINCREMENT只能用于由单个INTEGER字段组成的主键，且不能用于WITHOUT ROWID表".into());
+        }
+    }
+    Ok(())
+}
+
+/// 生成单个字段的定义，用于建表和`ALTER TABLE ADD COLUMN`。
+///
+/// # Arguments
+///
+/// * `column`: 字段定义。
+/// * `primary_key`: 是否在字段上声明主键，以及是否AUTOINCREMENT。由多个字段组成的主键作为表约束声明。
+pub fn column_sql(column: &ColumnSpec, primary_key: Option<bool>) -> String {
+    let mut sql = quote_ident(&column.name);
+    if !column.decl_type.trim().is_empty() {
+        sql.push(' ');
+        sql.push_str(column.decl_type.trim());
+    }
+    if let Some(autoincrement) = primary_key {
+        sql.push_str(" PRIMARY KEY");
+        if autoincrement {
+            sql.push_str(" AUTOINCREMENT");
+        }
+    }
+    if column.not_null {
+        sql.push_str(" NOT NULL");
+    }
+    // PRAGMA返回的默认值不带外层括号，统一加上括号，以支持任意表达式。
+    if let Some(default_value) = column.default_value.as_ref().filter(|v| !v.trim().is_empty()) {
+        sql.push_str(&format!(" DEFAULT ({})", default_value.trim()));
+    }
+    if let Some(collation) = column.collation.as_ref().filter(|c| !c.trim().is_empty()) {
+        sql.push_str(&format!(" COLLATE {}", collation_name(collation.trim())));
+    }
+    if let Some(generated) = column.generated.as_ref().filter(|g| !g.trim().is_empty()) {
+        sql.push_str(&format!(" GENERATED ALWAYS AS ({}) {}", generated.trim(), if column.stored { "STORED" } else { "VIRTUAL" }));
+    }
+    sql
+}
+
+/// 根据表定义生成`CREATE TABLE`语句。
+///
+/// # Arguments
+///
+/// * `spec`: 表定义，忽略字段的`original_name`。
+///
+/// returns: Result<String, Box<dyn Error, Global>> 表定义不完整、约束引用了不存在的字段，或类型、表达式、外键动作无效时返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let spec = TableSpec {
+///     name: "users".to_string(),
+///     columns: vec![ColumnSpec { name: "id".to_string(), decl_type: "INTEGER".to_string(), ..ColumnSpec::default() }],
+///     primary_key: vec!["id".to_string()],
+///     ..TableSpec::default()
+/// };
+/// assert_eq!(create_table_sql(&spec).unwrap(), "CREATE TABLE \"users\" (\n    \"id\" INTEGER PRIMARY KEY\n)");
+/// ```
+pub fn create_table_sql(spec: &TableSpec) -> Result<String, Box<dyn Error>> {
+    validate_table_spec(spec)?;
+    let single_pk = if spec.primary_key.len() == 1 { spec.primary_key.first() } else { None };
+    let mut lines: Vec<String> = spec.columns.iter().map(|c| {
+        let primary_key = single_pk.filter(|pk| pk.eq_ignore_ascii_case(&c.name)).map(|_| spec.autoincrement);
+        column_sql(c, primary_key)
+    }).collect();
+    if spec.primary_key.len() > 1 {
+        lines.push(format!("PRIMARY KEY ({})", quote_list(&spec.primary_key)));
+    }
+    for unique in spec.unique.iter() {
+        lines.push(format!("UNIQUE ({})", quote_list(unique)));
+    }
+    for check in spec.checks.iter().filter(|c| !c.trim().is_empty()) {
+        lines.push(format!("CHECK ({})", check.trim()));
+    }
+    for fk in spec.foreign_keys.iter() {
+        let mut line = format!("FOREIGN KEY ({}) REFERENCES {}", quote_list(&fk.columns), quote_ident(&fk.ref_table));
+        if !fk.ref_columns.is_empty() {
+            line.push_str(&format!(" ({})", quote_list(&fk.ref_columns)));
+        }
+        if let Some(action) = fk.on_update.as_deref().map(fk_action).transpose()?.flatten() {
+            line.push_str(&format!(" ON UPDATE {}", action));
+        }
+        if let Some(action) = fk.on_delete.as_deref().map(fk_action).transpose()?.flatten() {
+            line.push_str(&format!(" ON DELETE {}", action));
+        }
+        lines.push(line);
+    }
+    let mut options = vec![];
+    if spec.without_rowid {
+        options.push("WITHOUT ROWID");
+    }
+    if spec.strict {
+        options.push("STRICT");
+    }
+    let mut sql = format!("CREATE TABLE {} (\n    {}\n)", quote_ident(&spec.name), lines.join(",\n    "));
+    if !options.is_empty() {
+        sql.push(' ');
+        sql.push_str(&options.join(", "));
+    }
+    Ok(sql)
+}
+
+/// 双引号字符串字面量在DDL和DML中的开关状态。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct DqsConfig {
+    ddl: bool,
+    dml: bool,
+}
+
+/// 设置一项布尔类型的连接配置，`value`为-1时只查询不修改，返回设置后的值。
+fn db_config_flag(conn: &Connection, op: c_int, value: c_int) -> Result<bool, Box<dyn Error>> {
+    let mut current: c_int = 0;
+    let rc = unsafe { ffi::sqlite3_db_config(conn.handle(), op, value, &mut current as *mut c_int) };
+    if rc != ffi::SQLITE_OK {
+        return Err(format!("设置连接配置 {} 失败，错误码 {}", op, rc).into());
+    }
+    Ok(current != 0)
+}
+
+/// 开启或关闭双引号字符串字面量。关闭后，SQL中引用了不存在的字段`"name"`时会报错，而不是被当作字符串`'name'`。
+///
+/// returns: Result<DqsConfig, Box<dyn Error, Global>> 返回修改前的状态，用完后以`restore_double_quoted_strings`恢复。
+pub(crate) fn set_double_quoted_strings(conn: &Connection, enabled: bool) -> Result<DqsConfig, Box<dyn Error>> {
+    let previous = DqsConfig {
+        ddl: db_config_flag(conn, ffi::SQLITE_DBCONFIG_DQS_DDL, -1)?,
+        dml: db_config_flag(conn, ffi::SQLITE_DBCONFIG_DQS_DML, -1)?,
+    };
+    restore_double_quoted_strings(conn, DqsConfig { ddl: enabled, dml: enabled })?;
+    Ok(previous)
+}
+
+/// 将双引号字符串字面量恢复为`set_double_quoted_strings`修改前的状态。
+pub(crate) fn restore_double_quoted_strings(conn: &Connection, config: DqsConfig) -> Result<(), Box<dyn Error>> {
+    db_config_flag(conn, ffi::SQLITE_DBCONFIG_DQS_DDL, config.ddl as c_int)?;
+    db_config_flag(conn, ffi::SQLITE_DBCONFIG_DQS_DML, config.dml as c_int)?;
+    Ok(())
+}
+
+/// 根据表定义生成并校验`CREATE TABLE`语句，可选择直接建表。
+///
+/// 除检查表定义本身外，还在事务中试建该表，以发现类型、默认值、CHECK约束等表达式中的错误，并检查外键引用的表是否存在。
+/// 生成的语句只作为一条语句编译执行，其后还有其它语句时报错。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接，不能处于事务中。
+/// * `spec`: 表定义。
+/// * `execute`: 为`true`时提交建表，否则试建后回滚。
+///
+/// returns: Result<String, Box<dyn Error, Global>> 返回生成的`CREATE TABLE`语句。
+///
+/// # Examples
+///
+/// ```
+/// let mut conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let sql = create_table(&mut conn, &spec, false).unwrap();
+/// println!("{}", sql);
+/// ```
+pub fn create_table(conn: &mut Connection, spec: &TableSpec, execute: bool) -> Result<String, Box<dyn Error>> {
+    let sql = create_table_sql(spec)?;
+    let exists: i64 = conn.query_row("select count(*) from sqlite_master where name = ?1 collate nocase", [&spec.name], |r| r.get(0))?;
+    if exists > 0 {
+        return Err(format!("{} 已存在", spec.name).into());
+    }
+    for fk in spec.foreign_keys.iter().filter(|fk| !fk.ref_table.eq_ignore_ascii_case(&spec.name)) {
+        let found: i64 = conn.query_row("select count(*) from sqlite_master where type = 'table' and name = ?1 collate nocase", [&fk.ref_table], |r| r.get(0))?;
+        if found == 0 {
+            return Err(format!("外键引用的表 {} 不存在", fk.ref_table).into());
+        }
+    }
+
+    let dqs = set_double_quoted_strings(conn, false)?;
+    let result = conn.transaction().map_err(|e| e.into()).and_then(|tx| -> Result<(), Box<dyn Error>> {
+        execute_single(&tx, &sql)?;
+        if execute {
+            tx.commit()?;
+        }
+        Ok(())
+    });
+    let restored = restore_double_quoted_strings(conn, dqs);
+    result?;
+    restored?;
+    Ok(sql)
+}
+
+/// 将表定义规范化，用于比较两个定义是否等价：忽略字段原名、类型和约束动作的大小写、表达式中的空白和引号，以及UNIQUE约束的先后顺序。
+pub fn normalize_table_spec(spec: &TableSpec) -> TableSpec {
+    let expr = |e: &Option<String>| e.as_ref().map(|e| normalize_expr(e)).filter(|e| !e.is_empty());
+    let upper = |s: &Option<String>| s.as_ref().map(|s| s.trim().to_uppercase()).filter(|s| !s.is_empty() && s != "NO ACTION");
+    let mut unique: Vec<Vec<String>> = spec.unique.iter().map(|u| u.iter().map(|c| c.to_lowercase()).collect()).collect();
+    unique.sort();
+    TableSpec {
+        name: spec.name.clone(),
+        columns: spec.columns.iter().map(|c| ColumnSpec {
+            original_name: None,
+            name: c.name.clone(),
+            decl_type: c.decl_type.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase(),
+            not_null: c.not_null,
+            default_value: expr(&c.default_value),
+            collation: upper(&c.collation),
+            stored: c.generated.is_some() && c.stored,
+            generated: expr(&c.generated),
+        }).collect(),
+        primary_key: spec.primary_key.iter().map(|c| c.to_lowercase()).collect(),
+        autoincrement: spec.autoincrement,
+        unique,
+        checks: spec.checks.iter().map(|c| normalize_expr(c)).filter(|c| !c.is_empty()).collect(),
+        foreign_keys: spec.foreign_keys.iter().map(|fk| ForeignKeySpec {
+            columns: fk.columns.iter().map(|c| c.to_lowercase()).collect(),
+            ref_table: fk.ref_table.to_lowercase(),
+            ref_columns: fk.ref_columns.iter().map(|c| c.to_lowercase()).collect(),
+            on_update: upper(&fk.on_update),
+            on_delete: upper(&fk.on_delete),
+        }).collect(),
+        without_rowid: spec.without_rowid,
+        strict: spec.strict,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_spec_round_trip() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key autoincrement, email text);
+            create table \"my \"\"items\"\"\" (
+                -- 注释中的 check (x) 不是约束
+                order_id integer not null,
+                line int,
+                name varchar(20) default 'a,b' collate nocase check (length(name) > 0),
+                price real default (1 + 1),
+                total real generated always as (price * 2) stored,
+                user_id integer references users on delete cascade,
+                constraint pk primary key (order_id, line),
+                unique (name, line),
+                check (price >= 0 and (line > 0))
+            ) without rowid;
+        ").unwrap();
+
+        let spec = read_table_spec(&conn, "MY \"ITEMS\"").unwrap();
+        assert_eq!(spec.name, "my \"items\"");
+        assert_eq!(spec.primary_key, vec!["order_id", "line"]);
+        assert_eq!(spec.checks, vec!["length(name) > 0", "price >= 0 and (line > 0)"]);
+        assert_eq!(spec.unique, vec![vec!["name", "line"]]);
+        assert!(spec.without_rowid && !spec.autoincrement);
+        let name = &spec.columns[2];
+        assert_eq!((name.decl_type.as_str(), name.default_value.as_deref(), name.collation.as_deref()), ("varchar(20)", Some("'a,b'"), Some("nocase")));
+        assert_eq!(spec.columns[3].default_value.as_deref(), Some("1 + 1"));
+        let total = &spec.columns[4];
+        assert_eq!((total.generated.as_deref(), total.stored), (Some("price * 2"), true));
+        assert_eq!(spec.foreign_keys, vec![ForeignKeySpec {
+            columns: vec!["user_id".to_string()],
+            ref_table: "users".to_string(),
+            ref_columns: vec![],
+            on_update: None,
+            on_delete: Some("CASCADE".to_string()),
+        }]);
+        assert!(read_table_spec(&conn, "users").unwrap().autoincrement);
+        assert!(read_table_spec(&conn, "sqlite_sequence").is_err());
+
+        // 按生成的语句重建后，读取到的定义应与原定义等价。
+        let sql = create_table_sql(&TableSpec { name: "copy".to_string(), ..spec.clone() }).unwrap();
+        conn.execute_batch(&sql).unwrap();
+        let copy = read_table_spec(&conn, "copy").unwrap();
+        assert_eq!(normalize_table_spec(&copy), normalize_table_spec(&TableSpec { name: "copy".to_string(), ..spec }), "{}", sql);
+
+        assert_eq!(normalize_expr("\"Price\"  >=0"), normalize_expr("price >= 0"));
+        let header = create_header("create temp view if not exists main.\"my view\" as select 1").unwrap();
+        assert_eq!((header.temp, header.obj_type.as_str(), header.name.as_str()), (true, "view", "my view"));
+        assert_eq!(trigger_header("CREATE TRIGGER \"before\" instead of update of a, \"b c\" on v begin select 1; end"), Some(TriggerHeader {
+            timing: "INSTEAD OF".to_string(),
+            event: "UPDATE".to_string(),
+            columns: vec!["a".to_string(), "b c".to_string()],
+        }));
+        assert_eq!(trigger_header("create trigger t delete on x begin select 1; end").unwrap().timing, "AFTER");
+        let mut invalid = read_table_spec(&conn, "copy").unwrap();
+        invalid.unique.push(vec!["missing".to_string()]);
+        assert!(create_table_sql(&invalid).is_err());
+        invalid.unique.pop();
+        invalid.primary_key.clear();
+        assert!(create_table_sql(&invalid).is_err(), "WITHOUT ROWID表必须有主键");
+    }
+
+    #[test]
+    fn test_create_table() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("create table users (id integer primary key)").unwrap();
+        let column = |name: &str, decl_type: &str| ColumnSpec { name: name.to_string(), decl_type: decl_type.to_string(), ..ColumnSpec::default() };
+        let mut spec = TableSpec {
+            name: "orders".to_string(),
+            columns: vec![
+                column("id", "INTEGER"),
+                ColumnSpec { not_null: true, ..column("user_id", "INTEGER") },
+                ColumnSpec { default_value: Some("0".to_string()), ..column("amount", "REAL") },
+                ColumnSpec { generated: Some("amount * 2".to_string()), ..column("doubled", "REAL") },
+            ],
+            primary_key: vec!["id".to_string()],
+            autoincrement: true,
+            checks: vec!["amount >= 0".to_string()],
+            foreign_keys: vec![ForeignKeySpec { columns: vec!["user_id".to_string()], ref_table: "users".to_string(), on_delete: Some("cascade".to_string()), ..ForeignKeySpec::default() }],
+            strict: true,
+            ..TableSpec::default()
+        };
+
+        let sql = create_table(&mut conn, &spec, false).unwrap();
+        assert_eq!(sql, "CREATE TABLE \"orders\" (
+    \"id\" INTEGER PRIMARY KEY AUTOINCREMENT,
+    \"user_id\" INTEGER NOT NULL,
+    \"amount\" REAL DEFAULT (0),
+    \"doubled\" REAL GENERATED ALWAYS AS (amount * 2) VIRTUAL,
+    CHECK (amount >= 0),
+    FOREIGN KEY (\"user_id\") REFERENCES \"users\" ON DELETE CASCADE
+) STRICT");
+        assert!(read_table_spec(&conn, "orders").is_err(), "未执行时不应建表");
+
+        let invalid = |spec: TableSpec| create_table(&mut Connection::open_in_memory().unwrap(), &spec, false).unwrap_err().to_string();
+        let mut strict = spec.clone();
+        strict.columns[2].decl_type = "DECIMAL(10, 2)".to_string();
+        assert!(invalid(strict).contains("STRICT"));
+        let mut autoincrement = spec.clone();
+        autoincrement.without_rowid = true;
+        assert!(invalid(autoincrement).contains("AUTOINCREMENT"));
+        assert!(invalid(spec.clone()).contains("users"), "外键引用的表不存在");
+        let mut bad_check = spec.clone();
+        bad_check.checks = vec!["\"missing\" > 0".to_string()];
+        assert!(create_table(&mut conn, &bad_check, false).is_err(), "CHECK约束引用了不存在的字段");
+
+        // 校验结束后恢复连接原有的双引号字符串设置。
+        let dqs = DqsConfig { ddl: false, dml: true };
+        restore_double_quoted_strings(&conn, dqs).unwrap();
+        create_table(&mut conn, &spec, false).unwrap();
+        assert_eq!(set_double_quoted_strings(&conn, true).unwrap(), dqs);
+
+        // 拼接进语句的类型、表达式和外键动作不能改变语句结构。
+        let mut injected = spec.clone();
+        injected.columns[0].decl_type = "INTEGER); DROP TABLE users; --".to_string();
+        assert!(create_table_sql(&injected).unwrap_err().to_string().contains("类型"));
+        let mut injected = spec.clone();
+        injected.foreign_keys[0].on_update = Some("CASCADE; DROP TABLE users".to_string());
+        assert!(create_table_sql(&injected).unwrap_err().to_string().contains("外键动作"));
+        injected.foreign_keys[0].on_update = Some(" set  null ".to_string());
+        assert!(create_table_sql(&injected).unwrap().contains("ON UPDATE SET NULL"));
+        for expr in ["0); DROP TABLE users; --", "0) , \"x\" TEXT, CHECK (1", "'a", "0 -- )", "0 /* ) */", "[x"] {
+            let mut injected = spec.clone();
+            injected.columns[2].default_value = Some(expr.to_string());
+            assert!(create_table_sql(&injected).is_err(), "{}", expr);
+            injected.columns[2].default_value = None;
+            injected.checks = vec![expr.to_string()];
+            assert!(create_table_sql(&injected).is_err(), "{}", expr);
+        }
+        let mut injected = spec.clone();
+        injected.checks = vec!["1); DROP TABLE users; --".to_string()];
+        assert!(create_table(&mut conn, &injected, true).is_err());
+        assert!(read_table_spec(&conn, "users").is_ok());
+        for expr in ["'a)''b'", "(1 + \"x;\")", "[a b]", "'--'"] {
+            assert!(is_expr_fragment(expr), "{}", expr);
+        }
+
+        create_table(&mut conn, &spec, true).unwrap();
+        let created = read_table_spec(&conn, "orders").unwrap();
+        assert_eq!(normalize_table_spec(&created), normalize_table_spec(&spec));
+        assert!(create_table(&mut conn, &spec, true).unwrap_err().to_string().contains("已存在"));
+        spec.name = "self_ref".to_string();
+        spec.foreign_keys[0].ref_table = "self_ref".to_string();
+        create_table(&mut conn, &spec, false).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+//! 以SQL语句导出和恢复数据库，相当于sqlite3的`.dump`和`.read`。
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+use serde::{Deserialize, Serialize};
+
+use crate::support::ddl::sql_words;
+use crate::support::executor::StatementReader;
+use crate::support::ident::quote_ident;
+
+/// 导出选项。
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DumpOptions {
+    /// 要导出的表、视图、索引或触发器名称，为`None`时导出全部对象。选中表时一并导出其索引和触发器。
+    pub objects: Option<Vec<String>>,
+    /// 只导出结构，不导出数据。
+    pub schema_only: bool,
+}
+
+/// 导出结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DumpReport {
+    /// 导出的对象数。
+    pub objects: usize,
+    /// 导出的数据行数。
+    pub rows: u64,
+}
+
+/// 恢复结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RestoreReport {
+    /// 执行的语句数，不含脚本中被忽略的事务语句。
+    pub statements: u64,
+}
+
+/// `sqlite_master`中的一个对象。
+#[derive(Clone, Debug)]
+struct SchemaObject {
+    obj_type: String,
+    name: String,
+    tbl_name: String,
+    sql: String,
+}
+
+/// 将SQLITE的值转换为SQL字面量。
+fn sql_literal(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) if f.is_nan() => "NULL".to_string(),
+        ValueRef::Real(f) if f.is_infinite() => if f > 0.0 { "1e999".to_string() } else { "-1e999".to_string() },
+        ValueRef::Real(f) => {
+            // `Debug`格式可无损还原，且较大或较小的数使用指数形式；保证恢复后仍为浮点数，而非整数。
+            let text = format!("{:?}", f);
+            if text.contains(|c| c == '.' || c == 'e' || c == 'E') { text } else { format!("{}.0", text) }
+        }
+        ValueRef::Text(t) => format!("'{}'", String::from_utf8_lossy(t).replace('\'', "''")),
+        ValueRef::Blob(b) => format!("X'{}'", b.iter().map(|b| format!("{:02X}", b)).collect::<String>()),
+    }
+}
+
+/// 提取SQL语句中出现的标识符，统一转换为小写，用于判断视图和触发器引用了哪些对象。
+fn referenced_names(sql: &str) -> HashSet<String> {
+    sql_words(sql).into_iter().map(|w| w.to_lowercase()).collect()
+}
+
+/// 按依赖关系排序，被依赖的对象在前。存在循环依赖时，剩余对象保持原有顺序。
+fn sort_by_dependencies<F>(objects: Vec<SchemaObject>, dependencies: F) -> Result<Vec<SchemaObject>, Box<dyn Error>>
+    where F: Fn(&SchemaObject) -> Result<HashSet<String>, Box<dyn Error>> {
+    let group: HashSet<String> = objects.iter().map(|o| o.name.to_lowercase()).collect();
+    let mut pending = vec![];
+    for object in objects {
+        let mut deps = dependencies(&object)?;
+        deps.retain(|d| group.contains(d) && !d.eq(&object.name.to_lowercase()));
+        pending.push((object, deps));
+    }
+
+    let mut sorted: Vec<SchemaObject> = vec![];
+    let mut emitted = HashSet::new();
+    while !pending.is_empty() {
+        let index = pending.iter().position(|(_, deps)| deps.is_subset(&emitted)).unwrap_or(0);
+        let (object, _) = pending.remove(index);
+        emitted.insert(object.name.to_lowercase());
+        sorted.push(object);
+    }
+    Ok(sorted)
+}
+
+/// 读取需要导出的对象，排除SQLITE内部对象和虚拟表的影子表。
+fn load_objects(conn: &Connection, selected: &Option<Vec<String>>) -> Result<Vec<SchemaObject>, Box<dyn Error>> {
+    let shadow_tables: HashSet<String> = conn.prepare("select name from pragma_table_list where type = 'shadow'")?
+        .query_map([], |r| r.get(0))?.collect::<rusqlite::Result<_>>()?;
+    let mut stmt = conn.prepare("select type, name, tbl_name, sql from sqlite_master where sql is not null and name not like 'sqlite\\_%' escape '\\' order by rowid")?;
+    let objects = stmt.query_map([], |r| Ok(SchemaObject { obj_type: r.get(0)?, name: r.get(1)?, tbl_name: r.get(2)?, sql: r.get(3)? }))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let objects = objects.into_iter().filter(|o| !shadow_tables.contains(&o.name));
+
+    let selected = match selected {
+        Some(selected) => selected,
+        None => return Ok(objects.collect()),
+    };
+    let objects: Vec<SchemaObject> = objects.collect();
+    let mut names = HashSet::new();
+    for name in selected {
+        let object = objects.iter().find(|o| o.name.eq_ignore_ascii_case(name)).ok_or_else(|| format!("对象 {} 不存在", name))?;
+        names.insert(object.name.to_lowercase());
+    }
+    // 选中表时一并导出依附于该表的索引和触发器。
+    Ok(objects.into_iter().filter(|o| {
+        names.contains(&o.name.to_lowercase()) || (o.obj_type != "view" && o.obj_type != "table" && names.contains(&o.tbl_name.to_lowercase()))
+    }).collect())
+}
+
+/// 写入表数据，返回写入的行数。
+fn dump_rows<W: Write>(conn: &Connection, table_name: &str, writer: &mut W) -> Result<u64, Box<dyn Error>> {
+    // 生成列和虚拟表的隐藏列不能写入。
+    let columns: Vec<String> = conn.prepare("select name from pragma_table_xinfo(?1) where hidden = 0")?
+        .query_map([table_name], |r| r.get::<_, String>(0))?.map(|n| n.map(|n| quote_ident(&n))).collect::<rusqlite::Result<_>>()?;
+    let column_list = columns.join(",");
+    let insert = format!("INSERT INTO {}({}) VALUES(", quote_ident(table_name), column_list);
+
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM {}", column_list, quote_ident(table_name)))?;
+    let mut rows = stmt.raw_query();
+    let mut count = 0u64;
+    while let Some(row) = rows.next()? {
+        writer.write_all(insert.as_bytes())?;
+        for i in 0..columns.len() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+            writer.write_all(sql_literal(row.get_ref(i)?).as_bytes())?;
+        }
+        writer.write_all(b");\n")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 将数据库的结构和数据以SQL语句写出。
+///
+/// 输出顺序为：表(被外键引用的表在前)及其数据、自增序列、索引、视图(按引用关系排序)、触发器。
+/// 触发器最后创建，恢复数据时不会被触发。输出以`BEGIN TRANSACTION`和`COMMIT`包围，可直接由sqlite3执行。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `writer`: 输出目标。
+/// * `options`: 导出的对象范围等选项。
+///
+/// returns: Result<DumpReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let mut file = BufWriter::new(File::create("/home/foo/tmp/my.sql").unwrap());
+/// let options = DumpOptions { objects: Some(vec!["users".to_string()]), ..Default::default() };
+/// let report = dump_db(&conn, &mut file, &options).unwrap();
+/// ```
+pub fn dump_db<W: Write>(conn: &Connection, writer: &mut W, options: &DumpOptions) -> Result<DumpReport, Box<dyn Error>> {
+    // 在同一个读事务中导出，保证结构和数据一致。
+    let _tx = conn.unchecked_transaction()?;
+    let objects = load_objects(conn, &options.objects)?;
+    let of_type = |t: &str| objects.iter().filter(|o| o.obj_type == t).cloned().collect::<Vec<_>>();
+    let tables = sort_by_dependencies(of_type("table"), |o| {
+        let parents = conn.prepare("select \"table\" from pragma_foreign_key_list(?1)")?
+            .query_map([&o.name], |r| r.get::<_, String>(0))?.map(|n| n.map(|n| n.to_lowercase())).collect::<rusqlite::Result<_>>()?;
+        Ok(parents)
+    })?;
+    let views = sort_by_dependencies(of_type("view"), |o| Ok(referenced_names(&o.sql)))?;
+
+    writer.write_all(b"PRAGMA foreign_keys=OFF;\nBEGIN TRANSACTION;\n")?;
+    let mut rows = 0u64;
+    for table in &tables {
+        writeln!(writer, "{};", table.sql)?;
+        if !options.schema_only {
+            rows += dump_rows(conn, &table.name, writer)?;
+        }
+    }
+    let has_sequence: bool = conn.query_row("select count(*) from sqlite_master where name = 'sqlite_sequence'", [], |r| r.get::<_, i64>(0))? > 0;
+    if has_sequence && !options.schema_only {
+        let mut stmt = conn.prepare("select name, seq from sqlite_sequence")?;
+        let sequences = stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?)))?.collect::<rusqlite::Result<Vec<_>>>()?;
+        for (name, seq) in sequences.iter().filter(|(name, _)| tables.iter().any(|t| t.name.eq(name))) {
+            let name = sql_literal(ValueRef::Text(name.as_bytes()));
+            writeln!(writer, "DELETE FROM sqlite_sequence WHERE name = {};", name)?;
+            writeln!(writer, "INSERT INTO sqlite_sequence(name, seq) VALUES({}, {});", name, seq)?;
+        }
+    }
+    for object in of_type("index").iter().chain(views.iter()).chain(of_type("trigger").iter()) {
+        writeln!(writer, "{};", object.sql)?;
+    }
+    if options.objects.is_none() {
+        let user_version: i64 = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+        if user_version != 0 {
+            writeln!(writer, "PRAGMA user_version={};", user_version)?;
+        }
+    }
+    writer.write_all(b"COMMIT;\n")?;
+    writer.flush()?;
+    Ok(DumpReport { objects: objects.len(), rows })
+}
+
+/// 跳过空白和注释，返回语句的第一个关键字(大写)。
+fn leading_keyword(sql: &str) -> String {
+    sql_words(sql).into_iter().next().unwrap_or_default().to_uppercase()
+}
+
+/// 在一个事务中逐条执行SQL脚本，任一语句失败时回滚全部修改。
+///
+/// 脚本读入后逐条编译执行，语句的拆分与`execute_script`相同。事务由本函数管理，脚本中的`BEGIN`、`COMMIT`和`END`语句被忽略；
+/// 执行期间关闭外键约束，以免数据的插入顺序导致约束失败。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `reader`: SQL脚本，通常是`dump_db`的输出。
+///
+/// returns: Result<RestoreReport, Box<dyn Error, Global>> 执行失败时错误信息中包含语句所在的行号。
+pub fn restore_dump<R: BufRead>(conn: &Connection, mut reader: R) -> Result<RestoreReport, Box<dyn Error>> {
+    let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = (|| -> Result<RestoreReport, Box<dyn Error>> {
+        let mut script = String::new();
+        reader.read_to_string(&mut script)?;
+        let tx = conn.unchecked_transaction()?;
+        let mut statements = 0u64;
+        let mut pieces = StatementReader::new(&tx, &script)?;
+        loop {
+            let mut prepared = match pieces.next() {
+                Ok(Some(prepared)) => prepared,
+                Ok(None) => break,
+                Err(e) => return Err(format!("第 {} 行的语句执行失败: {}", pieces.line(), e).into()),
+            };
+            let line = prepared.piece.line;
+            match leading_keyword(&prepared.piece.sql).as_str() {
+                "BEGIN" | "COMMIT" | "END" => {}
+                "ROLLBACK" => return Err(format!("第 {} 行: 脚本中不能包含ROLLBACK语句", line).into()),
+                _ => {
+                    let mut rows = prepared.stmt.raw_query();
+                    while rows.next().map_err(|e| format!("第 {} 行的语句执行失败: {}", line, e))?.is_some() {}
+                    statements += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(RestoreReport { statements })
+    })();
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_restore() {
+        let source = Connection::open_in_memory().unwrap();
+        source.execute_batch("
+            create table orders (id integer primary key autoincrement, user_id integer references \"my users\"(id), note text);
+            create table \"my users\" (id integer primary key, name text, score real, avatar blob, upper_name text as (upper(name)));
+            create index idx_orders_user on orders(user_id);
+            create view v_users as select id, name, score from \"my users\";
+            create view v_top as select * from v_users where score > 1;
+            drop view v_users;
+            create view v_users as select id, name, score from \"my users\";
+            create trigger trg_orders after insert on orders begin update \"my users\" set score = score + 1 where id = new.user_id; end;
+            insert into \"my users\" (id, name, score, avatar) values (1, 'it''s', 2.0, x'00FF'), (2, null, 1e300, null);
+            insert into orders (user_id, note) values (1, 'a;b'), (2, 'line1
+line2');
+            delete from orders where id = 2;
+            pragma user_version = 7;
+        ").unwrap();
+
+        let mut dump = vec![];
+        let report = dump_db(&source, &mut dump, &DumpOptions::default()).unwrap();
+        assert_eq!((report.objects, report.rows), (6, 3));
+        let text = String::from_utf8(dump.clone()).unwrap();
+        let lower = text.to_lowercase();
+        let position = |s: &str| lower.find(s).unwrap_or_else(|| panic!("缺少 {}", s));
+        assert!(position("create table \"my users\"") < position("create table orders"), "被引用的表应在前");
+        assert!(position("create view v_users") < position("create view v_top"), "被引用的视图应在前");
+        assert!(position("insert into \"orders\"") < position("create trigger"), "触发器应在数据之后创建");
+        assert!(text.contains("VALUES(2,NULL,1e300,NULL);"), "{}", text);
+        assert!(text.contains("VALUES(1,'it''s',3.0,X'00FF');"), "{}", text);
+
+        let target = Connection::open_in_memory().unwrap();
+        assert_eq!(restore_dump(&target, dump.as_slice()).unwrap().statements, 13);
+        let score: f64 = target.query_row("select score from \"my users\" where id = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(score, 3.0, "触发器不应在恢复数据时触发");
+        let upper: String = target.query_row("select upper_name from v_users join \"my users\" using (id) where id = 1", [], |r| r.get(0)).unwrap();
+        assert_eq!(upper, "IT'S");
+        target.execute("insert into orders (user_id) values (1)", []).unwrap();
+        assert_eq!(target.last_insert_rowid(), 3, "自增序列应被恢复");
+        let version: i64 = target.pragma_query_value(None, "user_version", |r| r.get(0)).unwrap();
+        assert_eq!(version, 7);
+
+        // 选中的表连同其索引和触发器导出，且只导出结构。
+        let mut dump = vec![];
+        let options = DumpOptions { objects: Some(vec!["ORDERS".to_string()]), schema_only: true };
+        assert_eq!(dump_db(&source, &mut dump, &options).unwrap().objects, 3);
+        assert!(!String::from_utf8(dump).unwrap().contains("INSERT"));
+        assert!(dump_db(&source, &mut Vec::<u8>::new(), &DumpOptions { objects: Some(vec!["missing".to_string()]), ..Default::default() }).is_err());
+
+        // 任一语句失败时回滚全部修改。
+        let target = Connection::open_in_memory().unwrap();
+        let script = "create table a (v);\ninsert into a values (1);\ninsert into missing values (1);\n";
+        let error = restore_dump(&target, script.as_bytes()).unwrap_err().to_string();
+        assert!(error.contains("第 3 行"), "{}", error);
+        let tables: i64 = target.query_row("select count(*) from sqlite_master", [], |r| r.get(0)).unwrap();
+        assert_eq!(tables, 0);
+        assert!(restore_dump(&target, "create table b (v".as_bytes()).is_err());
+    }
+}
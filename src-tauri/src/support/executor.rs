@@ -0,0 +1,709 @@
+//! 自定义SQL脚本执行引擎：按SQLite的词法规则拆分语句、绑定参数，并逐条返回执行结果。
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use rusqlite::{Connection, ErrorCode, ffi, InterruptHandle, Statement};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use serde::{Deserialize, Serialize};
+
+use crate::support::ddl::sql_words;
+
+/// 正在执行的查询，以查询编号为键，用于取消查询。
+static RUNNING_QUERIES: Lazy<Mutex<HashMap<String, InterruptHandle>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 各数据库文件的查询超时时间。
+static QUERY_TIMEOUTS: Lazy<Mutex<HashMap<String, Duration>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_QUERY_ID: AtomicU64 = AtomicU64::new(1);
+/// 各数据库文件最近执行过的查询和数据修改语句，包括用户执行的SQL、浏览表数据和游标的查询，最早的在前，用于分析索引的使用情况。
+static RECENT_QUERIES: Lazy<Mutex<HashMap<String, VecDeque<String>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 每个数据库最多保留的最近语句数。
+pub const RECENT_QUERY_LIMIT: usize = 100;
+
+/// 脚本中的一条语句。
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqlPiece {
+    /// 去除首尾空白后的语句文本。
+    pub sql: String,
+    /// 语句在脚本中的起始行号，从1开始。
+    pub line: usize,
+}
+
+/// 结果集字段描述。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnDesc {
+    pub name: String,
+    /// 来源字段的声明类型，表达式等非表字段为`None`。
+    pub decl_type: Option<String>,
+    /// 来源表名，表达式等非表字段为`None`。
+    pub origin_table: Option<String>,
+    /// 来源字段名，字段使用了别名时与`name`不同。
+    pub origin_column: Option<String>,
+    /// 是否可能为空。仅当来源字段声明了`NOT NULL`或为`rowid`时为`false`。
+    pub nullable: bool,
+}
+
+/// 单条语句的执行结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatementResult {
+    sql: String,
+    line: usize,
+    /// 结果集字段，按结果集中的顺序排列，仅对返回结果集的语句(包括`WITH`、`VALUES`、`RETURNING`等)有值。
+    columns: Option<Vec<ColumnDesc>>,
+    /// 结果集数据，每行按`columns`的顺序排列。
+    rows: Option<Vec<Vec<serde_json::Value>>>,
+    /// 语句插入、更新或删除的行数。
+    changes: u64,
+    /// 执行耗时，单位为毫秒。
+    elapsed_ms: f64,
+}
+
+/// 脚本的执行状态。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecStatus {
+    Completed,
+    /// 被`cancel_query`取消。
+    Interrupted,
+    /// 超过了数据库设置的查询超时时间。
+    TimedOut,
+}
+
+/// 可取消的脚本执行结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScriptOutcome {
+    query_id: String,
+    status: ExecStatus,
+    /// 已执行完成的语句的结果，被中断的语句不包含在内。
+    results: Vec<StatementResult>,
+}
+
+/// 脚本中已编译的一条语句。
+pub(crate) struct PreparedPiece<'conn> {
+    pub piece: SqlPiece,
+    pub stmt: Statement<'conn>,
+    /// `stmt`的原始句柄，用于读取rusqlite未提供的字段元数据，随`stmt`一同释放。
+    handle: *mut ffi::sqlite3_stmt,
+}
+
+/// 逐条编译脚本中的语句，以SQLITE编译时返回的剩余文本作为下一条语句的起点，字符串、注释和触发器定义中的分号不会被误拆分。
+///
+/// 语句在读取时才编译，前一条语句执行后创建的对象可被之后的语句引用。
+pub(crate) struct StatementReader<'conn, 'sql> {
+    conn: &'conn Connection,
+    sql: &'sql str,
+    /// 下一条语句在脚本中的起始位置。
+    pos: usize,
+    /// 已统计换行的位置及该位置所在的行号。
+    counted: usize,
+    line: usize,
+}
+
+impl<'conn, 'sql> StatementReader<'conn, 'sql> {
+    /// SQLITE在NUL字符处结束编译，其后的内容无法执行，因此脚本中不能包含NUL字符。
+    pub fn new(conn: &'conn Connection, sql: &'sql str) -> Result<StatementReader<'conn, 'sql>, Box<dyn Error>> {
+        if sql.contains('\0') {
+            return Err("SQL脚本中不能包含NUL字符".into());
+        }
+        Ok(StatementReader { conn, sql, pos: 0, counted: 0, line: 1 })
+    }
+
+    /// 最近读取的语句的起始行号，从1开始，编译出错时即出错语句的行号。
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// 编译下一条语句，只包含注释或分号的部分被跳过，脚本结束时返回`None`。
+    pub fn next(&mut self) -> Result<Option<PreparedPiece<'conn>>, Box<dyn Error>> {
+        loop {
+            // 空语句没有可执行的内容，与空白一同跳过。
+            let rest = &self.sql[self.pos..];
+            let trimmed = rest.trim_start_matches(|c: char| c == ';' || c.is_whitespace());
+            if trimmed.is_empty() {
+                self.pos = self.sql.len();
+                return Ok(None);
+            }
+            let start = self.pos + rest.len() - trimmed.len();
+            self.line += self.sql[self.counted..start].matches('\n').count();
+            self.counted = start;
+
+            // 新编译的语句位于连接的语句列表之首，由此取得其句柄；`sqlite3_sql()`返回的文本即编译时读取的部分。
+            let db = unsafe { self.conn.handle() };
+            let head = unsafe { ffi::sqlite3_next_stmt(db, ptr::null_mut()) };
+            let stmt = self.conn.prepare(trimmed)?;
+            let handle = unsafe { ffi::sqlite3_next_stmt(db, ptr::null_mut()) };
+            if handle == head {
+                self.pos = start + unsafe { empty_statement_len(db, trimmed)? };
+                continue;
+            }
+            let len = unsafe { CStr::from_ptr(ffi::sqlite3_sql(handle)).to_bytes().len() };
+            self.pos = start + len.clamp(1, trimmed.len());
+            let piece = SqlPiece { sql: self.sql[start..self.pos].trim_end().to_string(), line: self.line };
+            return Ok(Some(PreparedPiece { piece, stmt, handle }));
+        }
+    }
+}
+
+/// 没有编译出语句时(只有注释或分号)，返回SQLITE读取的长度。这种情况不会生成语句，再次调用`sqlite3_prepare_v2()`只为取得剩余文本的位置。
+unsafe fn empty_statement_len(db: *mut ffi::sqlite3, sql: &str) -> Result<usize, Box<dyn Error>> {
+    let mut stmt = ptr::null_mut();
+    let mut tail = ptr::null();
+    let head = sql.as_ptr() as *const c_char;
+    if ffi::sqlite3_prepare_v2(db, head, sql.len() as c_int, &mut stmt, &mut tail) != ffi::SQLITE_OK {
+        return Err(c_text(ffi::sqlite3_errmsg(db)).unwrap_or_default().into());
+    }
+    ffi::sqlite3_finalize(stmt);
+    let len = if tail.is_null() { 0 } else { tail as usize - head as usize };
+    // 未能前进时跳过剩余内容，避免死循环。
+    Ok(if len == 0 { sql.len() } else { len })
+}
+
+/// 编译单条语句，SQL中还有其它语句时返回错误。
+pub(crate) fn prepare_single<'conn>(conn: &'conn Connection, sql: &str) -> Result<PreparedPiece<'conn>, Box<dyn Error>> {
+    let mut reader = StatementReader::new(conn, sql)?;
+    let prepared = reader.next()?.ok_or("SQL语句为空")?;
+    // 第二条语句编译失败同样说明有多余的内容。
+    if !matches!(reader.next(), Ok(None)) {
+        return Err(format!("只能包含一条语句: {}", sql).into());
+    }
+    Ok(prepared)
+}
+
+/// 执行单条语句，SQL中还有其它语句时不执行并返回错误。用于执行拼接了前端传入内容的DDL语句。
+///
+/// returns: Result<usize, Box<dyn Error, Global>> 返回语句插入、更新或删除的行数。
+pub(crate) fn execute_single(conn: &Connection, sql: &str) -> Result<usize, Box<dyn Error>> {
+    Ok(prepare_single(conn, sql)?.stmt.execute([])?)
+}
+
+/// 脚本参数。
+enum ScriptParams<'a> {
+    None,
+    /// 按出现顺序依次绑定到整个脚本中的匿名参数`?`及`?NNN`。
+    Positional(&'a [serde_json::Value]),
+    /// 按名称绑定到`:name`、`@name`或`$name`参数，名称可带或不带前缀。
+    Named(&'a serde_json::Map<String, serde_json::Value>),
+}
+
+impl<'a> ScriptParams<'a> {
+    fn from_json(params: &'a Option<serde_json::Value>) -> Result<ScriptParams<'a>, Box<dyn Error>> {
+        match params {
+            None | Some(serde_json::Value::Null) => Ok(ScriptParams::None),
+            Some(serde_json::Value::Array(values)) => Ok(ScriptParams::Positional(values)),
+            Some(serde_json::Value::Object(values)) => Ok(ScriptParams::Named(values)),
+            Some(_) => Err("SQL参数应为数组或对象".into()),
+        }
+    }
+}
+
+/// 绑定语句参数，`positional_offset`为脚本中此前语句已消耗的匿名参数个数。
+fn bind_params(stmt: &mut Statement<'_>, params: &ScriptParams, positional_offset: usize) -> Result<(), Box<dyn Error>> {
+    for index in 1..=stmt.parameter_count() {
+        let name = stmt.parameter_name(index).map(|n| n.to_string());
+        let value = match (params, &name) {
+            (ScriptParams::Named(values), Some(name)) if !name.starts_with('?') => {
+                values.get(name).or_else(|| values.get(&name[1..]))
+                    .ok_or_else(|| format!("缺少参数 {}", name))?
+            }
+            (ScriptParams::Positional(values), _) => {
+                values.get(positional_offset + index - 1)
+                    .ok_or_else(|| format!("缺少第 {} 个参数", positional_offset + index))?
+            }
+            _ => return Err(format!("缺少参数 {}", name.unwrap_or_else(|| "?".to_string())).into()),
+        };
+        stmt.raw_bind_parameter(index, json_to_sql(value))?;
+    }
+    Ok(())
+}
+
+/// 按`execute_script`的规则绑定单条语句的参数。
+pub(crate) fn bind_json_params(stmt: &mut Statement<'_>, params: &Option<serde_json::Value>) -> Result<(), Box<dyn Error>> {
+    bind_params(stmt, &ScriptParams::from_json(params)?, 0)
+}
+
+/// 将JSON值转换为SQLITE值，数组和对象按JSON字符串保存。
+fn json_to_sql(value: &serde_json::Value) -> SqlValue {
+    match value {
+        serde_json::Value::Null => SqlValue::Null,
+        serde_json::Value::Bool(b) => SqlValue::Integer(*b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+/// 将SQLITE值转换为JSON值，BLOB转换为字节数组。
+pub(crate) fn sql_to_json(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Null => serde_json::Value::Null,
+        ValueRef::Integer(i) => serde_json::Value::from(i),
+        ValueRef::Real(f) => serde_json::Number::from_f64(f).map_or(serde_json::Value::Null, serde_json::Value::Number),
+        ValueRef::Text(t) => serde_json::Value::String(String::from_utf8_lossy(t).to_string()),
+        ValueRef::Blob(b) => serde_json::Value::from(b.to_vec()),
+    }
+}
+
+/// 将SQLITE返回的C字符串转换为`String`，空指针返回`None`。
+unsafe fn c_text(text: *const c_char) -> Option<String> {
+    if text.is_null() { None } else { Some(CStr::from_ptr(text).to_string_lossy().into_owned()) }
+}
+
+/// 读取已编译语句的结果集字段描述。
+///
+/// 来源表和来源字段通过`sqlite3_column_table_name()`等接口获取，依赖编译SQLITE时启用的`SQLITE_ENABLE_COLUMN_METADATA`。
+///
+/// # Arguments
+///
+/// * `prepared`: 通过`StatementReader`或`prepare_single`编译的语句。
+///
+/// returns: Vec<ColumnDesc, Global> 不返回结果集的语句返回空数组。
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open_in_memory().unwrap();
+/// conn.execute_batch("create table t (id integer not null, name text)").unwrap();
+/// let prepared = prepare_single(&conn, "select id as k, name, 1 from t").unwrap();
+/// let columns = describe_columns(&prepared);
+/// assert_eq!(columns[0].origin_column, Some("id".to_string()));
+/// ```
+pub(crate) fn describe_columns(prepared: &PreparedPiece<'_>) -> Vec<ColumnDesc> {
+    let stmt = prepared.handle;
+    let mut columns = vec![];
+    unsafe {
+        let db = ffi::sqlite3_db_handle(stmt);
+        for i in 0..ffi::sqlite3_column_count(stmt) {
+            let database = ffi::sqlite3_column_database_name(stmt, i);
+            let table = ffi::sqlite3_column_table_name(stmt, i);
+            let origin = ffi::sqlite3_column_origin_name(stmt, i);
+            let origin_column = c_text(origin);
+            let mut nullable = true;
+            if !database.is_null() && !table.is_null() && !origin.is_null() {
+                let mut not_null = 0;
+                let rc = ffi::sqlite3_table_column_metadata(db, database, table, origin, ptr::null_mut(), ptr::null_mut(),
+                                                            &mut not_null, ptr::null_mut(), ptr::null_mut());
+                let is_rowid = origin_column.as_ref().map_or(false, |c| c.eq_ignore_ascii_case("rowid"));
+                nullable = rc != ffi::SQLITE_OK || (not_null == 0 && !is_rowid);
+            }
+            columns.push(ColumnDesc {
+                name: c_text(ffi::sqlite3_column_name(stmt, i)).unwrap_or_default(),
+                decl_type: c_text(ffi::sqlite3_column_decltype(stmt, i)),
+                origin_table: c_text(table),
+                origin_column,
+                nullable,
+            });
+        }
+    }
+    columns
+}
+
+/// 查询连接自打开以来修改的总行数，用于判断语句是否修改了数据。
+fn total_changes(conn: &Connection) -> rusqlite::Result<i64> {
+    conn.query_row("SELECT total_changes()", [], |r| r.get(0))
+}
+
+/// 逐条执行SQL脚本。
+///
+/// 每条语句单独提交，执行出错时立即停止，之前已执行的语句不会回滚；错误信息中包含出错语句的行号。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的rusqlite连接。
+/// * `sql`: SQL脚本，原样执行，不做大小写转换。
+/// * `params`: 可选的参数。数组按出现顺序绑定到脚本中的`?`参数；对象按名称绑定到`:name`等命名参数。
+///
+/// returns: Result<Vec<StatementResult, Global>, Box<dyn Error, Global>> 每条语句一个执行结果。
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open_in_memory().unwrap();
+/// let params = Some(json!({"name": "Tom"}));
+/// let results = execute_script(&conn, "create table t (name text); insert into t values (:name); select * from t", &params).unwrap();
+/// assert_eq!(results.len(), 3);
+/// ```
+pub fn execute_script(conn: &Connection, sql: &str, params: &Option<serde_json::Value>) -> Result<Vec<StatementResult>, Box<dyn Error>> {
+    let mut results = vec![];
+    run_statements(conn, sql, params, &mut results).map_err(|e| {
+        if is_interrupted(e.as_ref()) { "执行已中断".into() } else { e }
+    })?;
+    Ok(results)
+}
+
+/// 可取消地执行SQL脚本。
+///
+/// 执行期间可通过`cancel_query`按查询编号中断；数据库设置了查询超时时间时，超时后同样会被中断。
+/// 中断不作为错误返回，而是以`ExecStatus`区分，并返回中断前已完成的语句的结果。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的rusqlite连接。
+/// * `db_path`: 数据库文件路径，用于读取该库的查询超时时间。
+/// * `query_id`: 可选的查询编号，为`None`时自动生成；与正在执行的查询重复时返回错误。
+/// * `sql`: SQL脚本。
+/// * `params`: 可选的参数，同`execute_script`。
+///
+/// returns: Result<ScriptOutcome, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open_in_memory().unwrap();
+/// let outcome = execute_cancellable(&conn, "/home/foo/tmp/sqlite/my.db", Some("q1".to_string()), "select 1", &None).unwrap();
+/// // 在其它线程中调用 cancel_query("q1") 可中断执行。
+/// ```
+pub fn execute_cancellable(conn: &Connection, db_path: &str, query_id: Option<String>, sql: &str, params: &Option<serde_json::Value>) -> Result<ScriptOutcome, Box<dyn Error>> {
+    let timeout = query_timeout(db_path)?;
+    let query_id = {
+        // 编号重复时拒绝执行，否则先执行的查询将无法再被取消。
+        let mut running = RUNNING_QUERIES.lock()?;
+        let query_id = match query_id {
+            Some(id) if running.contains_key(&id) => return Err(format!("查询 {} 正在执行", id).into()),
+            Some(id) => id,
+            None => loop {
+                let id = format!("q{}", NEXT_QUERY_ID.fetch_add(1, Ordering::SeqCst));
+                if !running.contains_key(&id) {
+                    break id;
+                }
+            },
+        };
+        running.insert(query_id.clone(), conn.get_interrupt_handle());
+        query_id
+    };
+    let timed_out = Arc::new(AtomicBool::new(false));
+    if let Some(timeout) = timeout {
+        let started = Instant::now();
+        let timed_out = timed_out.clone();
+        conn.progress_handler(1000, Some(move || {
+            if started.elapsed() >= timeout {
+                timed_out.store(true, Ordering::SeqCst);
+                return true;
+            }
+            false
+        }));
+    }
+
+    let mut results = vec![];
+    let outcome = run_statements(conn, sql, params, &mut results);
+    RUNNING_QUERIES.lock()?.remove(&query_id);
+    conn.progress_handler(0, None::<fn() -> bool>);
+    for result in results.iter() {
+        record_recent_query(db_path, &result.sql)?;
+    }
+
+    let status = match outcome {
+        Ok(()) => ExecStatus::Completed,
+        Err(e) if is_interrupted(e.as_ref()) => {
+            if timed_out.load(Ordering::SeqCst) { ExecStatus::TimedOut } else { ExecStatus::Interrupted }
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(ScriptOutcome { query_id, status, results })
+}
+
+/// 取消正在执行的查询。
+///
+/// returns: Result<bool, Box<dyn Error, Global>> 查询已结束或编号不存在时返回`false`。
+pub fn cancel_query(query_id: &str) -> Result<bool, Box<dyn Error>> {
+    match RUNNING_QUERIES.lock()?.get(query_id) {
+        Some(handle) => {
+            handle.interrupt();
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// 设置数据库的查询超时时间，为`None`时不限制。
+pub fn set_query_timeout(db_path: &str, timeout: Option<Duration>) -> Result<(), Box<dyn Error>> {
+    let mut timeouts = QUERY_TIMEOUTS.lock()?;
+    match timeout {
+        Some(timeout) => { timeouts.insert(db_path.to_string(), timeout); }
+        None => { timeouts.remove(db_path); }
+    }
+    Ok(())
+}
+
+/// 获取数据库的查询超时时间。
+pub fn query_timeout(db_path: &str) -> Result<Option<Duration>, Box<dyn Error>> {
+    Ok(QUERY_TIMEOUTS.lock()?.get(db_path).cloned())
+}
+
+/// 记录执行成功的`SELECT`、`WITH`、`UPDATE`和`DELETE`语句。重复的语句只保留最近一次。
+///
+/// 除用户执行的SQL外，浏览表数据和游标的查询也在此记录，供`analyze_index_usage`分析。
+pub(crate) fn record_recent_query(db_path: &str, sql: &str) -> Result<(), Box<dyn Error>> {
+    let first = sql_words(sql).into_iter().next().unwrap_or_default().to_lowercase();
+    if !["select", "with", "update", "delete"].contains(&first.as_str()) {
+        return Ok(());
+    }
+    let mut recent = RECENT_QUERIES.lock()?;
+    let queries = recent.entry(db_path.to_string()).or_default();
+    queries.retain(|q| q != sql);
+    queries.push_back(sql.to_string());
+    while queries.len() > RECENT_QUERY_LIMIT {
+        queries.pop_front();
+    }
+    Ok(())
+}
+
+/// 获取数据库最近执行过的查询和数据修改语句，最早的在前。
+pub fn recent_queries(db_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(RECENT_QUERIES.lock()?.get(db_path).map(|q| q.iter().cloned().collect()).unwrap_or_default())
+}
+
+/// 关闭数据库时清除其最近执行的语句。
+pub fn clear_recent_queries(db_path: &str) -> Result<(), Box<dyn Error>> {
+    RECENT_QUERIES.lock()?.remove(db_path);
+    Ok(())
+}
+
+/// 判断错误是否由`sqlite3_interrupt()`或进度回调中断引起。
+pub(crate) fn is_interrupted(e: &(dyn Error + 'static)) -> bool {
+    matches!(e.downcast_ref::<rusqlite::Error>(), Some(rusqlite::Error::SqliteFailure(f, _)) if f.code == ErrorCode::OperationInterrupted)
+}
+
+/// 逐条执行脚本中的语句，结果依次追加到`results`中。中断引起的错误原样返回，其余错误附加出错语句的行号。
+fn run_statements(conn: &Connection, sql: &str, params: &Option<serde_json::Value>, results: &mut Vec<StatementResult>) -> Result<(), Box<dyn Error>> {
+    let params = ScriptParams::from_json(params)?;
+    let mut positional_offset = 0;
+    let mut reader = StatementReader::new(conn, sql)?;
+
+    loop {
+        let started = Instant::now();
+        let result = (|| -> Result<Option<StatementResult>, Box<dyn Error>> {
+            let mut prepared = match reader.next()? {
+                Some(prepared) => prepared,
+                None => return Ok(None),
+            };
+            bind_params(&mut prepared.stmt, &params, positional_offset)?;
+            positional_offset += prepared.stmt.parameter_count();
+
+            let changes_before = total_changes(conn)?;
+            let column_count = prepared.stmt.column_count();
+            let mut columns = None;
+            let mut data = None;
+            if column_count > 0 {
+                columns = Some(describe_columns(&prepared));
+                let mut rows = prepared.stmt.raw_query();
+                let mut values = vec![];
+                while let Some(row) = rows.next()? {
+                    values.push((0..column_count).map(|i| row.get_ref(i).map(sql_to_json)).collect::<rusqlite::Result<Vec<_>>>()?);
+                }
+                data = Some(values);
+            } else {
+                prepared.stmt.raw_execute()?;
+            }
+            let PreparedPiece { piece, stmt, .. } = prepared;
+            drop(stmt);
+            let changes = if total_changes(conn)? != changes_before { conn.changes() } else { 0 };
+
+            Ok(Some(StatementResult {
+                sql: piece.sql,
+                line: piece.line,
+                columns,
+                rows: data,
+                changes,
+                elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+            }))
+        })();
+
+        match result {
+            Ok(Some(r)) => results.push(r),
+            Ok(None) => return Ok(()),
+            Err(e) if is_interrupted(e.as_ref()) => return Err(e),
+            Err(e) => return Err(format!("第 {} 行的语句执行出错: {}", reader.line(), e).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// 读取脚本中的全部语句，语句只编译不执行。
+    fn read_pieces(conn: &Connection, sql: &str) -> Result<Vec<SqlPiece>, Box<dyn Error>> {
+        let mut reader = StatementReader::new(conn, sql)?;
+        let mut pieces = vec![];
+        while let Some(prepared) = reader.next()? {
+            pieces.push(prepared.piece);
+        }
+        Ok(pieces)
+    }
+
+    #[test]
+    fn test_statement_reader() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("create table t (v)").unwrap();
+        let sql = "insert into t values ('a;b'); -- 注释;\n\ncreate trigger tr after insert on t begin\n  update t set v = 1;\nend;\nselect 1";
+        let pieces = read_pieces(&conn, sql).unwrap();
+        let lines: Vec<usize> = pieces.iter().map(|p| p.line).collect();
+        assert_eq!(pieces.len(), 3, "{:?}", pieces);
+        assert_eq!(pieces[0].sql, "insert into t values ('a;b');");
+        // 注释中的分号不作为语句结尾，注释归入下一条语句。
+        assert!(pieces[1].sql.starts_with("-- 注释;") && pieces[1].sql.ends_with("end;"));
+        assert_eq!(pieces[2].sql, "select 1");
+        assert_eq!(lines, vec![1, 1, 6]);
+
+        // 关键字不区分大小写，TEMP触发器同样整体保留；空语句和只有注释的部分被跳过。
+        let sql = "CREATE TEMP TRIGGER tr AFTER INSERT ON t BEGIN select ';'; END;; ; /* ; */ select [x;y] from (select 1 as \"x;y\");\n;select 2 -- ;";
+        let pieces = read_pieces(&conn, sql).unwrap();
+        assert_eq!(pieces.len(), 3, "{:?}", pieces);
+        assert!(pieces[0].sql.ends_with("END;"));
+        assert_eq!(pieces[1].sql, "/* ; */ select [x;y] from (select 1 as \"x;y\");");
+        assert_eq!((pieces[2].sql.as_str(), pieces[2].line), ("select 2 -- ;", 2));
+        assert!(read_pieces(&conn, " -- 只有注释\n;").unwrap().is_empty());
+
+        // 未闭合的字符串在编译时报错，行号指向出错的语句。
+        let mut reader = StatementReader::new(&conn, "select 1;\nselect 'a; select 2;").unwrap();
+        assert!(reader.next().unwrap().is_some());
+        assert!(reader.next().is_err());
+        assert_eq!(reader.line(), 2);
+        assert!(StatementReader::new(&conn, "select \"a\0b\"").is_err(), "脚本中不能包含NUL字符");
+    }
+
+    #[test]
+    fn test_execute_script() {
+        let conn = Connection::open_in_memory().unwrap();
+        let sql = "create table T (Name text, v integer);\n\
+                   insert into T values ('Tom', ?), ('JERRY', ?);\n\
+                   -- 只有注释\n\
+                   with x as (select Name from T where v > :min) select * from x;\n\
+                   values (1, 'A');\n\
+                   update T set v = v + 1 returning Name, v;\n\
+                   delete from T where Name = 'nobody'";
+        let params = Some(json!({"min": 1}));
+        assert!(execute_script(&conn, sql, &params).is_err(), "缺少匿名参数时应报错");
+
+        let conn = Connection::open_in_memory().unwrap();
+        let sql = sql.replace("?), ('JERRY', ?)", "1), ('JERRY', 2)");
+        let results = execute_script(&conn, &sql, &params).unwrap();
+        assert_eq!(results.len(), 6);
+        assert_eq!(results[1].changes, 2);
+        assert_eq!(results[2].columns.as_ref().map(|c| c[0].name.as_str()), Some("Name"));
+        assert_eq!(results[2].rows, Some(vec![vec![json!("JERRY")]]), "字符串字面量应保持原有大小写");
+        assert_eq!(results[2].changes, 0);
+        assert_eq!(results[3].rows, Some(vec![vec![json!(1), json!("A")]]));
+        assert_eq!(results[4].rows.as_ref().map(|r| r.len()), Some(2));
+        assert_eq!(results[4].changes, 2);
+        assert_eq!(results[5].columns, None);
+        assert_eq!(results[5].changes, 0);
+
+        let results = execute_script(&conn, "select ?, ?2; select ?", &Some(json!([1, 2, "x"]))).unwrap();
+        assert_eq!(results[0].rows, Some(vec![vec![json!(1), json!(2)]]));
+        assert_eq!(results[1].rows, Some(vec![vec![json!("x")]]));
+
+        let err = execute_script(&conn, "select 1;\nselect * from missing", &None).unwrap_err();
+        assert!(err.to_string().starts_with("第 2 行"), "{}", err);
+
+        assert_eq!(execute_single(&conn, "insert into T values ('a', 1); -- 注释").unwrap(), 1);
+        assert!(execute_single(&conn, "insert into T values ('b', 1); drop table T").is_err());
+        assert!(execute_single(&conn, "insert into T values ('c', 1); select * from missing").is_err());
+        let count: i64 = conn.query_row("select count(*) from T", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 3, "有多条语句时不应执行第一条");
+    }
+
+    /// 生成大量数据的慢查询。
+    const SLOW_SQL: &str = "select 1; with recursive c(x) as (select 1 union all select x + 1 from c) select count(*) from c";
+
+    #[test]
+    fn test_cancel_query() {
+        let conn = Connection::open_in_memory().unwrap();
+        let canceller = std::thread::spawn(|| {
+            for _ in 0..100 {
+                std::thread::sleep(Duration::from_millis(50));
+                if cancel_query("test-cancel").unwrap() {
+                    return true;
+                }
+            }
+            false
+        });
+        let outcome = execute_cancellable(&conn, ":memory:", Some("test-cancel".to_string()), SLOW_SQL, &None).unwrap();
+        assert!(canceller.join().unwrap());
+        assert_eq!(outcome.status, ExecStatus::Interrupted);
+        assert_eq!(outcome.results.len(), 1, "中断前已完成的语句结果应保留");
+        assert!(!cancel_query("test-cancel").unwrap(), "查询结束后应不可再取消");
+
+        // 中断后连接仍可继续使用。
+        let outcome = execute_cancellable(&conn, ":memory:", None, "select 2", &None).unwrap();
+        assert_eq!(outcome.status, ExecStatus::Completed);
+
+        let running = std::thread::spawn(|| {
+            let conn = Connection::open_in_memory().unwrap();
+            execute_cancellable(&conn, ":memory:", Some("test-duplicate".to_string()), SLOW_SQL, &None).unwrap().status
+        });
+        while !RUNNING_QUERIES.lock().unwrap().contains_key("test-duplicate") {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let duplicate = execute_cancellable(&conn, ":memory:", Some("test-duplicate".to_string()), "select 3", &None);
+        assert!(duplicate.is_err(), "编号与正在执行的查询重复时应拒绝");
+        // 中断可能在语句开始执行前到达而被忽略，反复取消直到查询结束。
+        while cancel_query("test-duplicate").unwrap() {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(running.join().unwrap(), ExecStatus::Interrupted, "重复的编号不应替换原查询的中断句柄");
+    }
+
+    #[test]
+    fn test_query_timeout() {
+        let db_path = "sqlcipher-front-timeout.db";
+        let conn = Connection::open_in_memory().unwrap();
+        set_query_timeout(db_path, Some(Duration::from_millis(200))).unwrap();
+        let started = Instant::now();
+        let outcome = execute_cancellable(&conn, db_path, None, SLOW_SQL, &None).unwrap();
+        assert_eq!(outcome.status, ExecStatus::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        set_query_timeout(db_path, None).unwrap();
+        assert_eq!(query_timeout(db_path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_recent_queries() {
+        let db_path = "sqlcipher-front-recent.db";
+        let conn = Connection::open_in_memory().unwrap();
+        let sql = "create table t (v); insert into t values (1); select * from t; /* 注释 */ update t set v = 2; select * from t; select * from missing;";
+        assert!(execute_cancellable(&conn, db_path, None, sql, &None).is_err());
+        assert_eq!(recent_queries(db_path).unwrap(), vec!["/* 注释 */ update t set v = 2;", "select * from t;"], "重复语句只保留最近一次，失败的语句不记录");
+
+        for i in 0..RECENT_QUERY_LIMIT {
+            execute_cancellable(&conn, db_path, None, &format!("select {}", i), &None).unwrap();
+        }
+        let recent = recent_queries(db_path).unwrap();
+        assert_eq!((recent.len(), recent[0].as_str()), (RECENT_QUERY_LIMIT, "select 0"));
+        clear_recent_queries(db_path).unwrap();
+        assert!(recent_queries(db_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_describe_columns() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("create table a (id integer not null, name text); create table b (id int, a_id integer);").unwrap();
+        let results = execute_script(&conn, "select a.rowid, a.id, b.id, b.a_id as ref, count(*) from a join b on b.a_id = a.id", &None).unwrap();
+        let columns = results[0].columns.clone().unwrap();
+        let names: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["rowid", "id", "id", "ref", "count(*)"], "同名字段应按顺序保留");
+        assert_eq!(columns[1], ColumnDesc {
+            name: "id".to_string(),
+            decl_type: Some("INTEGER".to_string()),
+            origin_table: Some("a".to_string()),
+            origin_column: Some("id".to_string()),
+            nullable: false,
+        });
+        assert!(!columns[0].nullable);
+        assert_eq!((columns[2].origin_table.as_deref(), columns[2].decl_type.as_deref(), columns[2].nullable), (Some("b"), Some("INT"), true));
+        assert_eq!(columns[3].origin_column.as_deref(), Some("a_id"));
+        assert_eq!((columns[4].decl_type.as_ref(), columns[4].origin_table.as_ref(), columns[4].nullable), (None, None, true));
+        assert!(describe_columns(&prepare_single(&conn, "insert into a values (1, 'x')").unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,288 @@
+//! 将表、视图或查询结果导出为CSV、JSON数组或NDJSON文件。结果逐行写入文件，不在内存中保留。
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+use rusqlite::types::ValueRef;
+use serde::{Deserialize, Serialize};
+
+use crate::support::blob::{BlobEncoding, encode_blob};
+use crate::support::csv::{CsvFormat, CsvWriter};
+use crate::support::executor::{bind_json_params, prepare_single, sql_to_json};
+use crate::support::load_db::{FilterSpec, SortSpec};
+
+/// 每写入多少行检查一次是否需要报告进度。
+const PROGRESS_CHECK_ROWS: u64 = 1000;
+
+/// 导出的文件格式。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    /// 整个文件为一个JSON数组，每行为一个对象。
+    Json,
+    /// 每行一个JSON对象。
+    Ndjson,
+}
+
+/// 导出的数据来源。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExportSource {
+    /// 表或视图，可按条件排序和过滤。
+    Table {
+        table_name: String,
+        order_by: Option<Vec<SortSpec>>,
+        filters: Option<Vec<FilterSpec>>,
+    },
+    /// 单条查询语句，参数规则同`exec_sql`。
+    Query {
+        sql: String,
+        params: Option<serde_json::Value>,
+    },
+}
+
+/// 导出选项。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// CSV格式选项，仅在导出CSV时生效。
+    pub csv: CsvFormat,
+    /// BLOB的文本编码方式，默认为十六进制。
+    pub blob_encoding: Option<BlobEncoding>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions { format: ExportFormat::Csv, csv: CsvFormat::default(), blob_encoding: None }
+    }
+}
+
+/// 数据导出进度。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataExportProgress {
+    pub target_path: String,
+    /// 已写入的行数。
+    pub rows: u64,
+    /// 总行数，导出查询结果时未知。
+    pub total_rows: Option<u64>,
+}
+
+/// 数据导出进度回调函数。
+pub type DataExportProgressFn = Box<dyn Fn(DataExportProgress) + Send + Sync>;
+
+/// 数据导出结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DataExportReport {
+    pub target_path: String,
+    pub rows: u64,
+    /// 目标文件的字节数。
+    pub file_size: u64,
+}
+
+/// 将查询结果逐行写入文件，导出失败时删除已写入的部分文件。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `sql`: 单条返回结果集的查询语句。
+/// * `params`: 可选的参数，规则同`execute_script`。
+/// * `total_rows`: 预先查询的总行数，仅用于报告进度。
+/// * `target_path`: 目标文件路径，已存在时将被覆盖。
+/// * `options`: 导出格式等选项。
+/// * `progress`: 可选的进度回调，导出期间定时报告已写入的行数。
+///
+/// returns: Result<DataExportReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let options = ExportOptions { format: ExportFormat::Ndjson, ..Default::default() };
+/// let report = export_query(&conn, "select * from my_table", &None, None, "/home/foo/tmp/my_table.ndjson", &options, None).unwrap();
+/// println!("导出 {} 行", report.rows);
+/// ```
+pub fn export_query(conn: &Connection, sql: &str, params: &Option<serde_json::Value>, total_rows: Option<u64>, target_path: &str,
+                    options: &ExportOptions, progress: Option<DataExportProgressFn>) -> Result<DataExportReport, Box<dyn Error>> {
+    if options.format == ExportFormat::Csv {
+        options.csv.validate()?;
+    }
+    let mut stmt = prepare_single(conn, sql)?.stmt;
+    if stmt.column_count() == 0 {
+        return Err("只能导出返回结果集的语句".into());
+    }
+    bind_json_params(&mut stmt, params)?;
+    let names: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+
+    let file = BufWriter::new(File::create(target_path)?);
+    let mut writer = RowWriter::new(file, &names, options)?;
+    let blob_encoding = options.blob_encoding.unwrap_or(BlobEncoding::Hex);
+    let report = |rows: u64| {
+        if let Some(progress) = &progress {
+            progress(DataExportProgress { target_path: target_path.to_string(), rows, total_rows });
+        }
+    };
+
+    let written = (|| -> Result<u64, Box<dyn Error>> {
+        let mut rows = stmt.raw_query();
+        let mut count = 0u64;
+        let mut last_report = Instant::now();
+        loop {
+            let row = match rows.next() {
+                Ok(Some(row)) => row,
+                Ok(None) => break,
+                Err(e) => return Err(e.into()),
+            };
+            let values = (0..names.len()).map(|i| row.get_ref(i)).collect::<rusqlite::Result<Vec<_>>>()?;
+            writer.write_row(&values, blob_encoding)?;
+            count += 1;
+            if count % PROGRESS_CHECK_ROWS == 0 && last_report.elapsed() >= Duration::from_millis(200) {
+                last_report = Instant::now();
+                report(count);
+            }
+        }
+        writer.finish()?;
+        Ok(count)
+    })();
+
+    match written {
+        Ok(rows) => {
+            report(rows);
+            let file_size = fs::metadata(target_path)?.len();
+            Ok(DataExportReport { target_path: target_path.to_string(), rows, file_size })
+        }
+        Err(e) => {
+            let _ = fs::remove_file(target_path);
+            Err(e)
+        }
+    }
+}
+
+/// 按导出格式写入每一行。
+enum RowWriter<W: Write> {
+    Csv(CsvWriter<W>),
+    Json {
+        writer: W,
+        /// 已转换为JSON字符串的字段名。
+        names: Vec<String>,
+        /// 为`true`时每行一个对象，否则整个文件为一个数组。
+        lines: bool,
+        rows: u64,
+    },
+}
+
+impl<W: Write> RowWriter<W> {
+    fn new(mut writer: W, names: &[String], options: &ExportOptions) -> Result<RowWriter<W>, Box<dyn Error>> {
+        if options.format == ExportFormat::Csv {
+            let mut csv = CsvWriter::new(writer, options.csv.clone());
+            if options.csv.has_header {
+                csv.write_record(names)?;
+            }
+            return Ok(RowWriter::Csv(csv));
+        }
+        let lines = options.format == ExportFormat::Ndjson;
+        if !lines {
+            writer.write_all(b"[")?;
+        }
+        let names = names.iter().map(serde_json::to_string).collect::<serde_json::Result<Vec<_>>>()?;
+        Ok(RowWriter::Json { writer, names, lines, rows: 0 })
+    }
+
+    fn write_row(&mut self, values: &[ValueRef<'_>], blob_encoding: BlobEncoding) -> Result<(), Box<dyn Error>> {
+        match self {
+            RowWriter::Csv(csv) => {
+                for value in values {
+                    match value {
+                        ValueRef::Null => csv.write_field("", false)?,
+                        ValueRef::Integer(i) => csv.write_field(&i.to_string(), true)?,
+                        ValueRef::Real(f) => csv.write_field(&format!("{:?}", f), true)?,
+                        ValueRef::Text(t) => csv.write_field(&String::from_utf8_lossy(t), false)?,
+                        ValueRef::Blob(b) => csv.write_field(&encode_blob(b, blob_encoding), false)?,
+                    }
+                }
+                csv.end_record()?;
+            }
+            RowWriter::Json { writer, names, lines, rows } => {
+                if !*lines {
+                    writer.write_all(if *rows > 0 { &b",\n"[..] } else { &b"\n"[..] })?;
+                }
+                // 逐个写入字段以保留字段顺序，重名字段也不会相互覆盖。
+                writer.write_all(b"{")?;
+                for (i, (name, value)) in names.iter().zip(values).enumerate() {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    writer.write_all(name.as_bytes())?;
+                    writer.write_all(b":")?;
+                    let value = match value {
+                        ValueRef::Blob(b) => serde_json::Value::String(encode_blob(b, blob_encoding)),
+                        other => sql_to_json(*other),
+                    };
+                    serde_json::to_writer(&mut *writer, &value)?;
+                }
+                writer.write_all(if *lines { &b"}\n"[..] } else { &b"}"[..] })?;
+                *rows += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            RowWriter::Csv(mut csv) => csv.flush()?,
+            RowWriter::Json { mut writer, lines, rows, .. } => {
+                if !lines {
+                    writer.write_all(if rows > 0 { &b"\n]\n"[..] } else { &b"]\n"[..] })?;
+                }
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_query() {
+        let mut dir = std::env::temp_dir();
+        dir.push("sqlcipher-front-export");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("create table t (id integer, name text, score real, data blob);
+            insert into t values (1, 'a,\"b\"', 1.5, x'CAFE'), (2, null, 2.0, null);").unwrap();
+        let sql = "select id, name, score, data, id as id from t where id >= ? order by id";
+        let params = Some(serde_json::json!([1]));
+        let export = |file: &str, options: ExportOptions| {
+            let path = dir.join(file).to_str().unwrap().to_string();
+            let report = export_query(&conn, sql, &params, Some(2), &path, &options, None).unwrap();
+            assert_eq!(report.rows, 2);
+            std::fs::read_to_string(&path).unwrap()
+        };
+
+        assert_eq!(export("t.csv", ExportOptions::default()), "id,name,score,data,id\r\n1,\"a,\"\"b\"\"\",1.5,CAFE,1\r\n2,,2.0,,2\r\n", "整数值的实数应保留小数点，再次导入时仍为REAL");
+        let options = ExportOptions { format: ExportFormat::Ndjson, blob_encoding: Some(BlobEncoding::Base64), ..Default::default() };
+        assert_eq!(export("t.ndjson", options), "{\"id\":1,\"name\":\"a,\\\"b\\\"\",\"score\":1.5,\"data\":\"yv4=\",\"id\":1}\n\
+            {\"id\":2,\"name\":null,\"score\":2.0,\"data\":null,\"id\":2}\n");
+        let json = export("t.json", ExportOptions { format: ExportFormat::Json, ..Default::default() });
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[0]["data"], "CAFE");
+        assert_eq!(parsed[1]["name"], serde_json::Value::Null);
+
+        let path = dir.join("bad.csv").to_str().unwrap().to_string();
+        for sql in ["select 1; select 2", "create table x (v)", "select * from missing"] {
+            assert!(export_query(&conn, sql, &None, None, &path, &ExportOptions::default(), None).is_err(), "{}", sql);
+        }
+        let options = ExportOptions { csv: CsvFormat { delimiter: '"', ..Default::default() }, ..Default::default() };
+        assert!(export_query(&conn, sql, &params, None, &path, &options, None).is_err(), "分隔符与引号相同时应报错");
+        assert!(export_query(&conn, "select abs(-9223372036854775807 - 1)", &None, None, &path, &ExportOptions::default(), None).is_err());
+        assert!(!std::path::Path::new(&path).exists(), "导出失败时应删除部分文件");
+    }
+}
@@ -0,0 +1,242 @@
+//! 数据库健康检查：执行`integrity_check`、`quick_check`、`foreign_key_check`和`cipher_integrity_check`，并将结果整理为结构化的问题列表。
+use std::collections::HashMap;
+use std::error::Error;
+
+use lazy_regex::regex_captures;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+/// `integrity_check`和`quick_check`默认最多报告的问题数。
+pub const DEFAULT_MAX_ERRORS: u32 = 100;
+
+/// 检查项。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    /// 完整检查，包括索引与表数据是否一致。
+    Integrity,
+    /// 快速检查，不检查索引内容。
+    Quick,
+    ForeignKey,
+    /// 校验SQLCipher每个页的HMAC，仅适用于加密数据库。
+    Cipher,
+}
+
+impl CheckKind {
+    pub const ALL: [CheckKind; 4] = [CheckKind::Integrity, CheckKind::Quick, CheckKind::ForeignKey, CheckKind::Cipher];
+}
+
+/// 检查发现的一个问题。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    /// 问题所属的表或索引，无法确定时为`None`。
+    pub object: Option<String>,
+    pub page: Option<i64>,
+    pub rowid: Option<i64>,
+    /// SQLITE报告的原始信息。
+    pub message: String,
+}
+
+/// 一个检查项的结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CheckOutcome {
+    pub check: CheckKind,
+    pub findings: Vec<Finding>,
+    /// 检查本身无法完成时的错误信息，例如文件严重损坏。
+    pub error: Option<String>,
+    /// 检查不适用时的原因，例如对未加密的数据库执行`cipher_integrity_check`。
+    pub skipped: Option<String>,
+}
+
+impl CheckOutcome {
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty() && self.error.is_none()
+    }
+}
+
+/// 健康检查报告。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HealthReport {
+    /// 所有检查均未发现问题。
+    pub ok: bool,
+    pub checks: Vec<CheckOutcome>,
+}
+
+/// 解析`integrity_check`或`quick_check`返回的一条信息。
+///
+/// # Arguments
+///
+/// * `message`: 一条信息，不含`*** in database main ***`之类的标题行。
+/// * `root_pages`: 根页号到表名或索引名的映射，用于将`Tree N`转换为对象名。
+fn parse_integrity_message(message: &str, root_pages: &HashMap<i64, String>) -> Finding {
+    let number = |s: &str| s.parse::<i64>().ok();
+    let mut finding = Finding { object: None, page: None, rowid: None, message: message.to_string() };
+    if let Some((_, root, page)) = regex_captures!(r"^Tree (\d+) page (\d+)", message) {
+        finding.object = number(root).and_then(|root| root_pages.get(&root).cloned());
+        finding.page = number(page);
+    } else if let Some((_, page)) = regex_captures!(r"(?i)\bpage (\d+)", message) {
+        finding.page = number(page);
+    }
+    if let Some((_, rowid, index)) = regex_captures!(r"^row (\d+) missing from index (.+)$", message) {
+        finding.rowid = number(rowid);
+        finding.object = Some(index.to_string());
+    } else if let Some((_, index)) = regex_captures!(r"\bindex (.+)$", message) {
+        finding.object = Some(index.to_string());
+    } else if let Some((_, table)) = regex_captures!(r"^NULL value in ([^.]+)\.", message) {
+        finding.object = Some(table.to_string());
+    } else if let Some((_, table)) = regex_captures!(r"constraint failed in (.+)$", message) {
+        finding.object = Some(table.to_string());
+    }
+    finding
+}
+
+/// 执行`integrity_check`或`quick_check`，结果为`ok`时返回空列表。
+fn run_integrity_check(conn: &Connection, pragma: &str, max_errors: u32) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select rootpage, name from sqlite_master where rootpage > 0")?;
+    let root_pages = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?.collect::<rusqlite::Result<HashMap<i64, String>>>()?;
+
+    let mut stmt = conn.prepare(&format!("PRAGMA {}({})", pragma, max_errors))?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows.iter()
+        .filter(|row| row.as_str() != "ok")
+        .flat_map(|row| row.lines())
+        .filter(|line| !line.trim().is_empty() && !line.starts_with("***"))
+        .map(|line| parse_integrity_message(line, &root_pages))
+        .collect())
+}
+
+/// 执行`foreign_key_check`，每条违反外键约束的行为一个问题。
+fn run_foreign_key_check(conn: &Connection) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select \"table\", rowid, parent, fkid from pragma_foreign_key_check")?;
+    let findings = stmt.query_map([], |r| {
+        let table: String = r.get(0)?;
+        let parent: String = r.get(2)?;
+        let fkid: i64 = r.get(3)?;
+        Ok(Finding {
+            object: Some(table.clone()),
+            page: None,
+            rowid: r.get(1)?,
+            message: format!("表 {} 的第 {} 个外键引用的 {} 中不存在对应的行", table, fkid, parent),
+        })
+    })?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(findings)
+}
+
+/// 执行`cipher_integrity_check`，每条信息为一个问题，通常带有页号。
+fn run_cipher_check(conn: &Connection) -> Result<Vec<Finding>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("PRAGMA cipher_integrity_check")?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows.iter().map(|message| parse_integrity_message(message, &HashMap::new())).collect())
+}
+
+/// 对数据库执行健康检查。单个检查项失败不影响其他检查项，其错误记录在对应的`CheckOutcome.error`中。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `encrypted`: 数据库是否加密，未加密时跳过`cipher_integrity_check`。
+/// * `checks`: 要执行的检查项，按给定顺序执行。
+/// * `max_errors`: `integrity_check`和`quick_check`最多报告的问题数。
+///
+/// returns: Result<HealthReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let report = check_health(&conn, false, &[CheckKind::Quick, CheckKind::ForeignKey], DEFAULT_MAX_ERRORS).unwrap();
+/// for check in report.checks.iter().filter(|c| !c.passed()) {
+///     println!("{:?}: {:?}", check.check, check.findings);
+/// }
+/// ```
+pub fn check_health(conn: &Connection, encrypted: bool, checks: &[CheckKind], max_errors: u32) -> Result<HealthReport, Box<dyn Error>> {
+    let max_errors = max_errors.max(1);
+    let mut outcomes = vec![];
+    for check in checks {
+        let mut outcome = CheckOutcome { check: *check, findings: vec![], error: None, skipped: None };
+        let result = match check {
+            CheckKind::Integrity => run_integrity_check(conn, "integrity_check", max_errors),
+            CheckKind::Quick => run_integrity_check(conn, "quick_check", max_errors),
+            CheckKind::ForeignKey => run_foreign_key_check(conn),
+            CheckKind::Cipher if !encrypted => {
+                outcome.skipped = Some("数据库未加密".to_string());
+                Ok(vec![])
+            }
+            CheckKind::Cipher => run_cipher_check(conn),
+        };
+        match result {
+            Ok(findings) => outcome.findings = findings,
+            Err(e) => outcome.error = Some(e.to_string()),
+        }
+        outcomes.push(outcome);
+    }
+    Ok(HealthReport { ok: outcomes.iter().all(|o| o.passed()), checks: outcomes })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_integrity_message() {
+        let root_pages: HashMap<i64, String> = vec![(2, "users".to_string())].into_iter().collect();
+        let parse = |m: &str| parse_integrity_message(m, &root_pages);
+        let finding = parse("row 3 missing from index idx_users_name");
+        assert_eq!((finding.object.as_deref(), finding.rowid), (Some("idx_users_name"), Some(3)));
+        let finding = parse("Tree 2 page 5 cell 0: 2nd reference to page 7");
+        assert_eq!((finding.object.as_deref(), finding.page), (Some("users"), Some(5)));
+        assert_eq!(parse("Page 9 is never used").page, Some(9));
+        assert_eq!(parse("NULL value in users.name").object.as_deref(), Some("users"));
+        assert_eq!(parse("non-unique entry in UNIQUE index idx_email").object.as_deref(), Some("idx_email"));
+    }
+
+    #[test]
+    fn test_check_health() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key, age integer check (age > 0));
+            create table orders (id integer primary key, user_id integer references users(id));
+            pragma ignore_check_constraints = 1;
+            pragma foreign_keys = 0;
+            insert into users values (1, -5);
+            insert into orders values (10, 1), (11, 2);
+            pragma ignore_check_constraints = 0;
+        ").unwrap();
+        let report = check_health(&conn, false, &CheckKind::ALL, DEFAULT_MAX_ERRORS).unwrap();
+        assert!(!report.ok);
+        let outcome = |kind: CheckKind| report.checks.iter().find(|c| c.check == kind).unwrap();
+        let integrity = outcome(CheckKind::Integrity);
+        assert_eq!(integrity.findings.len(), 1, "{:?}", integrity);
+        assert_eq!(integrity.findings[0].object.as_deref(), Some("users"));
+        assert_eq!(outcome(CheckKind::Quick).findings, integrity.findings, "quick_check同样检查CHECK约束");
+        let foreign_key = outcome(CheckKind::ForeignKey);
+        assert_eq!(foreign_key.findings.len(), 1);
+        assert_eq!((foreign_key.findings[0].object.as_deref(), foreign_key.findings[0].rowid), (Some("orders"), Some(11)));
+        assert!(outcome(CheckKind::Cipher).skipped.is_some());
+
+        // 破坏加密数据库的一个数据页。
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-health.db");
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "key", "123456").unwrap();
+        conn.execute_batch("create table t (v text); with recursive c(x) as (select 1 union all select x + 1 from c where x < 50) insert into t select hex(randomblob(500)) from c;").unwrap();
+        assert!(check_health(&conn, true, &[CheckKind::Cipher], DEFAULT_MAX_ERRORS).unwrap().ok);
+        drop(conn);
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(4096 * 3 + 100)).unwrap();
+        file.write_all(&[0xAB; 16]).unwrap();
+        drop(file);
+
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "key", "123456").unwrap();
+        let report = check_health(&conn, true, &[CheckKind::Cipher, CheckKind::Quick], DEFAULT_MAX_ERRORS).unwrap();
+        assert!(!report.ok);
+        let cipher = &report.checks[0];
+        assert!(cipher.findings.iter().any(|f| f.page == Some(4)), "{:?}", cipher);
+        assert!(!report.checks[1].passed(), "{:?}", report.checks[1]);
+    }
+}
@@ -16,19 +16,30 @@ use toml::de::Error as TomlError;
 use toml::Value;
 use toml::value::Table;
 
+use crate::support::cipher::CipherProfile;
+use crate::support::vault::{fetch_key, forget_key, store_key};
+
 #[derive(Serialize, Deserialize)]
 struct HisList {
+    #[serde(rename = "His", default)]
     his: Vec<His>,
 }
 
+/// 历史记录条目。
 #[derive(Serialize, Deserialize)]
-struct His {
-    name: String,
-    path: String,
-    key: Option<String>,
+pub struct His {
+    pub name: String,
+    pub path: String,
+    /// 旧版本以明文保存的密钥，解锁密钥库后会被迁移到密钥库。
+    pub key: Option<String>,
+    /// 密钥在密钥库中的引用编号。
+    pub key_ref: Option<String>,
+    pub cipher: Option<CipherProfile>,
+    /// 自定义SQL的查询超时时间，单位为毫秒。
+    pub query_timeout_ms: Option<u64>,
 }
 
-/// 读取加载文件的历史列表。
+/// 读取加载文件的历史列表。返回的条目中不包含密钥及其引用，仅以`encrypted`标识是否保存了密钥。
 ///
 /// # Arguments
 ///
@@ -54,8 +65,14 @@ pub fn get_open_history(mut data_path: PathBuf) -> Option<Value> {
 
         match his_list {
             Ok(his_list) => {
-                let his_arr = his_list.get("His").unwrap();
-                Some(his_arr.clone())
+                let mut his_arr = his_list.get("His").unwrap().clone();
+                if let Some(array) = his_arr.as_array_mut() {
+                    for entry in array.iter_mut().filter_map(|e| e.as_table_mut()) {
+                        let encrypted = entry.remove("key").is_some() | entry.remove("key_ref").is_some();
+                        entry.insert("encrypted".to_string(), Value::Boolean(encrypted));
+                    }
+                }
+                Some(his_arr)
             }
             Err(e) => {
                 error!("get open history error: {:?}", e);
@@ -83,6 +100,8 @@ impl Error for NotSupportedOs {}
 /// * `data_path`: 历史记录保存目录。
 /// * `name`: 文件名。
 /// * `path`: 文件路径。
+/// * `key`: 可选的密钥，保存到密钥库中，历史记录只保存其引用编号。
+/// * `cipher`: 可选的加密参数，与库默认设置一致时不保存。
 ///
 /// returns: Result<(), Error> 返回执行成败信息。
 ///
@@ -90,12 +109,12 @@ impl Error for NotSupportedOs {}
 ///
 /// ```
 /// let data_path = PathBuf::from("/home/john/tmp");
-/// let result = add_open_history(data_path, "my.db".to_string(), "/home/john/.cache/my.db".to_string());
+/// let result = add_open_history(data_path, "my.db".to_string(), "/home/john/.cache/my.db".to_string(), None, None);
 /// if let Err(e) = result {
 ///     assert!(false, "新增失败 {}", e);
 /// }
 /// ```
-pub fn add_open_history(mut data_path: PathBuf, name: String, path: String, key: Option<String>) -> Result<(), Box<dyn Error>> {
+pub fn add_open_history(mut data_path: PathBuf, name: String, path: String, key: Option<String>, cipher: Option<CipherProfile>) -> Result<(), Box<dyn Error>> {
     data_path.push("history.toml");
     let mut content = String::new();
     if data_path.exists() {
@@ -106,33 +125,37 @@ pub fn add_open_history(mut data_path: PathBuf, name: String, path: String, key:
     let his_list: Result<Table, TomlError> = toml::from_str(content.as_str());
     match his_list {
         Ok(mut his_list) => {
+            let mut new_entry = toml::map::Map::new();
+            new_entry.insert("name".to_string(), Value::String(name));
+            new_entry.insert("path".to_string(), Value::String(path));
+            if let Some(key) = key {
+                new_entry.insert("key_ref".to_string(), Value::String(store_key(&key)?));
+            }
+            if let Some(cipher) = cipher.filter(|c| !c.is_default()) {
+                new_entry.insert("cipher".to_string(), Value::try_from(cipher)?);
+            }
+
             let list: Option<&mut Value> = his_list.get_mut("His");
-            let new_content;
             match list {
                 Some(list) => {
-                    let mut new_entry = toml::map::Map::new();
-                    new_entry.insert("name".to_string(), Value::String(name));
-                    new_entry.insert("path".to_string(), Value::String(path));
-                    if key.is_some() {
-                        new_entry.insert("key".to_string(), Value::String(key.unwrap()));
-                    }
-
                     let array = list.as_array_mut().unwrap();
                     array.insert(0, Value::Table(new_entry));
 
-                    if array.len() > 20 {
-                        array.remove(21);
+                    // 最多保留20条，同时从密钥库删除被移除条目的密钥。
+                    for entry in array.iter().skip(20) {
+                        if let Some(Value::String(key_ref)) = entry.as_table().and_then(|t| t.get("key_ref")) {
+                            if let Err(e) = forget_key(key_ref) {
+                                error!("从密钥库删除密钥时出错 {:?}", e);
+                            }
+                        }
                     }
-                    new_content = toml::to_string(&his_list).unwrap();
+                    array.truncate(20);
                 }
                 None => {
-                    if key.is_some() {
-                        new_content = format!("[[His]]\nname = \"{}\"\npath = \"{}\"\nkey=\"{}\"\n\n", name, path, key.unwrap());
-                    } else {
-                        new_content = format!("[[His]]\nname = \"{}\"\npath = \"{}\"\n\n", name, path);
-                    }
+                    his_list.insert("His".to_string(), Value::Array(vec![Value::Table(new_entry)]));
                 }
             }
+            let new_content = toml::to_string(&his_list)?;
 
             write_content_to_file(data_path.clone(), &new_content)?;
             Ok(())
@@ -222,6 +245,11 @@ pub fn remove_open_history(mut data_path: PathBuf, index: usize) -> Result<Optio
                         if let Some(path) = entry_path {
                             removed_path = Some(path.as_str().unwrap().to_string());
                         }
+                        if let Some(Value::String(key_ref)) = entry.as_table().unwrap().get("key_ref") {
+                            if let Err(e) = forget_key(key_ref) {
+                                error!("从密钥库删除密钥时出错 {:?}", e);
+                            }
+                        }
                     }
 
                     let new_content = toml::to_string(&his_list).unwrap();
@@ -241,6 +269,185 @@ pub fn remove_open_history(mut data_path: PathBuf, index: usize) -> Result<Optio
     }
 }
 
+/// 修改指定数据库文件对应的所有历史记录中保存的密钥。
+///
+/// # Arguments
+///
+/// * `data_path`: 历史记录保存目录。
+/// * `path`: 数据库文件路径。
+/// * `key`: 新密钥，为`None`时移除已保存的密钥。
+///
+/// returns: Result<(), Error> 操作成败信息。
+///
+/// # Examples
+///
+/// ```
+/// let data_path = PathBuf::from("/home/john/tmp");
+/// let result = update_open_history_key(data_path, "/home/john/.cache/my.db", Some("654321".to_string()));
+/// if let Err(e) = result {
+///     assert!(false, "修改历史记录失败 {}", e);
+/// }
+/// ```
+pub fn update_open_history_key(data_path: PathBuf, path: &str, key: Option<String>) -> Result<(), Box<dyn Error>> {
+    update_history_entries(data_path, path, |entry| {
+        entry.remove("key");
+        if let Some(Value::String(key_ref)) = entry.remove("key_ref") {
+            forget_key(&key_ref)?;
+        }
+        match &key {
+            Some(key) => { entry.insert("key_ref".to_string(), Value::String(store_key(key)?)); }
+            None => {
+                // 明文数据库不再需要加密参数。
+                entry.remove("cipher");
+            }
+        }
+        Ok(())
+    })
+}
+
+/// 修改指定数据库文件对应的所有历史记录中保存的加密参数。
+///
+/// # Arguments
+///
+/// * `data_path`: 历史记录保存目录。
+/// * `path`: 数据库文件路径。
+/// * `cipher`: 新的加密参数，为`None`或与库默认设置一致时移除已保存的加密参数。
+///
+/// returns: Result<(), Error> 操作成败信息。
+pub fn update_open_history_cipher(data_path: PathBuf, path: &str, cipher: Option<CipherProfile>) -> Result<(), Box<dyn Error>> {
+    let cipher = cipher.filter(|c| !c.is_default());
+    update_history_entries(data_path, path, |entry| {
+        match &cipher {
+            Some(cipher) => { entry.insert("cipher".to_string(), Value::try_from(cipher)?); }
+            None => { entry.remove("cipher"); }
+        }
+        Ok(())
+    })
+}
+
+/// 修改指定数据库文件对应的所有历史记录中保存的查询超时时间。
+///
+/// # Arguments
+///
+/// * `data_path`: 历史记录保存目录。
+/// * `path`: 数据库文件路径。
+/// * `timeout_ms`: 查询超时时间，单位为毫秒，为`None`时移除超时设置。
+///
+/// returns: Result<(), Error> 操作成败信息。
+pub fn update_open_history_query_timeout(data_path: PathBuf, path: &str, timeout_ms: Option<u64>) -> Result<(), Box<dyn Error>> {
+    update_history_entries(data_path, path, |entry| {
+        match timeout_ms {
+            Some(ms) => { entry.insert("query_timeout_ms".to_string(), Value::Integer(ms as i64)); }
+            None => { entry.remove("query_timeout_ms"); }
+        }
+        Ok(())
+    })
+}
+
+/// 对指定数据库文件对应的每一条历史记录执行修改，然后写回历史记录文件。
+fn update_history_entries<F>(data_path: PathBuf, path: &str, mut update: F) -> Result<(), Box<dyn Error>>
+    where F: FnMut(&mut Table) -> Result<(), Box<dyn Error>> {
+    rewrite_history(data_path, |entry| {
+        if entry.get("path").and_then(|p| p.as_str()) == Some(path) {
+            update(entry)?;
+        }
+        Ok(())
+    })
+}
+
+/// 对每一条历史记录执行修改，然后写回历史记录文件。
+fn rewrite_history<F>(mut data_path: PathBuf, mut update: F) -> Result<(), Box<dyn Error>>
+    where F: FnMut(&mut Table) -> Result<(), Box<dyn Error>> {
+    data_path.push("history.toml");
+    if !data_path.exists() {
+        return Ok(());
+    }
+    let mut content = String::new();
+    let mut file = File::open(data_path.clone())?;
+    file.read_to_string(&mut content)?;
+
+    let mut his_list: Table = toml::from_str(content.as_str())?;
+    if let Some(Value::Array(array)) = his_list.get_mut("His") {
+        for entry in array.iter_mut().filter_map(|e| e.as_table_mut()) {
+            update(entry)?;
+        }
+        let new_content = toml::to_string(&his_list)?;
+        write_content_to_file(data_path, &new_content)?;
+    }
+    Ok(())
+}
+
+/// 将旧版本以明文保存在历史记录中的密钥迁移到密钥库，须在密钥库解锁后调用。
+///
+/// # Arguments
+///
+/// * `data_path`: 历史记录保存目录。
+///
+/// returns: Result<usize, Error> 返回迁移的密钥数量。
+pub fn migrate_history_keys(data_path: PathBuf) -> Result<usize, Box<dyn Error>> {
+    let mut migrated = 0;
+    rewrite_history(data_path, |entry| {
+        if let Some(Value::String(key)) = entry.remove("key") {
+            entry.insert("key_ref".to_string(), Value::String(store_key(&key)?));
+            migrated += 1;
+        }
+        Ok(())
+    })?;
+    Ok(migrated)
+}
+
+/// 读取某一条历史记录。
+///
+/// # Arguments
+///
+/// * `data_path`: 历史记录保存目录。
+/// * `index`: 历史记录索引编号，从0开始。
+///
+/// returns: Result<Option<His>, Error> 索引超出范围时返回`None`。
+pub fn get_open_history_entry(mut data_path: PathBuf, index: usize) -> Result<Option<His>, Box<dyn Error>> {
+    data_path.push("history.toml");
+    if !data_path.exists() {
+        return Ok(None);
+    }
+    let mut content = String::new();
+    File::open(data_path)?.read_to_string(&mut content)?;
+    let his_list: HisList = toml::from_str(content.as_str())?;
+    Ok(his_list.his.into_iter().nth(index))
+}
+
+/// 取出历史记录对应的数据库密钥，优先从密钥库读取。
+pub fn resolve_history_key(his: &His) -> Result<Option<String>, Box<dyn Error>> {
+    match &his.key_ref {
+        Some(key_ref) => match fetch_key(key_ref)? {
+            Some(key) => Ok(Some(key)),
+            None => Err(format!("密钥库中未找到 {} 的密钥", his.path).into()),
+        },
+        None => Ok(his.key.clone()),
+    }
+}
+
+/// 删除某一条历史记录保存的密钥，数据库文件和历史记录本身保持不变。
+///
+/// # Arguments
+///
+/// * `data_path`: 历史记录保存目录。
+/// * `index`: 历史记录索引编号，从0开始。
+///
+/// returns: Result<(), Error> 操作成败信息。
+pub fn forget_open_history_key(data_path: PathBuf, index: usize) -> Result<(), Box<dyn Error>> {
+    let mut current = 0;
+    rewrite_history(data_path, |entry| {
+        if current == index {
+            entry.remove("key");
+            if let Some(Value::String(key_ref)) = entry.remove("key_ref") {
+                forget_key(&key_ref)?;
+            }
+        }
+        current += 1;
+        Ok(())
+    })
+}
+
 pub fn read_template_record(temp_file_path: PathBuf) -> DaoResult {
     let mut record = String::new();
     if temp_file_path.exists() && temp_file_path.is_file() {
@@ -264,6 +471,9 @@ mod tests {
     use names::{Generator, Name};
     use rand::Rng;
 
+    use crate::support::cipher::CipherPreset;
+    use crate::support::vault::unlock_test_vault;
+
     use super::*;
 
     #[test]
@@ -275,17 +485,123 @@ mod tests {
 
     #[test]
     fn test_add_open_history() {
+        let _vault = unlock_test_vault();
         let data_path = env::temp_dir();
         let path = if cfg!(windows) {
             env::temp_dir().as_os_str().to_str().unwrap().to_string()
         } else { "/home/liuning/.cache/my.db".to_string() };
         println!("path: {:?}", path);
-        let result = add_open_history(data_path, "my3.db".to_string(), path, Some("123456".to_string()));
+        let result = add_open_history(data_path, "my3.db".to_string(), path, Some("123456".to_string()), None);
         if let Err(e) = result {
             assert!(false, "新增失败 {}", e);
         }
     }
 
+    #[test]
+    fn test_add_open_history_with_cipher() {
+        let _vault = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-cipher-his");
+        fs::create_dir_all(&data_path).unwrap();
+        empty_open_history(data_path.clone()).unwrap();
+
+        let cipher = CipherProfile { preset: CipherPreset::Sqlcipher3, kdf_iter: Some(10000), ..Default::default() };
+        add_open_history(data_path.clone(), "v3.db".to_string(), "/tmp/v3.db".to_string(), Some("123456".to_string()), Some(cipher.clone())).unwrap();
+        add_open_history(data_path.clone(), "plain.db".to_string(), "/tmp/plain.db".to_string(), None, Some(CipherProfile::default())).unwrap();
+
+        let his: Vec<His> = get_open_history(data_path).unwrap().try_into().unwrap();
+        assert_eq!(his.len(), 2);
+        assert_eq!(his[0].cipher, None, "默认加密参数不应写入历史记录");
+        assert_eq!(his[1].cipher, Some(cipher));
+    }
+
+    #[test]
+    fn test_add_open_history_limit() {
+        let _vault = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-limit-his");
+        fs::create_dir_all(&data_path).unwrap();
+        empty_open_history(data_path.clone()).unwrap();
+
+        for i in 0..20 {
+            add_open_history(data_path.clone(), format!("{}.db", i), format!("/tmp/{}.db", i), Some(format!("key{}", i)), None).unwrap();
+        }
+        let oldest = get_open_history_entry(data_path.clone(), 19).unwrap().unwrap();
+        let key_ref = oldest.key_ref.clone().unwrap();
+        assert_eq!(fetch_key(&key_ref).unwrap(), Some("key0".to_string()));
+
+        add_open_history(data_path.clone(), "20.db".to_string(), "/tmp/20.db".to_string(), None, None).unwrap();
+        let his: Vec<His> = get_open_history(data_path).unwrap().try_into().unwrap();
+        assert_eq!(his.len(), 20);
+        assert_eq!(his[19].path, "/tmp/1.db");
+        assert_eq!(fetch_key(&key_ref).unwrap(), None, "被移除条目的密钥应从密钥库删除");
+    }
+
+    #[test]
+    fn test_update_open_history_query_timeout() {
+        let _vault = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-timeout-his");
+        fs::create_dir_all(&data_path).unwrap();
+        empty_open_history(data_path.clone()).unwrap();
+
+        add_open_history(data_path.clone(), "a.db".to_string(), "/tmp/a.db".to_string(), None, None).unwrap();
+        update_open_history_query_timeout(data_path.clone(), "/tmp/a.db", Some(3000)).unwrap();
+        assert_eq!(get_open_history_entry(data_path.clone(), 0).unwrap().unwrap().query_timeout_ms, Some(3000));
+        update_open_history_query_timeout(data_path.clone(), "/tmp/a.db", None).unwrap();
+        assert_eq!(get_open_history_entry(data_path, 0).unwrap().unwrap().query_timeout_ms, None);
+    }
+
+    #[test]
+    fn test_update_open_history_key() {
+        let _vault = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-rekey-his");
+        fs::create_dir_all(&data_path).unwrap();
+        empty_open_history(data_path.clone()).unwrap();
+
+        add_open_history(data_path.clone(), "a.db".to_string(), "/tmp/a.db".to_string(), Some("123456".to_string()), None).unwrap();
+        add_open_history(data_path.clone(), "b.db".to_string(), "/tmp/b.db".to_string(), Some("123456".to_string()), None).unwrap();
+        update_open_history_key(data_path.clone(), "/tmp/a.db", Some("654321".to_string())).unwrap();
+        update_open_history_key(data_path.clone(), "/tmp/b.db", None).unwrap();
+
+        let b = get_open_history_entry(data_path.clone(), 0).unwrap().unwrap();
+        let a = get_open_history_entry(data_path, 1).unwrap().unwrap();
+        assert_eq!(resolve_history_key(&b).unwrap(), None);
+        assert_eq!(resolve_history_key(&a).unwrap(), Some("654321".to_string()));
+    }
+
+    #[test]
+    fn test_history_keys_in_vault() {
+        let _vault = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-vault-his");
+        fs::create_dir_all(&data_path).unwrap();
+        empty_open_history(data_path.clone()).unwrap();
+
+        // 旧版本以明文保存的密钥。
+        let legacy = "[[His]]\nname = \"old.db\"\npath = \"/tmp/old.db\"\nkey = \"123456\"\n";
+        write_content_to_file(data_path.join("history.toml"), &legacy.to_string()).unwrap();
+        assert_eq!(migrate_history_keys(data_path.clone()).unwrap(), 1);
+        add_open_history(data_path.clone(), "new.db".to_string(), "/tmp/new.db".to_string(), Some("654321".to_string()), None).unwrap();
+
+        let mut content = String::new();
+        File::open(data_path.join("history.toml")).unwrap().read_to_string(&mut content).unwrap();
+        assert!(!content.contains("123456") && !content.contains("654321"), "历史记录文件不应包含明文密钥");
+
+        let his = get_open_history(data_path.clone()).unwrap();
+        let sanitized = toml::to_string(&his.as_array().unwrap()[0]).unwrap();
+        assert!(!sanitized.contains("key") && sanitized.contains("encrypted = true"), "返回给前端的历史记录不应包含密钥及其引用");
+
+        let old = get_open_history_entry(data_path.clone(), 1).unwrap().unwrap();
+        assert_eq!(resolve_history_key(&old).unwrap(), Some("123456".to_string()));
+
+        forget_open_history_key(data_path.clone(), 1).unwrap();
+        let old = get_open_history_entry(data_path, 1).unwrap().unwrap();
+        assert!(old.key_ref.is_none());
+        assert_eq!(resolve_history_key(&old).unwrap(), None);
+    }
+
     #[test]
     fn test_clear_open_history() {
         let data_path = env::temp_dir();
@@ -297,6 +613,7 @@ mod tests {
 
     #[test]
     fn test_remove_open_history() {
+        let _vault = unlock_test_vault();
         let data_path = env::temp_dir();
         let result = remove_open_history(data_path, 1);
         match result {
@@ -0,0 +1,96 @@
+//! SQL标识符处理。拼接进SQL语句的表名、字段名须经`quote_ident`转换，行号须经`parse_rowid`转换，不得直接插值。
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug)]
+pub struct InvalidRowid(String);
+
+impl Display for InvalidRowid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "无效的rowid: {}", self.0)
+    }
+}
+
+impl Error for InvalidRowid {}
+
+/// 将表名、字段名等转换为带双引号的SQL标识符，标识符中的双引号转义为两个双引号。
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(quote_ident("order"), "\"order\"");
+/// assert_eq!(quote_ident("my \"t\""), "\"my \"\"t\"\"\"");
+/// ```
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// 字段约束的起始关键字，不能出现在类型名中。
+const CONSTRAINT_KEYWORDS: [&str; 11] = ["CONSTRAINT", "PRIMARY", "NOT", "NULL", "UNIQUE", "CHECK", "DEFAULT", "COLLATE", "REFERENCES", "GENERATED", "AS"];
+
+/// 检查字段类型名是否合法：一个或多个标识符，后面可以跟`(n)`或`(n, m)`，`n`、`m`为带符号的数字。
+///
+/// 类型名无法加引号，拼接进`CREATE TABLE`语句前须经此检查。
+///
+/// # Examples
+///
+/// ```
+/// assert!(is_type_name("VARCHAR(10)"));
+/// assert!(is_type_name("unsigned big int"));
+/// assert!(!is_type_name("TEXT); DROP TABLE users; --"));
+/// ```
+pub fn is_type_name(type_name: &str) -> bool {
+    let (names, size) = match type_name.find('(') {
+        Some(i) => (&type_name[..i], Some(&type_name[i + 1..])),
+        None => (type_name, None),
+    };
+    let is_word = |w: &str| w.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && w.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !CONSTRAINT_KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(w));
+    let is_number = |n: &str| {
+        let n = n.trim();
+        let n = n.strip_prefix(|c| c == '+' || c == '-').unwrap_or(n);
+        !n.is_empty() && n.parse::<f64>().is_ok() && n.chars().all(|c| c.is_ascii_digit() || c == '.')
+    };
+    let mut words = names.split_whitespace().peekable();
+    if words.peek().is_none() || !words.all(is_word) {
+        return false;
+    }
+    match size.map(|s| s.trim_end().strip_suffix(')')) {
+        None => true,
+        Some(None) => false,
+        Some(Some(args)) => {
+            let args: Vec<&str> = args.split(',').collect();
+            args.len() <= 2 && args.into_iter().all(is_number)
+        }
+    }
+}
+
+/// 将前端传入的rowid解析为整数。
+pub fn parse_rowid(rowid: &str) -> Result<i64, InvalidRowid> {
+    rowid.trim().parse::<i64>().map_err(|_| InvalidRowid(rowid.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_ident() {
+        assert_eq!(quote_ident("my_table"), "\"my_table\"");
+        assert_eq!(quote_ident("a\"; drop table t; --"), "\"a\"\"; drop table t; --\"");
+        assert_eq!(parse_rowid(" 42 ").unwrap(), 42);
+        assert!(parse_rowid("1) or (1=1").is_err());
+
+        assert!(is_type_name("INTEGER"));
+        assert!(is_type_name("varchar( 10 )"));
+        assert!(is_type_name("DECIMAL(10, -2)"));
+        assert!(is_type_name("unsigned big int"));
+        assert!(!is_type_name(""));
+        assert!(!is_type_name("TEXT); DROP TABLE users; --"));
+        assert!(!is_type_name("TEXT(1); DROP TABLE users; --)"));
+        assert!(!is_type_name("INT(1, 2, 3)"));
+        assert!(!is_type_name("TEXT DEFAULT 'x'"));
+        assert!(!is_type_name("TEXT -- comment"));
+    }
+}
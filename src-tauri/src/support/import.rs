@@ -0,0 +1,444 @@
+//! 从CSV/TSV文件导入数据：推断字段类型，按映射关系写入已有的表或新建的表，无法写入的行连同行号一并返回。
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+
+use rusqlite::{Connection, ErrorCode};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+
+use crate::support::csv::{CsvFormat, CsvReader, CsvRecord};
+use crate::support::executor::execute_single;
+use crate::support::ident::{is_type_name, quote_ident};
+
+/// 推断字段类型时最多读取的记录数。
+const INFER_ROWS: usize = 1000;
+/// 最多返回的被拒绝行数，超出的只计数。
+const MAX_REJECTED_REPORTED: usize = 1000;
+
+/// 推断出的字段类型。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum InferredType {
+    Integer,
+    Real,
+    Text,
+}
+
+impl InferredType {
+    /// 结合新的非空取值放宽类型。
+    fn widen(self, value: &str) -> InferredType {
+        let value = value.trim();
+        match self {
+            InferredType::Integer if value.parse::<i64>().is_ok() => InferredType::Integer,
+            InferredType::Integer | InferredType::Real if value.parse::<f64>().is_ok() => InferredType::Real,
+            _ => InferredType::Text,
+        }
+    }
+}
+
+/// CSV中的一个字段。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvColumn {
+    /// 字段在记录中的位置，从0开始。
+    index: usize,
+    /// 字段名，文件没有标题行或标题为空时为`column1`、`column2`等。
+    name: String,
+    inferred_type: InferredType,
+}
+
+/// CSV文件的预览，用于在导入前设置字段映射。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvPreview {
+    format: CsvFormat,
+    columns: Vec<CsvColumn>,
+    /// 开头的若干条数据记录。
+    rows: Vec<Vec<String>>,
+}
+
+/// CSV字段到表字段的映射。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    /// CSV字段的位置，从0开始。
+    pub source: usize,
+    /// 表字段名。
+    pub target: String,
+    /// 新建表时的字段类型，为空时使用推断的类型。
+    pub column_type: Option<String>,
+}
+
+/// 导入选项。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvImportOptions {
+    /// CSV格式，为空时按文件扩展名选择。
+    pub format: Option<CsvFormat>,
+    /// 字段映射，为空时按字段名(不区分大小写)匹配已有表的字段，新建表时使用全部CSV字段。
+    pub mappings: Option<Vec<ColumnMapping>>,
+    /// 目标表不存在时是否新建。
+    pub create_table: bool,
+    /// 是否将空字符串作为NULL写入。
+    pub empty_as_null: bool,
+    /// 有被拒绝的行时是否回滚全部数据。
+    pub all_or_nothing: bool,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        CsvImportOptions { format: None, mappings: None, create_table: false, empty_as_null: true, all_or_nothing: false }
+    }
+}
+
+/// 被拒绝的行。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RejectedRow {
+    /// 记录在文件中的起始行号，从1开始。
+    line: usize,
+    reason: String,
+}
+
+/// 导入结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvImportReport {
+    inserted: u64,
+    /// 被拒绝的行，最多返回1000条。
+    rejected: Vec<RejectedRow>,
+    rejected_count: u64,
+    created_table: bool,
+    /// 是否因`all_or_nothing`回滚了全部数据。
+    rolled_back: bool,
+}
+
+/// 按SQLITE的类型亲和性规则确定字段的转换方式。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Affinity {
+    Integer,
+    Real,
+    Numeric,
+    Text,
+}
+
+impl Affinity {
+    fn of(decl_type: &str) -> Affinity {
+        let t = decl_type.to_uppercase();
+        if t.contains("INT") {
+            Affinity::Integer
+        } else if t.contains("CHAR") || t.contains("CLOB") || t.contains("TEXT") || t.contains("BLOB") || t.is_empty() {
+            Affinity::Text
+        } else if t.contains("REAL") || t.contains("FLOA") || t.contains("DOUB") {
+            Affinity::Real
+        } else {
+            Affinity::Numeric
+        }
+    }
+
+    /// 将CSV字段值转换为SQLITE值，整数和浮点数字段不接受非数值文本。
+    fn convert(self, value: &str, empty_as_null: bool) -> Result<SqlValue, String> {
+        if value.is_empty() && empty_as_null {
+            return Ok(SqlValue::Null);
+        }
+        let trimmed = value.trim();
+        match self {
+            Affinity::Text => Ok(SqlValue::Text(value.to_string())),
+            _ if trimmed.parse::<i64>().is_ok() && self != Affinity::Real => Ok(SqlValue::Integer(trimmed.parse().unwrap_or_default())),
+            _ if trimmed.parse::<f64>().is_ok() => Ok(SqlValue::Real(trimmed.parse().unwrap_or_default())),
+            Affinity::Numeric => Ok(SqlValue::Text(value.to_string())),
+            Affinity::Integer => Err(format!("{} 不是整数", value)),
+            Affinity::Real => Err(format!("{} 不是数值", value)),
+        }
+    }
+}
+
+/// 已读取字段名的CSV文件。
+struct CsvSource {
+    reader: CsvReader<BufReader<File>>,
+    /// 字段名，文件没有标题行或标题为空时为`column1`、`column2`等。
+    names: Vec<String>,
+    /// 没有标题行时，为读取字段数而提前读取的第一条记录。
+    pending: Option<CsvRecord>,
+}
+
+impl CsvSource {
+    fn open(path: &str, format: &CsvFormat) -> Result<CsvSource, Box<dyn Error>> {
+        format.validate()?;
+        let mut reader = CsvReader::new(BufReader::new(File::open(path)?), format.clone());
+        let first = reader.next_record()?.ok_or("CSV文件为空")?;
+        let names: Vec<String> = first.fields.iter().enumerate().map(|(i, name)| {
+            let name = name.trim();
+            if format.has_header && !name.is_empty() { name.to_string() } else { format!("column{}", i + 1) }
+        }).collect();
+        let pending = if format.has_header { None } else { Some(first) };
+        Ok(CsvSource { reader, names, pending })
+    }
+
+    /// 读取下一条数据记录。
+    fn next_record(&mut self) -> Result<Option<CsvRecord>, Box<dyn Error>> {
+        match self.pending.take() {
+            Some(record) => Ok(Some(record)),
+            None => self.reader.next_record(),
+        }
+    }
+}
+
+/// 读取CSV文件开头至多`max_rows`条记录并推断字段类型，返回其中前`sample_rows`条记录。
+fn infer_columns(path: &str, format: CsvFormat, max_rows: usize, sample_rows: usize) -> Result<CsvPreview, Box<dyn Error>> {
+    let mut source = CsvSource::open(path, &format)?;
+    // 没有任何非空值的字段按文本处理。
+    let mut types: Vec<Option<InferredType>> = vec![None; source.names.len()];
+    let mut rows = vec![];
+    for _ in 0..max_rows {
+        let record = match source.next_record()? {
+            Some(record) => record,
+            None => break,
+        };
+        for (t, value) in types.iter_mut().zip(record.fields.iter()) {
+            if !value.trim().is_empty() {
+                *t = Some(t.unwrap_or(InferredType::Integer).widen(value));
+            }
+        }
+        if rows.len() < sample_rows {
+            rows.push(record.fields);
+        }
+    }
+
+    let columns = source.names.into_iter().zip(types).enumerate().map(|(index, (name, t))| {
+        CsvColumn { index, name, inferred_type: t.unwrap_or(InferredType::Text) }
+    }).collect();
+    Ok(CsvPreview { format, columns, rows })
+}
+
+/// 预览CSV文件：读取字段名、推断字段类型并返回开头的若干条记录。
+///
+/// # Arguments
+///
+/// * `path`: CSV或TSV文件路径。
+/// * `format`: CSV格式，为空时按文件扩展名选择。
+/// * `sample_rows`: 返回的记录数。
+///
+/// returns: Result<CsvPreview, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let preview = preview_csv("/home/foo/tmp/users.csv", None, 20).unwrap();
+/// ```
+pub fn preview_csv(path: &str, format: Option<CsvFormat>, sample_rows: usize) -> Result<CsvPreview, Box<dyn Error>> {
+    infer_columns(path, format.unwrap_or_else(|| CsvFormat::for_path(path)), INFER_ROWS, sample_rows)
+}
+
+/// 查询表的字段名和声明类型，表不存在时返回空数组。
+fn table_columns(conn: &Connection, table_name: &str) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select name, type from pragma_table_info(?1)")?;
+    let columns = stmt.query_map([table_name], |r| Ok((r.get(0)?, r.get(1)?)))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+/// 是否为单行数据导致的错误：违反约束或类型不符。
+fn is_row_error(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(f, _) if f.code == ErrorCode::ConstraintViolation || f.code == ErrorCode::TypeMismatch)
+}
+
+/// 将CSV文件导入到表中。
+///
+/// 所有数据在同一个事务中写入，插入语句只编译一次并重复执行。字段数不符、数值无法转换或违反约束的行被拒绝并记录行号，
+/// 其余行正常写入；`all_or_nothing`为`true`时有任何行被拒绝即回滚。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的rusqlite连接。
+/// * `table_name`: 目标表名。
+/// * `path`: CSV或TSV文件路径。
+/// * `options`: 导入选项。
+///
+/// returns: Result<CsvImportReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let options = CsvImportOptions { create_table: true, ..Default::default() };
+/// let report = import_csv(&conn, "users", "/home/foo/tmp/users.csv", &options).unwrap();
+/// ```
+pub fn import_csv(conn: &Connection, table_name: &str, path: &str, options: &CsvImportOptions) -> Result<CsvImportReport, Box<dyn Error>> {
+    let format = options.format.clone().unwrap_or_else(|| CsvFormat::for_path(path));
+    let existing = table_columns(conn, table_name)?;
+    let create_table = existing.is_empty();
+    if create_table && !options.create_table {
+        return Err(format!("表 {} 不存在", table_name).into());
+    }
+
+    /*
+    确定每个目标字段对应的CSV字段及转换方式。
+     */
+    let mut source = CsvSource::open(path, &format)?;
+    let names = source.names.clone();
+    let mut targets: Vec<(usize, String, Affinity)> = vec![];
+    let mut create_sql = None;
+    if create_table {
+        // 与预览使用相同的推断范围，新建表的字段类型与用户在预览中确认的一致。
+        let columns = infer_columns(path, format.clone(), INFER_ROWS, 0)?.columns;
+        let mappings = options.mappings.clone().unwrap_or_else(|| columns.iter().map(|c| ColumnMapping {
+            source: c.index,
+            target: c.name.clone(),
+            column_type: None,
+        }).collect());
+        let mut definitions = vec![];
+        for m in mappings {
+            let column = columns.get(m.source).ok_or_else(|| format!("CSV中不存在第 {} 个字段", m.source + 1))?;
+            let decl_type = match m.column_type.filter(|t| !t.trim().is_empty()) {
+                Some(t) if is_type_name(&t) => t,
+                Some(t) => return Err(format!("无效的字段类型: {}", t).into()),
+                None => format!("{:?}", column.inferred_type).to_uppercase(),
+            };
+            definitions.push(format!("{} {}", quote_ident(&m.target), decl_type));
+            targets.push((m.source, m.target, Affinity::of(&decl_type)));
+        }
+        create_sql = Some(format!("create table {} ({})", quote_ident(table_name), definitions.join(", ")));
+    } else {
+        let find = |name: &str| existing.iter().find(|(n, _)| n.eq_ignore_ascii_case(name));
+        match &options.mappings {
+            Some(mappings) => {
+                for m in mappings {
+                    if m.source >= names.len() {
+                        return Err(format!("CSV中不存在第 {} 个字段", m.source + 1).into());
+                    }
+                    let (name, decl_type) = find(&m.target).ok_or_else(|| format!("表 {} 中不存在字段 {}", table_name, m.target))?;
+                    targets.push((m.source, name.clone(), Affinity::of(decl_type)));
+                }
+            }
+            None => {
+                for (source, csv_name) in names.iter().enumerate() {
+                    if let Some((name, decl_type)) = find(csv_name) {
+                        targets.push((source, name.clone(), Affinity::of(decl_type)));
+                    }
+                }
+            }
+        }
+    }
+    if targets.is_empty() {
+        return Err(format!("CSV中没有与表 {} 对应的字段", table_name).into());
+    }
+
+    /*
+    在同一个事务中逐行写入。
+     */
+    let tx = conn.unchecked_transaction()?;
+    if let Some(sql) = &create_sql {
+        execute_single(&tx, sql)?;
+    }
+    let columns: Vec<String> = targets.iter().map(|(_, name, _)| quote_ident(name)).collect();
+    let insert = format!("insert into {} ({}) values ({})", quote_ident(table_name), columns.join(", "), vec!["?"; columns.len()].join(", "));
+    let mut stmt = tx.prepare(&insert)?;
+
+    let mut report = CsvImportReport { inserted: 0, rejected: vec![], rejected_count: 0, created_table: create_table, rolled_back: false };
+    let mut reject = |line: usize, reason: String| {
+        report.rejected_count += 1;
+        if report.rejected.len() < MAX_REJECTED_REPORTED {
+            report.rejected.push(RejectedRow { line, reason });
+        }
+    };
+    let mut inserted = 0;
+    while let Some(record) = source.next_record()? {
+        if record.fields.len() != names.len() {
+            reject(record.line, format!("字段数为 {}，应为 {}", record.fields.len(), names.len()));
+            continue;
+        }
+        let values: Result<Vec<SqlValue>, String> = targets.iter().map(|(source, name, affinity)| {
+            affinity.convert(&record.fields[*source], options.empty_as_null).map_err(|e| format!("字段 {}: {}", name, e))
+        }).collect();
+        match values {
+            Ok(values) => match stmt.execute(rusqlite::params_from_iter(values)) {
+                Ok(_) => inserted += 1,
+                Err(e) if is_row_error(&e) => reject(record.line, e.to_string()),
+                // 数据库忙、磁盘已满等错误与数据无关，中止导入并回滚。
+                Err(e) => return Err(e.into()),
+            },
+            Err(e) => reject(record.line, e),
+        }
+    }
+    drop(stmt);
+
+    report.inserted = inserted;
+    if options.all_or_nothing && report.rejected_count > 0 {
+        tx.rollback()?;
+        report.inserted = 0;
+        report.created_table = false;
+        report.rolled_back = true;
+    } else {
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_csv(name: &str, content: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_import_csv() {
+        let path = write_csv("sqlcipher-front-import.csv", "ID,Name,Score,Note\n1,Tom,9.5,\n2,Jerry,7,\"a, b\"\nx,Spike,8,\n4,Tyke\n5,\"Butch\",6,ok\n");
+        let preview = preview_csv(&path, None, 2).unwrap();
+        let types: Vec<InferredType> = preview.columns.iter().map(|c| c.inferred_type).collect();
+        assert_eq!(types, vec![InferredType::Text, InferredType::Text, InferredType::Real, InferredType::Text]);
+        assert_eq!(preview.rows.len(), 2);
+
+        // 新建表。
+        let conn = Connection::open_in_memory().unwrap();
+        let report = import_csv(&conn, "scores", &path, &CsvImportOptions { create_table: true, ..Default::default() }).unwrap();
+        assert!(report.created_table);
+        assert_eq!((report.inserted, report.rejected_count), (4, 1));
+        assert_eq!(report.rejected[0].line, 5);
+        let sql: String = conn.query_row("select sql from sqlite_master where name = 'scores'", [], |r| r.get(0)).unwrap();
+        assert_eq!(sql, "CREATE TABLE \"scores\" (\"ID\" TEXT, \"Name\" TEXT, \"Score\" REAL, \"Note\" TEXT)");
+
+        // 写入已有的表，按字段名匹配，非整数的id被拒绝。
+        conn.execute_batch("create table users (id integer primary key, name text not null, extra text)").unwrap();
+        let report = import_csv(&conn, "users", &path, &CsvImportOptions::default()).unwrap();
+        assert_eq!((report.inserted, report.rejected_count), (3, 2));
+        let lines: Vec<usize> = report.rejected.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![4, 5]);
+        assert!(report.rejected[0].reason.contains("id"), "{}", report.rejected[0].reason);
+        // 重复的主键违反约束。
+        let report = import_csv(&conn, "users", &path, &CsvImportOptions::default()).unwrap();
+        assert_eq!(report.inserted, 0);
+
+        // 按映射写入，有被拒绝的行时全部回滚。
+        conn.execute_batch("create table notes (who text, body text)").unwrap();
+        let mappings = Some(vec![
+            ColumnMapping { source: 1, target: "WHO".to_string(), column_type: None },
+            ColumnMapping { source: 3, target: "body".to_string(), column_type: None },
+        ]);
+        let options = CsvImportOptions { mappings: mappings.clone(), all_or_nothing: true, ..Default::default() };
+        let report = import_csv(&conn, "notes", &path, &options).unwrap();
+        assert!(report.rolled_back);
+        let count: i64 = conn.query_row("select count(*) from notes", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 0);
+        let report = import_csv(&conn, "notes", &path, &CsvImportOptions { mappings: mappings.clone(), ..Default::default() }).unwrap();
+        assert_eq!(report.inserted, 4);
+        let body: Option<String> = conn.query_row("select body from notes where who = 'Jerry'", [], |r| r.get(0)).unwrap();
+        assert_eq!(body.as_deref(), Some("a, b"));
+
+        assert!(import_csv(&conn, "missing", &path, &CsvImportOptions::default()).is_err());
+
+        // 字段类型不合法时不建表。
+        let evil = Some(vec![ColumnMapping { source: 0, target: "id".to_string(), column_type: Some("TEXT); DROP TABLE users; --".to_string()) }]);
+        let options = CsvImportOptions { mappings: evil, create_table: true, ..Default::default() };
+        assert!(import_csv(&conn, "evil", &path, &options).is_err());
+        let count: i64 = conn.query_row("select count(*) from sqlite_master where name in ('evil', 'users')", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 1);
+
+        // 与数据无关的错误中止导入。
+        conn.execute_batch("pragma query_only = 1").unwrap();
+        assert!(import_csv(&conn, "notes", &path, &CsvImportOptions { mappings, ..Default::default() }).is_err());
+        conn.execute_batch("pragma query_only = 0").unwrap();
+        let count: i64 = conn.query_row("select count(*) from notes", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 4);
+    }
+}
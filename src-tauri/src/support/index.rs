@@ -0,0 +1,361 @@
+//! 索引管理：列出、创建和删除索引，并通过`EXPLAIN QUERY PLAN`分析查询语句对索引的使用情况。
+use std::collections::HashMap;
+use std::error::Error;
+
+use lazy_regex::regex_captures;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::support::ddl::{collation_name, is_expr_fragment, restore_double_quoted_strings, set_double_quoted_strings, sql_words};
+use crate::support::executor::execute_single;
+use crate::support::ident::quote_ident;
+use crate::support::schema::{IndexInfo, load_indexes};
+
+/// 某张表上的索引。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub table_name: String,
+    #[serde(flatten)]
+    pub index: IndexInfo,
+}
+
+/// 索引的一个键，`column`和`expression`须且只能设置其中一个。
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexKey {
+    pub column: Option<String>,
+    /// 表达式索引的SQL表达式，如`lower(email)`。
+    pub expression: Option<String>,
+    pub collation: Option<String>,
+    pub desc: bool,
+}
+
+/// 新建索引的定义。
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexSpec {
+    pub name: String,
+    pub table_name: String,
+    pub keys: Vec<IndexKey>,
+    pub unique: bool,
+    /// 部分索引的`WHERE`条件。
+    pub where_clause: Option<String>,
+}
+
+/// 一个索引被分析的语句使用的次数。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexUsage {
+    pub index_name: String,
+    pub table_name: String,
+    /// 使用该索引的语句数，为0时该索引未被任何语句使用。
+    pub query_count: usize,
+}
+
+/// 语句中对表的全表扫描，通常可通过建立索引避免。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FullScan {
+    pub query: String,
+    pub table_name: String,
+    /// `EXPLAIN QUERY PLAN`的原始信息。
+    pub detail: String,
+    /// 可考虑建立索引的字段，取自语句`WHERE`和`ON`之后出现的该表字段。
+    pub candidate_columns: Vec<String>,
+    /// SQLITE是否为该语句临时建立了自动索引，是则说明相应字段上缺少索引。
+    pub automatic_index: bool,
+}
+
+/// 无法分析的语句，例如引用了已删除的表。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanError {
+    pub query: String,
+    pub message: String,
+}
+
+/// 索引使用情况的分析结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IndexUsageReport {
+    /// 分析的语句数。
+    pub queries: usize,
+    /// 用户创建的索引，不含主键和UNIQUE约束自动创建的索引。
+    pub indexes: Vec<IndexUsage>,
+    pub full_scans: Vec<FullScan>,
+    pub errors: Vec<PlanError>,
+}
+
+/// 列出索引。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `table_name`: 只列出该表的索引，为`None`时列出所有表的索引。
+///
+/// returns: Result<Vec<IndexEntry, Global>, Box<dyn Error, Global>>
+pub fn list_indexes(conn: &Connection, table_name: Option<&str>) -> Result<Vec<IndexEntry>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select name from sqlite_master where type = 'table' and name not like 'sqlite_%' and (?1 is null or name = ?1 collate nocase) order by name")?;
+    let tables = stmt.query_map([table_name], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    if tables.is_empty() {
+        if let Some(table_name) = table_name {
+            return Err(format!("表 {} 不存在", table_name).into());
+        }
+    }
+    let mut entries = vec![];
+    for table in tables {
+        for index in load_indexes(conn, &table)? {
+            entries.push(IndexEntry { table_name: table.clone(), index });
+        }
+    }
+    Ok(entries)
+}
+
+/// 根据索引定义生成`CREATE INDEX`语句。表达式和`WHERE`条件须为完整的表达式，不能包含分号、注释或不配对的括号。
+pub fn create_index_sql(spec: &IndexSpec) -> Result<String, Box<dyn Error>> {
+    if spec.name.trim().is_empty() || spec.table_name.trim().is_empty() {
+        return Err("索引名和表名不能为空".into());
+    }
+    if spec.keys.is_empty() {
+        return Err("索引至少需要一个字段或表达式".into());
+    }
+    let mut keys = vec![];
+    for key in spec.keys.iter() {
+        let column = key.column.as_ref().filter(|c| !c.trim().is_empty());
+        let expression = key.expression.as_ref().filter(|e| !e.trim().is_empty());
+        let mut sql = match (column, expression) {
+            (Some(column), None) => quote_ident(column),
+            (None, Some(expression)) if is_expr_fragment(expression) => format!("({})", expression.trim()),
+            (None, Some(expression)) => return Err(format!("索引表达式 {} 无效", expression).into()),
+            _ => return Err("索引的每个键须且只能指定字段或表达式之一".into()),
+        };
+        if let Some(collation) = key.collation.as_ref().filter(|c| !c.trim().is_empty()) {
+            sql.push_str(&format!(" COLLATE {}", collation_name(collation.trim())));
+        }
+        if key.desc {
+            sql.push_str(" DESC");
+        }
+        keys.push(sql);
+    }
+    let mut sql = format!("CREATE {}INDEX {} ON {} ({})", if spec.unique { "UNIQUE " } else { "" }, quote_ident(&spec.name), quote_ident(&spec.table_name), keys.join(", "));
+    if let Some(where_clause) = spec.where_clause.as_ref().filter(|w| !w.trim().is_empty()) {
+        if !is_expr_fragment(where_clause) {
+            return Err(format!("索引条件 {} 无效", where_clause).into());
+        }
+        sql.push_str(&format!(" WHERE {}", where_clause.trim()));
+    }
+    Ok(sql)
+}
+
+/// 创建索引。
+///
+/// 创建时关闭双引号字符串字面量，引用了不存在的字段时报错。生成的语句只作为一条语句编译执行。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `spec`: 索引定义。
+/// * `execute`: 为`true`时建立索引，否则在事务中试建后回滚，只返回语句。
+///
+/// returns: Result<String, Box<dyn Error, Global>> 返回`CREATE INDEX`语句。建立唯一索引时已有数据重复会返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let mut conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let spec = IndexSpec {
+///     name: "idx_users_email".to_string(),
+///     table_name: "users".to_string(),
+///     keys: vec![IndexKey { expression: Some("lower(email)".to_string()), ..IndexKey::default() }],
+///     ..IndexSpec::default()
+/// };
+/// create_index(&mut conn, &spec, true).unwrap();
+/// ```
+pub fn create_index(conn: &mut Connection, spec: &IndexSpec, execute: bool) -> Result<String, Box<dyn Error>> {
+    let sql = create_index_sql(spec)?;
+    let dqs = set_double_quoted_strings(conn, false)?;
+    let result = conn.transaction().map_err(|e| e.into()).and_then(|tx| -> Result<(), Box<dyn Error>> {
+        execute_single(&tx, &sql)?;
+        if execute {
+            tx.commit()?;
+        }
+        Ok(())
+    });
+    let restored = restore_double_quoted_strings(conn, dqs);
+    result?;
+    restored?;
+    Ok(sql)
+}
+
+/// 删除用户创建的索引。主键和UNIQUE约束自动创建的索引不能删除。
+pub fn drop_index(conn: &Connection, name: &str) -> Result<(), Box<dyn Error>> {
+    let found: Option<String> = conn.query_row("select name from sqlite_master where type = 'index' and name = ?1 collate nocase", [name], |r| r.get(0)).optional()?;
+    let name = found.ok_or_else(|| format!("索引 {} 不存在", name))?;
+    conn.execute_batch(&format!("DROP INDEX {}", quote_ident(&name)))?;
+    Ok(())
+}
+
+/// 将查询计划中的表别名还原为表名：在语句中查找`表名 [AS] 别名`。
+fn resolve_table(name: &str, words: &[String], tables: &HashMap<String, Vec<String>>) -> Option<String> {
+    let is_table = |w: &str| tables.keys().find(|t| t.eq_ignore_ascii_case(w)).cloned();
+    if let Some(table) = is_table(name) {
+        return Some(table);
+    }
+    words.iter().enumerate().filter(|(_, w)| w.eq_ignore_ascii_case(name)).find_map(|(i, _)| {
+        let before = words.get(i.checked_sub(1)?)?;
+        if before.eq_ignore_ascii_case("as") { is_table(words.get(i.checked_sub(2)?)?) } else { is_table(before) }
+    })
+}
+
+/// 语句中`WHERE`或`ON`之后出现的、属于该表的字段。
+fn filter_columns(words: &[String], columns: &[String]) -> Vec<String> {
+    let start = match words.iter().position(|w| w.eq_ignore_ascii_case("where") || w.eq_ignore_ascii_case("on")) {
+        Some(start) => start,
+        None => return vec![],
+    };
+    columns.iter().filter(|c| words[start..].iter().any(|w| w.eq_ignore_ascii_case(c))).cloned().collect()
+}
+
+/// 分析语句对索引的使用情况：对每条语句执行`EXPLAIN QUERY PLAN`，统计各索引被使用的次数，并找出按条件过滤却扫描全表的语句。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `queries`: 要分析的语句，通常为最近执行过的语句。带参数的语句按参数为`NULL`分析。
+///
+/// returns: Result<IndexUsageReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let report = analyze_index_usage(&conn, &["select * from users where email = ?".to_string()]).unwrap();
+/// for index in report.indexes.iter().filter(|i| i.query_count == 0) {
+///     println!("未使用的索引: {}", index.index_name);
+/// }
+/// ```
+pub fn analyze_index_usage(conn: &Connection, queries: &[String]) -> Result<IndexUsageReport, Box<dyn Error>> {
+    let mut indexes: Vec<IndexUsage> = list_indexes(conn, None)?.into_iter()
+        .filter(|e| e.index.origin == "c")
+        .map(|e| IndexUsage { index_name: e.index.name, table_name: e.table_name, query_count: 0 })
+        .collect();
+    let mut stmt = conn.prepare("select m.name, c.name from sqlite_master m join pragma_table_info(m.name) c where m.type = 'table' order by m.name, c.cid")?;
+    let mut tables: HashMap<String, Vec<String>> = HashMap::new();
+    for row in stmt.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))? {
+        let (table, column) = row?;
+        tables.entry(table).or_default().push(column);
+    }
+
+    let mut report = IndexUsageReport { queries: queries.len(), indexes: vec![], full_scans: vec![], errors: vec![] };
+    for query in queries {
+        let details = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", query)).and_then(|mut stmt| {
+            let mut rows = stmt.raw_query();
+            let mut details = vec![];
+            while let Some(row) = rows.next()? {
+                details.push(row.get::<_, String>(3)?);
+            }
+            Ok(details)
+        });
+        let details = match details {
+            Ok(details) => details,
+            Err(e) => {
+                report.errors.push(PlanError { query: query.clone(), message: e.to_string() });
+                continue;
+            }
+        };
+
+        let words = sql_words(query);
+        for index in indexes.iter_mut() {
+            let used = details.iter().any(|d| d.ends_with(&format!("INDEX {}", index.index_name)) || d.contains(&format!("INDEX {} (", index.index_name)));
+            if used {
+                index.query_count += 1;
+            }
+        }
+        for detail in details.iter() {
+            let scan = regex_captures!(r"^SCAN (?:TABLE )?(\S+)$", detail).map(|(_, name)| (name, None));
+            let automatic = regex_captures!(r"^SEARCH (?:TABLE )?(\S+) USING AUTOMATIC (?:COVERING )?INDEX \((.*)\)$", detail).map(|(_, name, keys)| (name, Some(keys)));
+            let (name, keys) = match scan.or(automatic) {
+                Some(found) => found,
+                None => continue,
+            };
+            let table_name = match resolve_table(name, &words, &tables) {
+                Some(table_name) => table_name,
+                None => continue,
+            };
+            let candidate_columns = match keys {
+                // 自动索引的键形如`a=? AND b=?`。
+                Some(keys) => keys.split(" AND ").filter_map(|k| regex_captures!(r"^(\w+)", k).map(|(_, c)| c.to_string())).collect(),
+                None => filter_columns(&words, &tables[&table_name]),
+            };
+            // 没有过滤条件的全表扫描无法通过索引避免。
+            if candidate_columns.is_empty() {
+                continue;
+            }
+            report.full_scans.push(FullScan { query: query.clone(), table_name, detail: detail.clone(), candidate_columns, automatic_index: keys.is_some() });
+        }
+    }
+    report.indexes = indexes;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manage_indexes() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key, email text unique, name text, age int);
+            create table orders (id integer primary key, user_id integer, amount real);
+            insert into users values (1, 'A@x', 'a', 20), (2, 'a@x', 'b', 30);
+        ").unwrap();
+        let key = |column: &str| IndexKey { column: Some(column.to_string()), ..IndexKey::default() };
+        let mut spec = IndexSpec {
+            name: "idx users email".to_string(),
+            table_name: "users".to_string(),
+            keys: vec![IndexKey { expression: Some("lower(email)".to_string()), ..IndexKey::default() }, IndexKey { desc: true, ..key("age") }],
+            unique: true,
+            where_clause: Some("age > 0".to_string()),
+        };
+        let sql = create_index(&mut conn, &spec, false).unwrap();
+        assert_eq!(sql, "CREATE UNIQUE INDEX \"idx users email\" ON \"users\" ((lower(email)), \"age\" DESC) WHERE age > 0");
+        assert_eq!(list_indexes(&conn, Some("users")).unwrap().len(), 1, "试建后应回滚，只有UNIQUE约束的索引");
+
+        spec.keys.pop();
+        assert!(create_index(&mut conn, &spec, true).is_err(), "lower(email)有重复值");
+        spec.unique = false;
+        create_index(&mut conn, &spec, true).unwrap();
+        let entries = list_indexes(&conn, Some("USERS")).unwrap();
+        let entry = entries.iter().find(|e| e.index.name == "idx users email").unwrap();
+        assert!(entry.index.partial && entry.index.columns[0].name.is_none());
+
+        assert!(create_index(&mut conn, &IndexSpec { name: "idx_bad".to_string(), keys: vec![key("missing")], ..spec.clone() }, true).is_err());
+        assert!(create_index(&mut conn, &IndexSpec { name: "idx_bad".to_string(), keys: vec![IndexKey::default()], ..spec.clone() }, true).is_err());
+        let injected = IndexSpec { name: "idx_bad".to_string(), where_clause: Some("1; DROP TABLE orders".to_string()), ..spec.clone() };
+        assert!(create_index(&mut conn, &injected, true).is_err());
+        let injected = IndexSpec { name: "idx_bad".to_string(), keys: vec![IndexKey { expression: Some("1)); DROP TABLE orders; --".to_string()), ..IndexKey::default() }], ..spec.clone() };
+        assert!(create_index(&mut conn, &injected, true).is_err());
+        assert!(list_indexes(&conn, Some("orders")).is_ok(), "不应执行多余的语句");
+        create_index(&mut conn, &IndexSpec { name: "idx_orders_amount".to_string(), table_name: "orders".to_string(), keys: vec![key("amount")], ..IndexSpec::default() }, true).unwrap();
+        assert_eq!(list_indexes(&conn, None).unwrap().len(), 3);
+        assert!(list_indexes(&conn, Some("missing")).is_err());
+
+        let queries: Vec<String> = vec![
+            "select * from users where lower(email) = ? and age > 0",
+            "select * from users u join orders o on o.user_id = u.id where o.amount > 10",
+            "select * from users where name = 'a'",
+            "select * from orders",
+            "select * from missing",
+        ].into_iter().map(|q| q.to_string()).collect();
+        let report = analyze_index_usage(&conn, &queries).unwrap();
+        assert_eq!(report.queries, 5);
+        assert_eq!(report.errors.len(), 1);
+        let usage: Vec<(&str, usize)> = report.indexes.iter().map(|i| (i.index_name.as_str(), i.query_count)).collect();
+        assert_eq!(usage, vec![("idx_orders_amount", 1), ("idx users email", 1)]);
+        let scan = report.full_scans.iter().find(|s| s.query.contains("name = 'a'")).unwrap();
+        assert_eq!((scan.table_name.as_str(), scan.candidate_columns.clone(), scan.automatic_index), ("users", vec!["name".to_string()], false));
+        assert!(!report.full_scans.iter().any(|s| s.query == "select * from orders"), "没有过滤条件的全表扫描不应报告");
+
+        drop_index(&conn, "IDX USERS EMAIL").unwrap();
+        assert!(drop_index(&conn, "idx users email").is_err());
+        let autoindex = list_indexes(&conn, Some("users")).unwrap().remove(0).index.name;
+        assert!(drop_index(&conn, &autoindex).is_err(), "UNIQUE约束的索引不能删除");
+    }
+}
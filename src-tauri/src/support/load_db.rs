@@ -1,20 +1,53 @@
 //! 请描述文件用途。
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind};
 use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use api_resp::{ApiResp, DaoResult, rollback};
 use once_cell::sync::Lazy;
 use rbatis::Rbatis;
 use rbdc::db::ConnectOptions;
 use rbdc_sqlite::driver::SqliteDriver;
-use rbdc_sqlite::SqliteConnectOptions;
-use rbs::{to_value, Value};
-use rusqlite::Connection;
+use rbdc_sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use rbs::to_value;
+use rusqlite::{Connection, DatabaseName, OptionalExtension};
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::types::ValueRef;
 use serde::{Deserialize, Serialize};
 
+use crate::support::alter::alter_table;
+use crate::support::blob::{BlobEncoding, DEFAULT_MAX_BYTES, export_blob, import_blob, read_blob, write_blob};
+use crate::support::cipher::{CipherPreset, CipherProfile};
+use crate::support::cursor::{close_db_cursors, open_cursor};
+use crate::support::db_info::{apply_pragma, JournalMode, PragmaUpdate, read_db_info};
+use crate::support::ddl::{create_table, read_table_spec, TableSpec};
+use crate::support::dump::{dump_db, DumpOptions, restore_dump};
+use crate::support::executor::{bind_json_params, clear_recent_queries, ColumnDesc, describe_columns, execute_cancellable, prepare_single, recent_queries, record_recent_query, sql_to_json};
+use crate::support::export::{DataExportProgressFn, export_query, ExportOptions, ExportSource};
+use crate::support::ident::{parse_rowid, quote_ident};
+use crate::support::health::{check_health, CheckKind, DEFAULT_MAX_ERRORS};
+use crate::support::import::{CsvImportOptions, import_csv};
+use crate::support::index::{analyze_index_usage, create_index, drop_index, IndexSpec, list_indexes};
+use crate::support::maintenance::{MaintenanceOp, run_maintenance, space_usage};
+use crate::support::objects::{list_triggers, replace_object};
+use crate::support::schema::describe_object;
+use crate::support::vault::VaultLocked;
+
 static OPENED_DBS: Lazy<Mutex<HashMap<String, Arc<Rbatis>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 各数据库文件最近一次打开时使用的密钥和加密参数，连接池重建或调用方未提供密钥时沿用。
+static DB_SECRETS: Lazy<Mutex<HashMap<String, DbSecret>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 通过`update_db_pragma`设置的外键约束开关。外键约束只对单个连接有效，之后新建的连接池和rusqlite连接均按此设置。
+static DB_FOREIGN_KEYS: Lazy<Mutex<HashMap<String, bool>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// 通过`update_db_pragma`设置的日志模式。连接池默认将每个连接切换为WAL模式，重建的连接池须按此设置，避免还原用户的修改。
+static DB_JOURNAL_MODES: Lazy<Mutex<HashMap<String, JournalMode>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 // static COLUMN_NAME_REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"\((.+)\)").unwrap());
 // static CREATE_VIEW_REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i)CREATE\s+VIEW").unwrap());
 
@@ -30,18 +63,73 @@ pub struct SqliteMeta {
 impl_select!(SqliteMeta{select_cols(table_column: &str) => "`order by name"}, "sqlite_master");
 
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct TableInfo {
-    data_type: String,
-    name: String,
+#[derive(Clone, Debug, Default, PartialEq)]
+struct DbSecret {
+    key: Option<String>,
+    cipher: CipherProfile,
+}
+
+/// 打开数据库失败的具体原因，`code()`作为接口响应的错误代码返回给前端。
+#[derive(Debug)]
+pub enum OpenDbError {
+    /// 密钥或加密参数错误，或者文件不是数据库。
+    WrongKey(String),
+    FileMissing(String),
+    PermissionDenied(String),
+    /// 数据库被其它进程锁定。
+    Locked(String),
+}
+
+impl OpenDbError {
+    pub fn code(&self) -> i32 {
+        match self {
+            OpenDbError::WrongKey(_) => 1001,
+            OpenDbError::FileMissing(_) => 1002,
+            OpenDbError::PermissionDenied(_) => 1003,
+            OpenDbError::Locked(_) => 1004,
+        }
+    }
+}
+
+impl Display for OpenDbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenDbError::WrongKey(path) => write!(f, "无法读取数据库 {}，密钥错误或文件不是数据库", path),
+            OpenDbError::FileMissing(path) => write!(f, "数据库文件 {} 不存在", path),
+            OpenDbError::PermissionDenied(path) => write!(f, "没有访问数据库文件 {} 的权限", path),
+            OpenDbError::Locked(path) => write!(f, "数据库 {} 已被其它程序锁定", path),
+        }
+    }
+}
+
+impl Error for OpenDbError {}
+
+/// 获取错误对应的接口错误代码，打开数据库失败时返回`OpenDbError`的代码，密钥库未解锁时返回`VaultLocked`的代码，其余错误返回-1。
+pub fn error_code(e: &(dyn Error + 'static)) -> i32 {
+    if let Some(e) = e.downcast_ref::<OpenDbError>() {
+        return e.code();
+    }
+    e.downcast_ref::<VaultLocked>().map_or(-1, VaultLocked::code)
+}
+
+/// 将错误转换为可以跨线程传递的类型，保留带有错误代码的`OpenDbError`和`VaultLocked`，其余错误转换为文本。
+fn sendable_error(e: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    let e = match e.downcast::<OpenDbError>() {
+        Ok(e) => return e,
+        Err(e) => e,
+    };
+    match e.downcast::<VaultLocked>() {
+        Ok(e) => e,
+        Err(e) => e.to_string().into(),
+    }
 }
 
-/// 打开或重新获取SQLITE数据库连接。
+/// 打开或重新获取SQLITE数据库连接，沿用该文件最近一次使用的加密参数。
 ///
 /// # Arguments
 ///
 /// * `db_path`: 数据库文件路径。
-/// * `key`: 可选的密钥字符串。
+/// * `key`: 可选的密钥字符串，为`None`时沿用该文件最近一次使用的密钥。
 ///
 /// returns: Result<Arc<Rbatis>, Box<dyn Error, Global>>
 ///
@@ -55,22 +143,70 @@ pub struct TableInfo {
 /// rb.exec("create table if not exists my_table (id text, name text, age integer)", vec![]).await.unwrap();
 /// ```
 pub fn open_db_connections(db_path: &String, key: &Option<String>) -> Result<Arc<Rbatis>, Box<dyn Error>> {
+    open_db_connections_with_cipher(db_path, key, &None)
+}
+
+/// 按指定的加密参数打开或重新获取SQLITE数据库连接。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥字符串，为`None`时沿用该文件最近一次使用的密钥。
+/// * `cipher`: 可选的加密参数，为`None`时沿用该文件最近一次使用的加密参数。
+///
+/// 密钥或加密参数与已缓存连接池使用的不同时，将重建连接池。创建连接池之前先校验密钥能否正确读取数据库，
+/// 失败时返回`OpenDbError`，并保留原有的连接池和密钥。
+///
+/// returns: Result<Arc<Rbatis>, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// use crate::support::cipher::{CipherPreset, CipherProfile};
+/// let cipher = Some(CipherProfile { preset: CipherPreset::Sqlcipher3, ..Default::default() });
+/// let conn_ref = open_db_connections_with_cipher(&"/home/foo/tmp/sqlite/v3.db".to_string(), &Some("123456".to_string()), &cipher).unwrap();
+/// ```
+pub fn open_db_connections_with_cipher(db_path: &String, key: &Option<String>, cipher: &Option<CipherProfile>) -> Result<Arc<Rbatis>, Box<dyn Error>> {
     let map_key = db_path.clone();
+    if !Path::new(db_path).exists() {
+        OPENED_DBS.lock()?.remove(&map_key);
+        return Err(OpenDbError::FileMissing(db_path.clone()).into());
+    }
 
-    /*
-    使用ruqlite绑定的sqlipher包，自动创建加密库文件。
-     */
-    let conn = Connection::open(db_path).unwrap();
-    if let Some(key) = key {
-        conn.pragma_update(None, "key", key.clone()).unwrap();
+    let mut secret = DB_SECRETS.lock()?.get(&map_key).cloned().unwrap_or_default();
+    if key.is_some() {
+        secret.key = key.clone();
+    }
+    if let Some(cipher) = cipher {
+        cipher.validate()?;
+        secret.cipher = cipher.clone();
+    }
+    // 校验密钥需要读取数据库，可能等待其他连接的锁，因此在持有全局锁之前进行。
+    let is_opened = |secrets: &HashMap<String, DbSecret>, map: &HashMap<String, Arc<Rbatis>>| map.contains_key(&map_key) && secrets.get(&map_key) == Some(&secret);
+    if !is_opened(&*DB_SECRETS.lock()?, &*OPENED_DBS.lock()?) {
+        open_verified_connection(db_path, &secret.key, &secret.cipher)?;
     }
 
+    let mut secrets = DB_SECRETS.lock()?;
     let mut map = OPENED_DBS.lock()?;
-    if !map.contains_key(&map_key) {
+    // 校验期间其他线程可能已用相同的密钥打开。
+    if !is_opened(&secrets, &map) {
+        map.remove(&map_key);
+        secrets.insert(map_key.clone(), secret.clone());
+
         let mut opts = SqliteConnectOptions::new();
         opts.set_uri(map_key.as_str()).unwrap();
-        if let Some(key) = key {
-            opts = opts.pragma("key", key.clone());
+        if let Some(key) = &secret.key {
+            opts = opts.pragma("key", secret.cipher.pool_key_value(key));
+        }
+        if let Some(enabled) = DB_FOREIGN_KEYS.lock()?.get(&map_key) {
+            opts = opts.pragma("foreign_keys", if *enabled { "ON" } else { "OFF" });
+        }
+        if let Some(mode) = DB_JOURNAL_MODES.lock()?.get(&map_key) {
+            opts = opts.journal_mode(match mode {
+                JournalMode::Delete => SqliteJournalMode::Delete,
+                JournalMode::Wal => SqliteJournalMode::Wal,
+            });
         }
         opts = opts.create_if_missing(false);
         let rb = Rbatis::new();
@@ -81,185 +217,1531 @@ pub fn open_db_connections(db_path: &String, key: &Option<String>) -> Result<Arc
 }
 
 pub fn remove_db_connection(db_path: &String) -> Result<(), Box<dyn Error>> {
+    let mut secrets = DB_SECRETS.lock()?;
     let mut map = OPENED_DBS.lock()?;
     map.remove(db_path);
+    secrets.remove(db_path);
+    DB_FOREIGN_KEYS.lock()?.remove(db_path);
+    DB_JOURNAL_MODES.lock()?.remove(db_path);
+    close_db_cursors(db_path)?;
+    clear_recent_queries(db_path)?;
+    Ok(())
+}
+
+/// 使用rusqlite直接打开数据库文件，并应用密钥和加密参数。文件不存在时将自动创建。
+fn open_raw_connection(db_path: &str, key: &Option<String>, cipher: &CipherProfile) -> Result<Connection, Box<dyn Error>> {
+    let conn = Connection::open(db_path)?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key.clone())?;
+        cipher.apply(&conn, None)?;
+    }
+    Ok(conn)
+}
+
+/// 打开已存在的数据库文件并校验密钥和加密参数能否正确读取数据库，失败时返回`OpenDbError`说明具体原因。
+fn open_verified_connection(db_path: &str, key: &Option<String>, cipher: &CipherProfile) -> Result<Connection, Box<dyn Error>> {
+    if !Path::new(db_path).exists() {
+        return Err(OpenDbError::FileMissing(db_path.to_string()).into());
+    }
+    let classify = |e: rusqlite::Error| -> Box<dyn Error> { classify_open_error(db_path, e) };
+    let conn = Connection::open(db_path).map_err(classify)?;
+    conn.busy_timeout(Duration::from_secs(1)).map_err(classify)?;
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key.clone()).map_err(classify)?;
+        cipher.apply(&conn, None)?;
+    }
+    probe_connection(&conn).map_err(classify)?;
+    Ok(conn)
+}
+
+/// 将打开或读取数据库时的SQLITE错误归类为`OpenDbError`，无法归类的错误原样返回。
+fn classify_open_error(db_path: &str, e: rusqlite::Error) -> Box<dyn Error> {
+    let path = db_path.to_string();
+    let code = match &e {
+        rusqlite::Error::SqliteFailure(f, _) => f.code,
+        _ => return e.into(),
+    };
+    match code {
+        rusqlite::ErrorCode::NotADatabase => OpenDbError::WrongKey(path).into(),
+        rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked => OpenDbError::Locked(path).into(),
+        rusqlite::ErrorCode::PermissionDenied => OpenDbError::PermissionDenied(path).into(),
+        rusqlite::ErrorCode::CannotOpen => match fs::File::open(db_path) {
+            Err(io) if io.kind() == ErrorKind::NotFound => OpenDbError::FileMissing(path).into(),
+            Err(io) if io.kind() == ErrorKind::PermissionDenied => OpenDbError::PermissionDenied(path).into(),
+            _ => e.into(),
+        },
+        _ => e.into(),
+    }
+}
+
+/// 新建数据库文件，文件已存在时不做任何操作。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥，新文件在首次写入时按该密钥加密。
+/// * `cipher`: 可选的加密参数。
+///
+/// returns: Result<(), Box<dyn Error, Global>>
+pub fn create_db_file(db_path: &str, key: &Option<String>, cipher: &Option<CipherProfile>) -> Result<(), Box<dyn Error>> {
+    if Path::new(db_path).exists() {
+        return Ok(());
+    }
+    open_raw_connection(db_path, key, &cipher.clone().unwrap_or_default())?;
+    Ok(())
+}
+
+/// 按该文件最近一次使用的密钥和加密参数打开独立的rusqlite连接，用于连接池无法完成的操作。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥，为`None`时沿用该文件最近一次使用的密钥。
+///
+/// returns: Result<Connection, Box<dyn Error, Global>> 密钥错误等情况下返回`OpenDbError`。
+pub(crate) fn open_registered_connection(db_path: &String, key: &Option<String>) -> Result<Connection, Box<dyn Error>> {
+    let secret = registered_secret(db_path, key)?;
+    let conn = open_verified_connection(db_path, &secret.key, &secret.cipher)?;
+    if let Some(enabled) = DB_FOREIGN_KEYS.lock()?.get(db_path) {
+        conn.pragma_update(None, "foreign_keys", enabled)?;
+    }
+    Ok(conn)
+}
+
+/// 获取数据库文件最近一次使用的密钥和加密参数，调用方提供了密钥时以其为准。
+fn registered_secret(db_path: &String, key: &Option<String>) -> Result<DbSecret, Box<dyn Error>> {
+    let mut secret = DB_SECRETS.lock()?.get(db_path).cloned().unwrap_or_default();
+    if key.is_some() {
+        secret.key = key.clone();
+    }
+    Ok(secret)
+}
+
+/// 导出进度。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportProgress {
+    target_path: String,
+    /// 目标文件已写入的字节数。
+    written_bytes: u64,
+    /// 预计总字节数，按源文件大小估算。
+    total_bytes: u64,
+}
+
+/// 导出进度回调函数。
+pub type ExportProgressFn = Box<dyn Fn(ExportProgress) + Send + Sync>;
+
+/// 导出结果，可直接用于新增历史记录。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExportResult {
+    pub name: String,
+    pub path: String,
+    /// 目标文件的密钥，只在后端写入密钥库，不返回给前端。
+    #[serde(skip_serializing)]
+    pub key: Option<String>,
+    pub cipher: Option<CipherProfile>,
+}
+
+/// 使用`sqlcipher_export()`将已打开的数据库完整导出到新文件。
+///
+/// # Arguments
+///
+/// * `conn`: 源数据库连接，须已设置密钥和加密参数。
+/// * `target_path`: 目标文件路径，文件须不存在。
+/// * `target_key`: 目标文件的密钥，为`None`时导出为明文数据库。
+/// * `target_cipher`: 目标文件的加密参数，仅在设置了密钥时生效。
+/// * `progress`: 可选的进度回调，导出期间按目标文件大小定时报告，完成时报告100%。
+fn sqlcipher_export_to(conn: &Connection, target_path: &str, target_key: &Option<String>, target_cipher: &CipherProfile, progress: Option<ExportProgressFn>) -> Result<(), Box<dyn Error>> {
+    conn.execute("ATTACH DATABASE ?1 AS exported KEY ?2", [target_path, target_key.as_deref().unwrap_or("")])?;
+
+    let progress = progress.map(Arc::new);
+    if let Some(progress) = progress.clone() {
+        let total_bytes = conn.path().and_then(|p| fs::metadata(p).ok()).map(|m| m.len()).unwrap_or(0);
+        let path = target_path.to_string();
+        let report = move || {
+            let written_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            progress(ExportProgress { target_path: path.clone(), written_bytes, total_bytes });
+        };
+        let report = AssertUnwindSafe(report);
+        let mut last_report = Instant::now();
+        conn.progress_handler(1000, Some(move || {
+            if last_report.elapsed() >= Duration::from_millis(200) {
+                last_report = Instant::now();
+                (*report)();
+            }
+            false
+        }));
+    }
+
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        if target_key.is_some() {
+            target_cipher.apply(conn, Some(DatabaseName::Attached("exported")))?;
+        }
+        conn.query_row("SELECT sqlcipher_export('exported')", [], |_| Ok(()))?;
+        let user_version: i64 = conn.pragma_query_value(Some(DatabaseName::Main), "user_version", |r| r.get(0))?;
+        conn.pragma_update(Some(DatabaseName::Attached("exported")), "user_version", user_version)?;
+        Ok(())
+    })();
+    conn.progress_handler(0, None::<fn() -> bool>);
+    conn.execute("DETACH DATABASE exported", [])?;
+    if let (Ok(()), Some(progress)) = (&result, progress) {
+        // 总字节数只是估算值，完成时按目标文件的实际大小再报告一次，使进度达到100%。
+        let written_bytes = fs::metadata(target_path).map(|m| m.len()).unwrap_or(0);
+        progress(ExportProgress { target_path: target_path.to_string(), written_bytes, total_bytes: written_bytes });
+    }
+    result
+}
+
+/// 将数据库导出为使用另一密钥和加密参数(或不加密)的新文件，源文件保持不变。
+///
+/// # Arguments
+///
+/// * `db_path`: 源数据库文件路径。
+/// * `key`: 源数据库的密钥。
+/// * `target_path`: 目标文件路径，文件须不存在。
+/// * `target_key`: 目标文件的密钥，为`None`时导出为明文数据库。
+/// * `target_cipher`: 目标文件的加密参数，为`None`时使用库的默认设置。
+/// * `progress`: 可选的进度回调。
+///
+/// returns: Result<ExportResult, Box<dyn Error, Global>> 返回目标文件信息，可用于新增历史记录。
+///
+/// # Examples
+///
+/// ```
+/// let result = export_to_file("/home/foo/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), "/home/foo/tmp/sqlite/plain.db".to_string(), None, None, None).await;
+/// match result {
+///     Err(e) => assert!(false, "导出数据库失败 {}", e),
+///     Ok(r) => println!("已导出到 {}", r.path),
+/// }
+/// ```
+pub async fn export_to_file(db_path: String, key: Option<String>, target_path: String, target_key: Option<String>, target_cipher: Option<CipherProfile>, progress: Option<ExportProgressFn>) -> Result<ExportResult, Box<dyn Error>> {
+    if Path::new(&target_path).exists() {
+        return Err(format!("目标文件 {} 已存在", target_path).into());
+    }
+    let secret = registered_secret(&db_path, &key)?;
+    let target_cipher = target_cipher.filter(|_| target_key.is_some());
+    if let Some(target_cipher) = &target_cipher {
+        target_cipher.validate()?;
+    }
+
+    let result = ExportResult {
+        name: Path::new(&target_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        path: target_path.clone(),
+        key: target_key.clone(),
+        cipher: target_cipher.clone(),
+    };
+
+    run_blocking(move || {
+        let conn = open_verified_connection(&db_path, &secret.key, &secret.cipher)?;
+        sqlcipher_export_to(&conn, &target_path, &target_key, &target_cipher.unwrap_or_default(), progress).map_err(|e| {
+            let _ = fs::remove_file(&target_path);
+            e
+        })
+    }).await?;
+    Ok(result)
+}
+
+/// 在线备份每步复制的页数，两步之间源数据库可被其他连接写入。
+const BACKUP_PAGES_PER_STEP: i32 = 256;
+/// 源数据库或目标数据库持续被锁定超过该时间时放弃复制。
+const BACKUP_BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 在线备份或从备份恢复的进度。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BackupProgress {
+    target_path: String,
+    copied_pages: i32,
+    total_pages: i32,
+}
+
+/// 在线备份进度回调函数。
+pub type BackupProgressFn = Box<dyn Fn(BackupProgress) + Send + Sync>;
+
+/// 使用SQLITE备份API逐页复制数据库，目标数据库的原有内容将被覆盖。
+fn copy_pages(source: &Connection, target: &mut Connection, target_path: &str, progress: &Option<BackupProgressFn>) -> Result<(), Box<dyn Error>> {
+    let backup = Backup::new(source, target)?;
+    let mut busy_since: Option<Instant> = None;
+    loop {
+        let state = backup.step(BACKUP_PAGES_PER_STEP)?;
+        if let Some(progress) = progress {
+            let p = backup.progress();
+            progress(BackupProgress { target_path: target_path.to_string(), copied_pages: p.pagecount - p.remaining, total_pages: p.pagecount });
+        }
+        match state {
+            StepResult::Done => return Ok(()),
+            StepResult::More => busy_since = None,
+            // 源数据库或目标数据库被其他连接锁定，稍后重试，持续锁定超时后返回错误。
+            _ => {
+                let since = *busy_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= BACKUP_BUSY_TIMEOUT {
+                    return Err(format!("数据库被其他连接锁定超过{}秒，复制已取消", BACKUP_BUSY_TIMEOUT.as_secs()).into());
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+    }
+}
+
+/// 备份API和`VACUUM INTO`按页复制，不能在加密和未加密的数据库之间转换。
+fn check_same_encryption(key: &Option<String>, other_key: &Option<String>) -> Result<(), Box<dyn Error>> {
+    if key.is_some() != other_key.is_some() {
+        return Err("按页复制不能在加密和未加密的数据库之间转换，请使用导出功能".into());
+    }
+    Ok(())
+}
+
+/// 使用SQLITE备份API将正在使用的数据库复制到新文件，复制期间其他连接仍可读写源数据库。
+///
+/// 备份文件沿用源数据库的加密参数，可使用不同的密钥，但不能在加密和未加密之间转换。
+///
+/// # Arguments
+///
+/// * `db_path`: 源数据库文件路径。
+/// * `key`: 源数据库的密钥。
+/// * `target_path`: 备份文件路径，文件须不存在。
+/// * `target_key`: 备份文件的密钥，为`None`时与源数据库相同。
+/// * `progress`: 可选的进度回调，每复制一批页报告一次。
+///
+/// returns: Result<ExportResult, Box<dyn Error, Global>> 返回备份文件信息，可用于新增历史记录。
+///
+/// # Examples
+///
+/// ```
+/// let result = backup_to_file("/home/foo/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), "/home/foo/tmp/sqlite/my.bak.db".to_string(), None, None).await;
+/// if let Err(e) = result {
+///     assert!(false, "备份数据库失败 {}", e);
+/// }
+/// ```
+pub async fn backup_to_file(db_path: String, key: Option<String>, target_path: String, target_key: Option<String>, progress: Option<BackupProgressFn>) -> Result<ExportResult, Box<dyn Error>> {
+    if Path::new(&target_path).exists() {
+        return Err(format!("目标文件 {} 已存在", target_path).into());
+    }
+    let secret = registered_secret(&db_path, &key)?;
+    let target_key = target_key.or_else(|| secret.key.clone());
+    check_same_encryption(&secret.key, &target_key)?;
+
+    let result = ExportResult {
+        name: Path::new(&target_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        path: target_path.clone(),
+        key: target_key.clone(),
+        cipher: if target_key.is_some() { Some(secret.cipher.clone()) } else { None },
+    };
+
+    run_blocking(move || {
+        let source = open_verified_connection(&db_path, &secret.key, &secret.cipher)?;
+        let copied = open_raw_connection(&target_path, &target_key, &secret.cipher)
+            .and_then(|mut target| copy_pages(&source, &mut target, &target_path, &progress));
+        if copied.is_err() {
+            let _ = fs::remove_file(&target_path);
+        }
+        copied
+    }).await?;
+    Ok(result)
+}
+
+/// 使用SQLITE备份API以备份文件覆盖正在使用的数据库，数据库的密钥保持不变。
+///
+/// 先确认备份文件能以给定的密钥读取，再丢弃缓存的连接池和游标，然后逐页覆盖。调用前应由用户确认。
+///
+/// # Arguments
+///
+/// * `db_path`: 被覆盖的数据库文件路径。
+/// * `key`: 被覆盖的数据库的密钥。
+/// * `backup_path`: 备份文件路径。
+/// * `backup_key`: 备份文件的密钥，为`None`时与被覆盖的数据库相同。
+/// * `progress`: 可选的进度回调。
+///
+/// returns: Result<(), Box<dyn Error, Global>>
+pub async fn restore_from_backup(db_path: String, key: Option<String>, backup_path: String, backup_key: Option<String>, progress: Option<BackupProgressFn>) -> Result<(), Box<dyn Error>> {
+    let secret = registered_secret(&db_path, &key)?;
+    let backup_key = backup_key.or_else(|| secret.key.clone());
+    check_same_encryption(&secret.key, &backup_key)?;
+
+    run_blocking(move || {
+        let source = open_verified_connection(&backup_path, &backup_key, &secret.cipher)?;
+        let mut target = open_verified_connection(&db_path, &secret.key, &secret.cipher)?;
+        OPENED_DBS.lock()?.remove(&db_path);
+        close_db_cursors(&db_path)?;
+        target.busy_timeout(Duration::from_secs(5))?;
+        copy_pages(&source, &mut target, &db_path, &progress)
+    }).await
+}
+
+/// 检测到的加密格式。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DetectedCipher {
+    /// 能够成功打开数据库的预设方案。
+    preset: CipherPreset,
+    /// 是否为需要迁移的旧版格式。
+    legacy: bool,
+    /// `PRAGMA cipher_settings`报告的完整加密参数。
+    settings: Vec<String>,
+}
+
+/// 迁移结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MigrateResult {
+    detected: DetectedCipher,
+    pub target: ExportResult,
+}
+
+/// 校验连接的密钥和加密参数能否正确读取数据库。
+fn probe_connection(conn: &Connection) -> rusqlite::Result<()> {
+    conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+}
+
+/// 依次尝试当前默认设置和SQLCipher 3、2、1的默认设置，返回第一个能正确读取数据库的方案。
+fn detect_cipher_blocking(db_path: &str, key: &str) -> Result<Option<DetectedCipher>, Box<dyn Error>> {
+    if !Path::new(db_path).exists() {
+        return Err(format!("数据库文件 {} 不存在", db_path).into());
+    }
+    let key = Some(key.to_string());
+    for preset in [CipherPreset::Default, CipherPreset::Sqlcipher3, CipherPreset::Sqlcipher2, CipherPreset::Sqlcipher1] {
+        let cipher = CipherProfile { preset, ..Default::default() };
+        let conn = open_raw_connection(db_path, &key, &cipher)?;
+        if probe_connection(&conn).is_err() {
+            continue;
+        }
+        let mut stmt = conn.prepare("PRAGMA cipher_settings")?;
+        let settings = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<Result<Vec<String>, _>>()?;
+        return Ok(Some(DetectedCipher { preset, legacy: preset != CipherPreset::Default, settings }));
+    }
+    Ok(None)
+}
+
+/// 检测加密数据库使用的SQLCipher格式。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 数据库密钥。
+///
+/// returns: Result<Option<DetectedCipher>, Box<dyn Error, Global>> 所有方案都无法读取时返回`None`，通常意味着密钥错误或文件不是数据库。
+///
+/// # Examples
+///
+/// ```
+/// let detected = detect_cipher("/home/foo/tmp/sqlite/v3.db".to_string(), "123456".to_string()).await.unwrap();
+/// println!("检测结果 {:?}", detected);
+/// ```
+pub async fn detect_cipher(db_path: String, key: String) -> Result<Option<DetectedCipher>, Box<dyn Error>> {
+    run_blocking(move || detect_cipher_blocking(&db_path, &key)).await
+}
+
+/// 使用`PRAGMA cipher_migrate`将旧版SQLCipher格式的数据库迁移为当前格式。
+///
+/// 默认先将源文件复制到目标路径，再对副本执行迁移，源文件保持不变；只有显式指定`in_place`时才原地迁移。
+///
+/// # Arguments
+///
+/// * `db_path`: 源数据库文件路径。
+/// * `key`: 数据库密钥，迁移后保持不变。
+/// * `target_path`: 可选的目标文件路径，默认为源文件同目录下的`<文件名>-sqlcipher4.<扩展名>`。
+/// * `in_place`: 是否原地迁移。
+///
+/// returns: Result<MigrateResult, Box<dyn Error, Global>> 返回检测到的旧版参数和迁移后的文件信息。
+///
+/// # Examples
+///
+/// ```
+/// let result = migrate_db("/home/foo/tmp/sqlite/v3.db".to_string(), "123456".to_string(), None, false).await;
+/// match result {
+///     Err(e) => assert!(false, "迁移失败 {}", e),
+///     Ok(r) => println!("已迁移到 {}", r.target.path),
+/// }
+/// ```
+pub async fn migrate_db(db_path: String, key: String, target_path: Option<String>, in_place: bool) -> Result<MigrateResult, Box<dyn Error>> {
+    // 先确认数据库确实需要迁移，检测失败时不影响已打开的连接池和游标。
+    let detect_path = db_path.clone();
+    let detect_key = key.clone();
+    let detected = run_blocking(move || {
+        match detect_cipher_blocking(&detect_path, &detect_key)? {
+            None => Err("无法读取数据库，密钥错误或文件不是SQLCipher数据库".into()),
+            Some(d) if !d.legacy => Err("数据库已是当前SQLCipher格式，无需迁移".into()),
+            Some(d) => Ok(d),
+        }
+    }).await?;
+    if in_place {
+        OPENED_DBS.lock()?.remove(&db_path);
+        close_db_cursors(&db_path)?;
+    }
+    let migrated_path = db_path.clone();
+    let result = run_blocking(move || {
+        let target_path = if in_place {
+            db_path.clone()
+        } else {
+            let target_path = target_path.unwrap_or_else(|| default_migrate_path(&db_path));
+            if Path::new(&target_path).exists() {
+                return Err(format!("目标文件 {} 已存在", target_path).into());
+            }
+            // WAL和回滚日志中可能有尚未写入主文件的内容，随主文件一并复制；共享内存索引由WAL重建，只清除目标的残留文件。
+            for suffix in ["-wal", "-shm", "-journal"] {
+                let _ = fs::remove_file(format!("{}{}", target_path, suffix));
+            }
+            fs::copy(&db_path, &target_path)?;
+            for suffix in ["-wal", "-journal"] {
+                let sidecar = format!("{}{}", db_path, suffix);
+                if Path::new(&sidecar).exists() {
+                    fs::copy(&sidecar, format!("{}{}", target_path, suffix))?;
+                }
+            }
+            target_path
+        };
+
+        let migrate = || -> Result<(), Box<dyn Error>> {
+            let conn = Connection::open(&target_path)?;
+            conn.pragma_update(None, "key", key.clone())?;
+            // 执行成功时返回"0"。
+            let code: String = conn.query_row("PRAGMA cipher_migrate", [], |r| r.get(0))?;
+            if code != "0" {
+                return Err(format!("cipher_migrate 执行失败，返回 {}", code).into());
+            }
+            drop(conn);
+            let conn = open_raw_connection(&target_path, &Some(key.clone()), &CipherProfile::default())?;
+            probe_connection(&conn)?;
+            Ok(())
+        };
+        if let Err(e) = migrate() {
+            if !in_place {
+                let _ = fs::remove_file(&target_path);
+                for suffix in ["-wal", "-shm", "-journal"] {
+                    let _ = fs::remove_file(format!("{}{}", target_path, suffix));
+                }
+            }
+            return Err(e);
+        }
+
+        let name = Path::new(&target_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(MigrateResult { detected, target: ExportResult { name, path: target_path, key: Some(key), cipher: None } })
+    }).await?;
+
+    if in_place {
+        if let Some(secret) = DB_SECRETS.lock()?.get_mut(&migrated_path) {
+            secret.cipher = CipherProfile::default();
+        }
+    }
+    Ok(result)
+}
+
+/// 生成迁移副本的默认路径。
+fn default_migrate_path(db_path: &str) -> String {
+    let path = Path::new(db_path);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-sqlcipher4.{}", stem, ext.to_string_lossy()),
+        None => format!("{}-sqlcipher4", stem),
+    };
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// 在阻塞线程池中执行耗时的rusqlite操作，避免占用异步运行时的工作线程。
+pub(crate) async fn run_blocking<T, F>(f: F) -> Result<T, Box<dyn Error>>
+    where T: Send + 'static,
+          F: FnOnce() -> Result<T, Box<dyn Error>> + Send + 'static {
+    let result = tokio::task::spawn_blocking(move || f().map_err(sendable_error)).await?;
+    result.map_err(|e| -> Box<dyn Error> { e })
+}
+
+/// 修改数据库密钥。
+///
+/// 新旧密钥均存在时使用`PRAGMA rekey`原地修改；由于`rekey`不能用于加密明文库或解密加密库，
+/// 这两种情况先通过`sqlcipher_export()`导出到临时文件，确认没有其它连接使用原文件后再替换。
+/// 校验原密钥后会丢弃已缓存的连接池并关闭该库的游标，下次访问时按新密钥重建。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 当前密钥，为`None`时沿用该文件最近一次使用的密钥，仍为`None`表示明文数据库。
+/// * `new_key`: 新密钥，为`None`表示解密为明文数据库。
+///
+/// returns: Result<(), Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let result = rekey_db(&"/home/foo/tmp/sqlite/my.db".to_string(), &Some("123456".to_string()), &Some("654321".to_string()));
+/// if let Err(e) = result {
+///     assert!(false, "修改密钥失败 {}", e);
+/// }
+/// ```
+pub fn rekey_db(db_path: &String, key: &Option<String>, new_key: &Option<String>) -> Result<(), Box<dyn Error>> {
+    let secret = registered_secret(db_path, key)?;
+    // 先确认原密钥正确，密钥错误时不影响已打开的连接池和游标。
+    let conn = open_verified_connection(db_path, &secret.key, &secret.cipher)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    OPENED_DBS.lock()?.remove(db_path);
+    close_db_cursors(db_path)?;
+
+    let mut new_secret = DbSecret { key: new_key.clone(), cipher: secret.cipher.clone() };
+    match (&secret.key, new_key) {
+        (None, None) => {}
+        (Some(_), Some(new_key)) => {
+            conn.pragma_update(None, "rekey", new_key.clone())?;
+        }
+        _ => {
+            let temp_path = format!("{}.rekey-tmp", db_path);
+            if Path::new(&temp_path).exists() {
+                fs::remove_file(&temp_path)?;
+            }
+            let journal_mode: String = conn.pragma_query_value(None, "journal_mode", |r| r.get(0))?;
+            let replaceable = sqlcipher_export_to(&conn, &temp_path, new_key, &CipherProfile::default(), None)
+                .and_then(|_| exclusive_checkpoint(&conn, &journal_mode));
+            if let Err(e) = replaceable {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+            drop(conn);
+
+            fs::rename(&temp_path, db_path)?;
+            // 原文件的日志文件已不再适用于新文件，必须一并清除。
+            for suffix in ["-wal", "-shm", "-journal"] {
+                let aux = format!("{}{}", db_path, suffix);
+                if Path::new(&aux).exists() {
+                    fs::remove_file(aux)?;
+                }
+            }
+            // 导出的文件使用默认的回滚日志模式，恢复原有的WAL模式。
+            if journal_mode.eq_ignore_ascii_case("wal") {
+                open_raw_connection(db_path, new_key, &CipherProfile::default())?.pragma_update(None, "journal_mode", "wal")?;
+            }
+            // 新文件使用库的默认加密参数。
+            new_secret.cipher = CipherProfile::default();
+        }
+    }
+    DB_SECRETS.lock()?.insert(db_path.clone(), new_secret);
+    Ok(())
+}
+
+/// 替换数据库文件前，确认没有其它连接仍在使用该文件，并将WAL中的内容全部写回主文件。
+///
+/// WAL模式下每个连接都持有数据库文件的共享锁，切换为回滚日志模式需要独占访问，成功即说明没有其它连接，同时会合并并删除WAL文件。
+/// 之后再获取一次排它锁，确认没有其它连接正在读写。
+fn exclusive_checkpoint(conn: &Connection, journal_mode: &str) -> Result<(), Box<dyn Error>> {
+    let in_use = |e: &dyn Display| format!("数据库仍被其它连接使用，请关闭后重试: {}", e);
+    if journal_mode.eq_ignore_ascii_case("wal") {
+        let mode: String = conn.query_row("PRAGMA journal_mode = DELETE", [], |r| r.get(0)).map_err(|e| in_use(&e))?;
+        if !mode.eq_ignore_ascii_case("delete") {
+            return Err(in_use(&format!("无法退出 {} 模式", mode)).into());
+        }
+    }
+    conn.execute_batch("BEGIN EXCLUSIVE; COMMIT;").map_err(|e| in_use(&e))?;
     Ok(())
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct MetaResult {
-    db_path: String,
-    table_names: Option<Vec<String>>,
-    view_names: Option<Vec<String>>,
-    key: Option<String>,
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MetaResult {
+    db_path: String,
+    table_names: Option<Vec<String>>,
+    view_names: Option<Vec<String>>,
+    cipher: Option<CipherProfile>,
+}
+
+/// 加载已定义的表和视图列表。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `cipher`: 可选的加密参数。
+///
+/// returns: Result<MetaResult, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
+/// let load_result = load_tables("/home/foo/tmp/sqlite/sms.db".to_string(), Some("123456".to_string()), None).await;
+/// match load_result {
+///     Err(e) => assert!(false, "查询表名失败 {}", e),
+///     Ok(metas) => {
+///         println!("查询到表名列表 {:?}", metas);
+///     }
+/// }
+/// ```
+pub async fn load_tables(db_path: String, key: Option<String>, cipher: Option<CipherProfile>) -> Result<MetaResult, Box<dyn Error>> {
+    let rb = open_db_connections_with_cipher(&db_path, &key, &cipher)?;
+    let mut rb = rb.deref();
+
+    let mut metas = SqliteMeta::select_cols(&mut rb, "type as obj_type,name").await?;
+    let table_opt = Some(String::from("table"));
+    let view_opt = Some(String::from("view"));
+
+    let mut names = |opt: Option<String>| -> Vec<String> {
+        metas.iter_mut().filter(|m| opt.eq(&m.obj_type)).map(|m| {
+            return m.name.replace("".to_string()).unwrap();
+        }).collect()
+    };
+    let table_names = names(table_opt);
+    let view_names = names(view_opt);
+
+    let mut result = MetaResult { db_path, table_names: None, view_names: None, cipher };
+    if !table_names.is_empty() {
+        result.table_names = Some(table_names);
+    }
+    if !view_names.is_empty() {
+        result.view_names = Some(view_names);
+    }
+    Ok(result)
+}
+
+pub async fn fetch_table_sql(db_path: String, key: Option<String>, table_name: String) -> DaoResult {
+    let _rb = open_db_connections(&db_path, &key)?;
+    let rb = _rb.deref();
+    let sql: String = rb.fetch_decode("select sql from sqlite_master where name = ?", vec![to_value!(table_name)]).await?;
+    Ok(ApiResp::success(serde_json::Value::String(sql)))
+}
+
+
+#[derive(Serialize, Deserialize)]
+pub struct TableData {
+    /// 结果集字段，按查询结果的顺序排列。
+    columns: Vec<ColumnDesc>,
+    /// 每行按`columns`的顺序排列，BLOB值以`{"blob_size": 字节数}`表示。
+    rows: Vec<Vec<serde_json::Value>>,
+    /// 目标是否为表。为`true`时第一列为`rowid`，用于修改数据。
+    has_rowid: bool,
+    /// 满足过滤条件的总条数，仅在请求时查询。
+    total: Option<u64>,
+}
+
+/// 排序条件。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SortSpec {
+    pub column: String,
+    #[serde(default)]
+    pub desc: bool,
+}
+
+/// 过滤运算符。
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Like,
+    NotLike,
+    /// 取值为数组。
+    In,
+    IsNull,
+    IsNotNull,
+}
+
+/// 过滤条件，各条件之间为`AND`关系。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FilterSpec {
+    pub column: String,
+    pub op: FilterOp,
+    /// 比较值，`is_null`和`is_not_null`无需取值。
+    pub value: Option<serde_json::Value>,
+}
+
+/// 表数据的分页、排序和过滤条件。
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TableQuery {
+    /// 每页条数，应大于0。
+    pub limit: u64,
+    pub offset: Option<u64>,
+    /// 键集分页，返回`rowid`大于(按`rowid`降序时为小于)该值的行。仅适用于表，且只能按`rowid`排序。
+    /// `rowid`、`_rowid_`和`oid`都是表的字段时无法引用rowid，改按`offset`分页。
+    pub after_rowid: Option<i64>,
+    pub order_by: Option<Vec<SortSpec>>,
+    pub filters: Option<Vec<FilterSpec>>,
+    /// 是否查询满足过滤条件的总条数。
+    pub with_total: bool,
+}
+
+/// rowid的三个别名，表中有同名字段时该别名指向字段而不是rowid。
+const ROWID_ALIASES: [&str; 3] = ["rowid", "_rowid_", "oid"];
+
+/// 名称是否指向表的rowid，即为rowid的别名且没有被同名字段占用。
+fn is_rowid_alias(name: &str, columns: &[String]) -> bool {
+    ROWID_ALIASES.iter().any(|a| a.eq_ignore_ascii_case(name)) && !columns.iter().any(|c| c.eq_ignore_ascii_case(name))
+}
+
+/// 表中未被同名字段占用的rowid别名，三个别名都被占用时返回`None`。
+fn rowid_alias(columns: &[String]) -> Option<&'static str> {
+    ROWID_ALIASES.iter().copied().find(|a| is_rowid_alias(a, columns))
+}
+
+/// 将字段名转换为带引号的标识符，字段名须为目标表的字段或未被字段占用的rowid别名。
+fn checked_column(column: &str, columns: &[String], is_table: bool) -> Result<String, Box<dyn Error>> {
+    if columns.iter().any(|c| c == column) || (is_table && is_rowid_alias(column, columns)) {
+        Ok(quote_ident(column))
+    } else {
+        Err(format!("字段 {} 不存在", column).into())
+    }
+}
+
+/// 分页查询语句及其绑定参数。
+struct PageSql {
+    select: String,
+    select_args: Vec<serde_json::Value>,
+    count: String,
+    count_args: Vec<serde_json::Value>,
+}
+
+/// 根据分页、排序和过滤条件生成查询语句，过滤值均以绑定参数传递。
+///
+/// # Arguments
+///
+/// * `from`: 已转换为SQL标识符的表名或视图名。
+/// * `columns`: 目标表的字段名列表，排序和过滤的字段须在其中。
+/// * `is_table`: 目标是否为表，只有表才能使用`rowid`。
+/// * `with_rowid`: 是否在第一列返回`rowid`，仅对表有效。
+/// * `query`: 分页、排序和过滤条件。
+fn build_page_sql(from: &str, columns: &[String], is_table: bool, with_rowid: bool, query: &TableQuery) -> Result<PageSql, Box<dyn Error>> {
+    let mut conditions: Vec<String> = vec![];
+    let mut args: Vec<serde_json::Value> = vec![];
+    for filter in query.filters.iter().flatten() {
+        let column = checked_column(&filter.column, columns, is_table)?;
+        let value = || filter.value.as_ref().filter(|v| !v.is_null()).ok_or_else(|| format!("过滤条件 {} 缺少比较值", filter.column));
+        let op = match filter.op {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "<>",
+            FilterOp::Lt => "<",
+            FilterOp::Le => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Ge => ">=",
+            FilterOp::Like => "LIKE",
+            FilterOp::NotLike => "NOT LIKE",
+            FilterOp::IsNull => {
+                conditions.push(format!("{} IS NULL", column));
+                continue;
+            }
+            FilterOp::IsNotNull => {
+                conditions.push(format!("{} IS NOT NULL", column));
+                continue;
+            }
+            FilterOp::In => {
+                let values = value()?.as_array().filter(|a| !a.is_empty()).ok_or_else(|| format!("过滤条件 {} 的取值应为非空数组", filter.column))?;
+                conditions.push(format!("{} IN ({})", column, vec!["?"; values.len()].join(",")));
+                args.extend(values.iter().cloned());
+                continue;
+            }
+        };
+        conditions.push(format!("{} {} ?", column, op));
+        args.push(value()?.clone());
+    }
+    let count_where = if conditions.is_empty() { String::new() } else { format!(" WHERE {}", conditions.join(" AND ")) };
+    let count_args = args.clone();
+
+    let mut orders: Vec<String> = vec![];
+    for sort in query.order_by.iter().flatten() {
+        orders.push(format!("{} {}", checked_column(&sort.column, columns, is_table)?, if sort.desc { "DESC" } else { "ASC" }));
+    }
+    let alias = rowid_alias(columns);
+    let mut offset = query.offset.unwrap_or(0);
+    if let Some(after) = query.after_rowid {
+        let sorts = query.order_by.as_deref().unwrap_or(&[]);
+        if !is_table || sorts.len() > 1 || sorts.iter().any(|s| !is_rowid_alias(&s.column, columns)) {
+            return Err("键集分页仅适用于按rowid排序的表".into());
+        }
+        match alias {
+            Some(alias) => {
+                let desc = sorts.first().map_or(false, |s| s.desc);
+                conditions.push(format!("{} {} ?", alias, if desc { "<" } else { ">" }));
+                args.push(serde_json::Value::from(after));
+                if orders.is_empty() {
+                    orders.push(format!("{} ASC", alias));
+                }
+            }
+            // 三个别名都被字段占用时无法引用rowid，退回按偏移量分页。
+            None => offset = query.offset.ok_or("表中的字段占用了rowid的所有别名，无法使用键集分页，请改用offset")?,
+        }
+    }
+
+    let rowid_column = if is_table && with_rowid { format!("{},", alias.unwrap_or("rowid")) } else { String::new() };
+    let mut select = format!("select {}* from {}", rowid_column, from);
+    if !conditions.is_empty() {
+        select.push_str(format!(" WHERE {}", conditions.join(" AND ")).as_str());
+    }
+    if !orders.is_empty() {
+        select.push_str(format!(" ORDER BY {}", orders.join(", ")).as_str());
+    }
+    select.push_str(" limit ? offset ?");
+    args.push(serde_json::Value::from(query.limit.min(i64::MAX as u64)));
+    args.push(serde_json::Value::from(offset));
+
+    Ok(PageSql { select, select_args: args, count: format!("select count(*) from {}{}", from, count_where), count_args })
+}
+
+/// 查询目标表或视图的字段名列表，并判断目标是否为表。
+fn table_columns(conn: &Connection, table_name: &str) -> Result<(Vec<String>, bool), Box<dyn Error>> {
+    let mut stmt = conn.prepare("select name from pragma_table_info(?1)")?;
+    let names = stmt.query_map([table_name], |r| r.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+    if names.is_empty() {
+        return Err(format!("表或视图 {} 不存在", table_name).into());
+    }
+    let target_type: Option<String> = conn.query_row("select type from sqlite_master where name = ?1", [table_name], |r| r.get(0)).optional()?;
+    Ok((names, target_type.map_or(false, |t| t.eq("table"))))
+}
+
+/// 表数据中的BLOB只返回字节数，内容通过`fetch_blob_cell`单独读取。
+fn cell_to_json(value: ValueRef<'_>) -> serde_json::Value {
+    match value {
+        ValueRef::Blob(b) => serde_json::json!({"blob_size": b.len()}),
+        other => sql_to_json(other),
+    }
+}
+
+/// 执行分页查询，字段按查询结果的顺序返回。
+fn query_table_page(conn: &Connection, db_path: &str, table_name: &str, query: &TableQuery) -> Result<TableData, Box<dyn Error>> {
+    let (names, is_table) = table_columns(conn, table_name)?;
+    let page = build_page_sql(quote_ident(table_name).as_str(), &names, is_table, true, query)?;
+
+    let mut prepared = prepare_single(conn, &page.select)?;
+    let columns = describe_columns(&prepared);
+    bind_json_params(&mut prepared.stmt, &Some(serde_json::Value::Array(page.select_args)))?;
+    let mut rows = vec![];
+    let mut result = prepared.stmt.raw_query();
+    while let Some(row) = result.next()? {
+        rows.push((0..columns.len()).map(|i| row.get_ref(i).map(cell_to_json)).collect::<rusqlite::Result<Vec<_>>>()?);
+    }
+    record_recent_query(db_path, &page.select)?;
+
+    let mut total = None;
+    if query.with_total {
+        let mut stmt = conn.prepare(&page.count)?;
+        bind_json_params(&mut stmt, &Some(serde_json::Value::Array(page.count_args)))?;
+        let count: i64 = stmt.raw_query().next()?.ok_or("查询总条数失败")?.get(0)?;
+        total = Some(count as u64);
+    }
+    Ok(TableData { columns, rows, has_rowid: is_table, total })
+}
+
+/// 分页查询目标表的数据，支持排序、过滤和键集分页。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `table_name`: 目标表名。
+/// * `query`: 分页、排序和过滤条件。
+/// * `key`: 可选的密钥。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`TableData`，字段描述和每行数据均按查询结果的顺序排列。
+///
+/// # Examples
+///
+/// ```
+/// let query = TableQuery {
+///     limit: 10,
+///     offset: Some(20),
+///     order_by: Some(vec![SortSpec { column: "name".to_string(), desc: true }]),
+///     filters: Some(vec![FilterSpec { column: "id".to_string(), op: FilterOp::Gt, value: Some(json!(100)) }]),
+///     with_total: true,
+///     ..Default::default()
+/// };
+/// let result = fetch_rows("/home/liuning/tmp/sqlite/my.db".to_string(), "my_table".to_string(), query, Some("123456".to_string())).await;
+/// if let Err(e) = result {
+///     assert!(false, "查询数据库失败 {}", e);
+/// } else {
+///     println!("查询到数据 {:?}", result.to_json_str("查询出错"));
+/// }
+/// ```
+pub async fn fetch_rows(db_path: String, table_name: String, query: TableQuery, key: Option<String>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let data = run_blocking(move || query_table_page(&conn, &db_path, &table_name, &query)).await?;
+    Ok(ApiResp::success(serde_json::json!(data)))
+}
+
+/// 执行用户输入的SQL脚本，脚本可包含多条语句和参数。执行期间可按查询编号取消。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `sql`: 用户SQL脚本，原样执行。
+/// * `key`: 可选的密钥。
+/// * `params`: 可选的参数，数组按顺序绑定到`?`参数，对象按名称绑定到`:name`等命名参数。
+/// * `query_id`: 可选的查询编号，用于取消查询，为`None`时自动生成。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`ScriptOutcome`，包含执行状态及每条语句的结果集、修改行数和耗时。
+///
+/// # Examples
+///
+/// ```
+/// fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
+/// let query_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "select rowid, * from my_table where name = ?", Some("123456".to_string()), Some(json!(["lisi"])), None).await;
+/// if let Err(e) = query_result {
+///     assert!(false, "查询数据库失败: {}", e);
+/// } else {
+///     println!("查询到数据 {:?}", query_result.to_json_str("查询出错"));
+/// }
+///
+/// let insert_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "insert into my_table(id,name) values(2,'lisi'); select changes()", Some("123456".to_string()), None, Some("q1".to_string())).await;
+/// if let Err(e) = insert_result {
+///     assert!(false, "更新数据库失败: {}", e);
+/// } else {
+///     println!("本次操作结果 {:?}", insert_result.to_json_str("操作出错"));
+/// }
+/// ```
+pub async fn exec_sql(db_path: String, sql: &str, key: Option<String>, params: Option<serde_json::Value>, query_id: Option<String>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let sql = sql.to_string();
+    let outcome = run_blocking(move || execute_cancellable(&conn, &db_path, query_id, &sql, &params)).await?;
+    Ok(ApiResp::success(serde_json::json!(outcome)))
+}
+
+/// 以游标方式查询目标表的数据，排序和过滤条件同`fetch_rows`，不分页，由前端通过`fetch_next`逐批读取。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `table_name`: 目标表名或视图名。
+/// * `query`: 排序和过滤条件，忽略`limit`和`with_total`。
+/// * `key`: 可选的密钥。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`CursorInfo`，目标是表时第一列为`rowid`。
+///
+/// # Examples
+///
+/// ```
+/// let query = TableQuery { order_by: Some(vec![SortSpec { column: "name".to_string(), desc: false }]), ..Default::default() };
+/// let result = open_table_cursor("/home/liuning/tmp/sqlite/my.db".to_string(), "my_table".to_string(), query, Some("123456".to_string())).await;
+/// if let Err(e) = result {
+///     assert!(false, "打开游标失败 {}", e);
+/// }
+/// ```
+pub async fn open_table_cursor(db_path: String, table_name: String, query: TableQuery, key: Option<String>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let info = run_blocking(move || {
+        let (names, is_table) = table_columns(&conn, &table_name)?;
+        let query = TableQuery { limit: i64::MAX as u64, with_total: false, ..query };
+        let page = build_page_sql(quote_ident(&table_name).as_str(), &names, is_table, true, &query)?;
+        open_cursor(conn, &db_path, &page.select, Some(serde_json::Value::Array(page.select_args)))
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(info)))
+}
+
+/// 以游标方式执行单条用户查询语句，由前端通过`fetch_next`逐批读取结果。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `sql`: 单条查询语句，游标连接为只读，不能修改数据。
+/// * `key`: 可选的密钥。
+/// * `params`: 可选的参数，规则同`exec_sql`。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`CursorInfo`。
+pub async fn open_sql_cursor(db_path: String, sql: String, key: Option<String>, params: Option<serde_json::Value>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let info = run_blocking(move || open_cursor(conn, &db_path, &sql, params)).await?;
+    Ok(ApiResp::success(serde_json::json!(info)))
+}
+
+/// 读取BLOB单元格，返回编码后的数据、总字节数和内容预览。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `table_name`: 目标表名。
+/// * `column`: 字段名。
+/// * `rowid`: 目标行的rowid。
+/// * `key`: 可选的密钥。
+/// * `encoding`: 返回数据的编码方式，默认为十六进制。
+/// * `max_bytes`: 最多返回的字节数，默认为64KB。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`BlobCell`。
+///
+/// # Examples
+///
+/// ```
+/// let result = fetch_blob_cell("/home/liuning/tmp/sqlite/my.db".to_string(), "files".to_string(), "content".to_string(), 1, Some("123456".to_string()), Some(BlobEncoding::Base64), None).await;
+/// if let Err(e) = result {
+///     assert!(false, "读取BLOB失败 {}", e);
+/// }
+/// ```
+pub async fn fetch_blob_cell(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>,
+                             encoding: Option<BlobEncoding>, max_bytes: Option<usize>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let cell = run_blocking(move || {
+        read_blob(&conn, &table_name, &column, rowid, encoding.unwrap_or(BlobEncoding::Hex), max_bytes.unwrap_or(DEFAULT_MAX_BYTES))
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(cell)))
+}
+
+/// 以十六进制或Base64编码的数据替换BLOB单元格的值。
+pub async fn update_blob_cell(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>, data: String, encoding: BlobEncoding) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    run_blocking(move || write_blob(&conn, &table_name, &column, rowid, &data, encoding)).await?;
+    Ok(ApiResp::suc())
+}
+
+/// 将BLOB单元格的内容保存到文件，返回写入的字节数。
+pub async fn save_blob_cell(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>, target_path: String) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let written = run_blocking(move || export_blob(&conn, &table_name, &column, rowid, Path::new(&target_path))).await?;
+    Ok(ApiResp::success(serde_json::json!(written)))
+}
+
+/// 以文件内容替换BLOB单元格的值，返回写入的字节数。文件内容通过增量BLOB I/O写入，不经过JSON传输。
+pub async fn load_blob_cell(db_path: String, table_name: String, column: String, rowid: i64, key: Option<String>, source_path: String) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let written = run_blocking(move || import_blob(&conn, &table_name, &column, rowid, Path::new(&source_path))).await?;
+    Ok(ApiResp::success(serde_json::json!(written)))
+}
+
+/// 将CSV或TSV文件导入到表中，所有数据在同一个事务中写入，被拒绝的行连同行号一并返回。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `table_name`: 目标表名，表不存在且`options.create_table`为`true`时按推断的字段类型新建。
+/// * `path`: CSV或TSV文件路径。
+/// * `key`: 可选的密钥。
+/// * `options`: 导入选项，包括CSV格式和字段映射。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`CsvImportReport`。
+///
+/// # Examples
+///
+/// ```
+/// let options = CsvImportOptions { create_table: true, ..Default::default() };
+/// let result = import_csv_file("/home/liuning/tmp/sqlite/my.db".to_string(), "users".to_string(), "/home/liuning/tmp/users.csv".to_string(), Some("123456".to_string()), options).await;
+/// if let Err(e) = result {
+///     assert!(false, "导入CSV失败 {}", e);
+/// }
+/// ```
+pub async fn import_csv_file(db_path: String, table_name: String, path: String, key: Option<String>, options: CsvImportOptions) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let report = run_blocking(move || import_csv(&conn, &table_name, &path, &options)).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 将表、视图或查询结果导出为CSV、JSON数组或NDJSON文件，结果逐行写入文件，不在内存中保留。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `source`: 导出的表、视图或查询语句。导出表或视图时先查询总行数以便报告进度。
+/// * `target_path`: 目标文件路径，已存在时将被覆盖。
+/// * `options`: 导出格式等选项。
+/// * `progress`: 可选的进度回调。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`DataExportReport`。
+///
+/// # Examples
+///
+/// ```
+/// let source = ExportSource::Table { table_name: "my_table".to_string(), order_by: None, filters: None };
+/// let options = ExportOptions { format: ExportFormat::Json, ..Default::default() };
+/// let result = export_rows_to_file("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), source, "/home/liuning/tmp/my_table.json".to_string(), options, None).await;
+/// if let Err(e) = result {
+///     assert!(false, "导出数据失败 {}", e);
+/// }
+/// ```
+pub async fn export_rows_to_file(db_path: String, key: Option<String>, source: ExportSource, target_path: String, options: ExportOptions,
+                                 progress: Option<DataExportProgressFn>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let report = run_blocking(move || {
+        conn.pragma_update(None, "query_only", true)?;
+        match source {
+            ExportSource::Table { table_name, order_by, filters } => {
+                let (names, is_table) = table_columns(&conn, &table_name)?;
+                let query = TableQuery { limit: i64::MAX as u64, order_by, filters, ..Default::default() };
+                // 只导出表本身的字段，不附加rowid。
+                let page = build_page_sql(quote_ident(&table_name).as_str(), &names, is_table, false, &query)?;
+                let mut stmt = conn.prepare(&page.count)?;
+                bind_json_params(&mut stmt, &Some(serde_json::Value::Array(page.count_args)))?;
+                let total: i64 = stmt.raw_query().next()?.ok_or("查询总条数失败")?.get(0)?;
+                export_query(&conn, &page.select, &Some(serde_json::Value::Array(page.select_args)), Some(total as u64), &target_path, &options, progress)
+            }
+            ExportSource::Query { sql, params } => export_query(&conn, &sql, &params, None, &target_path, &options, progress),
+        }
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 将数据库的结构和数据导出为SQL脚本，相当于sqlite3的`.dump`。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `target_path`: 脚本文件路径，已存在时将被覆盖。
+/// * `options`: 导出的对象范围等选项。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`DumpReport`。
+///
+/// # Examples
+///
+/// ```
+/// let result = dump_to_file("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), "/home/liuning/tmp/my.sql".to_string(), DumpOptions::default()).await;
+/// if let Err(e) = result {
+///     assert!(false, "导出SQL脚本失败 {}", e);
+/// }
+/// ```
+pub async fn dump_to_file(db_path: String, key: Option<String>, target_path: String, options: DumpOptions) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let report = run_blocking(move || {
+        let mut writer = BufWriter::new(File::create(&target_path)?);
+        dump_db(&conn, &mut writer, &options).map_err(|e| {
+            drop(writer);
+            let _ = fs::remove_file(&target_path);
+            e
+        })
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 在一个事务中执行SQL脚本，相当于sqlite3的`.read`，任一语句失败时不做任何修改。
+///
+/// # Arguments
+///
+/// * `db_path`: 目标数据库文件路径，文件不存在时按`key`和`cipher`新建。
+/// * `key`: 可选的密钥。
+/// * `cipher`: 新建或打开数据库时使用的加密参数，为`None`时沿用最近一次使用的参数。
+/// * `source_path`: 脚本文件路径，通常由`dump_to_file`生成。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`RestoreReport`。
+pub async fn restore_from_file(db_path: String, key: Option<String>, cipher: Option<CipherProfile>, source_path: String) -> DaoResult {
+    create_db_file(&db_path, &key, &cipher)?;
+    open_db_connections_with_cipher(&db_path, &key, &cipher)?;
+    let conn = open_registered_connection(&db_path, &key)?;
+    let report = run_blocking(move || restore_dump(&conn, BufReader::new(File::open(&source_path)?))).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 对数据库文件执行健康检查，数据库无需已打开。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥，为`None`时沿用该文件最近一次使用的密钥。
+/// * `cipher`: 可选的加密参数，为`None`时沿用该文件最近一次使用的参数。
+/// * `checks`: 要执行的检查项，为`None`时执行全部检查。
+/// * `max_errors`: `integrity_check`和`quick_check`最多报告的问题数，默认为100。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`HealthReport`，密钥错误等无法打开数据库的情况返回`OpenDbError`。
+///
+/// # Examples
+///
+/// ```
+/// let result = check_db_file("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), None, Some(vec![CheckKind::Quick]), None).await;
+/// if let Err(e) = result {
+///     assert!(false, "检查数据库失败 {}", e);
+/// }
+/// ```
+pub async fn check_db_file(db_path: String, key: Option<String>, cipher: Option<CipherProfile>, checks: Option<Vec<CheckKind>>, max_errors: Option<u32>) -> DaoResult {
+    let mut secret = registered_secret(&db_path, &key)?;
+    if let Some(cipher) = cipher {
+        secret.cipher = cipher;
+    }
+    let report = run_blocking(move || {
+        let conn = open_verified_connection(&db_path, &secret.key, &secret.cipher)?;
+        let checks = checks.unwrap_or_else(|| CheckKind::ALL.to_vec());
+        check_health(&conn, secret.key.is_some(), &checks, max_errors.unwrap_or(DEFAULT_MAX_ERRORS))
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 执行VACUUM、ANALYZE等维护操作，返回操作前后的文件大小和空闲页数。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `op`: 维护操作。`VacuumInto`的目标文件沿用源数据库的加密参数，指定了不同的密钥时生成后再修改密钥。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`MaintenanceReport`。
+///
+/// # Examples
+///
+/// ```
+/// let op = MaintenanceOp::VacuumInto { target_path: "/home/liuning/tmp/sqlite/compact.db".to_string(), target_key: Some("654321".to_string()) };
+/// let result = maintain_db("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), op).await;
+/// if let Err(e) = result {
+///     assert!(false, "维护数据库失败 {}", e);
+/// }
+/// ```
+pub async fn maintain_db(db_path: String, key: Option<String>, op: MaintenanceOp) -> DaoResult {
+    let secret = registered_secret(&db_path, &key)?;
+    if let MaintenanceOp::VacuumInto { target_key, .. } = &op {
+        check_same_encryption(&secret.key, &target_key.clone().or_else(|| secret.key.clone()))?;
+    }
+    let report = run_blocking(move || {
+        let conn = open_verified_connection(&db_path, &secret.key, &secret.cipher)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        let mut report = run_maintenance(&conn, &op)?;
+        if let MaintenanceOp::VacuumInto { target_path, target_key: Some(target_key) } = &op {
+            if secret.key.as_ref() != Some(target_key) {
+                // 修改密钥会重写所有页，目标文件的空间占用在修改后重新统计。
+                let rekeyed = open_verified_connection(target_path, &secret.key, &secret.cipher).and_then(|target| {
+                    target.pragma_update(None, "rekey", target_key)?;
+                    space_usage(&target)
+                });
+                match rekeyed {
+                    Ok(usage) => report.target = Some(usage),
+                    Err(e) => {
+                        let _ = fs::remove_file(target_path);
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 查询数据库的页大小、日志模式、SQLCipher版本等属性。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`DbInfo`。
+///
+/// # Examples
+///
+/// ```
+/// let result = read_db_properties("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string())).await;
+/// if let Err(e) = result {
+///     assert!(false, "查询数据库属性失败 {}", e);
+/// }
+/// ```
+pub async fn read_db_properties(db_path: String, key: Option<String>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let info = run_blocking(move || read_db_info(&conn)).await?;
+    Ok(ApiResp::success(serde_json::json!(info)))
 }
 
-/// 加载已定义的表和视图列表。
+/// 查询表或视图的完整结构，包括字段、索引、外键、触发器、WITHOUT ROWID和STRICT标记，以及表的总行数。
 ///
 /// # Arguments
 ///
 /// * `db_path`: 数据库文件路径。
 /// * `key`: 可选的密钥。
+/// * `name`: 表名或视图名。
 ///
-/// returns: Result<MetaResult, Box<dyn Error, Global>>
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`ObjectModel`。
 ///
 /// # Examples
 ///
 /// ```
-/// fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
-/// let load_result = load_tables("/home/foo/tmp/sqlite/sms.db".to_string(), Some("123456".to_string())).await;
-/// match load_result {
-///     Err(e) => assert!(false, "查询表名失败 {}", e),
-///     Ok(metas) => {
-///         println!("查询到表名列表 {:?}", metas);
-///     }
+/// let result = describe_db_object("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), "users".to_string()).await;
+/// if let Err(e) = result {
+///     assert!(false, "查询表结构失败 {}", e);
 /// }
 /// ```
-pub async fn load_tables(db_path: String, key: Option<String>) -> Result<MetaResult, Box<dyn Error>> {
-    let rb = open_db_connections(&db_path, &key)?;
-    let mut rb = rb.deref();
-
-    let mut metas = SqliteMeta::select_cols(&mut rb, "type as obj_type,name").await?;
-    let table_opt = Some(String::from("table"));
-    let view_opt = Some(String::from("view"));
-
-    let mut names = |opt: Option<String>| -> Vec<String> {
-        metas.iter_mut().filter(|m| opt.eq(&m.obj_type)).map(|m| {
-            return m.name.replace("".to_string()).unwrap();
-        }).collect()
-    };
-    let table_names = names(table_opt);
-    let view_names = names(view_opt);
-
-    let mut result = MetaResult { db_path, table_names: None, view_names: None, key };
-    if !table_names.is_empty() {
-        result.table_names = Some(table_names);
-    }
-    if !view_names.is_empty() {
-        result.view_names = Some(view_names);
-    }
-    Ok(result)
+pub async fn describe_db_object(db_path: String, key: Option<String>, name: String) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let model = run_blocking(move || describe_object(&conn, &name)).await?;
+    Ok(ApiResp::success(serde_json::json!(model)))
 }
 
-pub async fn fetch_table_sql(db_path: String, key: Option<String>, table_name: String) -> DaoResult {
-    let _rb = open_db_connections(&db_path, &key)?;
-    let rb = _rb.deref();
-    let sql: String = rb.fetch_decode("select sql from sqlite_master where name = ?", vec![to_value!(table_name)]).await?;
-    Ok(ApiResp::success(serde_json::Value::String(sql)))
+/// 读取表定义，供修改表结构的对话框编辑。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `table_name`: 表名。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`TableSpec`。
+pub async fn fetch_table_spec(db_path: String, key: Option<String>, table_name: String) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let spec = run_blocking(move || read_table_spec(&conn, &table_name)).await?;
+    Ok(ApiResp::success(serde_json::json!(spec)))
 }
 
-
-#[derive(Serialize, Deserialize)]
-pub struct TableData {
-    cols: Vec<TableInfo>,
-    rows: Vec<HashMap<String, Value>>,
+/// 根据表定义生成并校验`CREATE TABLE`语句，可选择直接建表。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `spec`: 表定义。
+/// * `execute`: 为`true`时建表，否则只校验并返回语句。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`CREATE TABLE`语句。
+pub async fn create_db_table(db_path: String, key: Option<String>, spec: TableSpec, execute: bool) -> DaoResult {
+    let mut conn = open_registered_connection(&db_path, &key)?;
+    let sql = run_blocking(move || {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        create_table(&mut conn, &spec, execute)
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(sql)))
 }
 
-/// 无条件查询目标表的数据（仅限制返回条数）。
+/// 按目标定义修改表结构，返回生成的SQL语句。
 ///
 /// # Arguments
 ///
 /// * `db_path`: 数据库文件路径。
-/// * `table_name`: 目标表名。
-/// * `limit`: 限制返回条数，应大于0。
 /// * `key`: 可选的密钥。
+/// * `table_name`: 要修改的表。
+/// * `spec`: 目标定义，保留的字段须设置`original_name`。
+/// * `apply`: 为`false`时只生成SQL供预览，不修改数据库。
 ///
-/// returns: Result<ApiResp, Box<dyn Error, Global>>
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`AlterPlan`。
 ///
 /// # Examples
 ///
 /// ```
-/// fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
-/// let result = fetch_rows("/home/liuning/tmp/sqlite/my.db".to_string(), "my_table".to_string(), 10, Some("123456".to_string())).await;
-/// if let Err(e) = result {
-///     assert!(false, "查询数据库失败 {}", e);
-/// } else {
-///     println!("查询到数据 {:?}", result.to_json_str("查询出错"));
+/// let result = alter_db_table("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), "users".to_string(), spec, false).await;
+/// if let Ok(resp) = result {
+///     println!("{}", resp.to_json());
 /// }
 /// ```
-pub async fn fetch_rows(db_path: String, table_name: String, limit: u64, key: Option<String>) -> DaoResult {
-    let conn = open_db_connections(&db_path, &key)?;
-    let rb = conn.deref();
-
-    /*
-    获取目标表或视图的字段名列表
-     */
-    let mut cols: Vec<TableInfo> = vec![];
-    let table_info_sql = format!("pragma table_info({})", table_name);
-    let result: Vec<HashMap<String, rbs::Value>> = rb.fetch_decode(table_info_sql.as_str(), vec![]).await.unwrap();
-    for r in result {
-        let mut data_type: String = String::new();
-        let mut name: String = String::new();
-        for (k, v) in r {
-            if k.eq("name") {
-                name.push_str(v.as_str().unwrap());
-            } else if k.eq("type") {
-                data_type.push_str(v.as_str().unwrap());
-            }
-        }
-        cols.push(TableInfo { data_type, name });
-    }
+pub async fn alter_db_table(db_path: String, key: Option<String>, table_name: String, spec: TableSpec, apply: bool) -> DaoResult {
+    let mut conn = open_registered_connection(&db_path, &key)?;
+    let plan = run_blocking(move || {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        alter_table(&mut conn, &table_name, &spec, apply)
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(plan)))
+}
 
-    /*
-    检查目标是表还是视图。
-     */
-    let mut is_table = true;
-    let target_type: String = rb.fetch_decode("select type from sqlite_master where name = ?", vec![to_value!(&table_name)]).await.unwrap();
-    if !target_type.eq("table") {
-        is_table = false;
-    }
+/// 列出索引，包括主键和UNIQUE约束自动创建的索引。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `table_name`: 只列出该表的索引，为`None`时列出所有表的索引。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`IndexEntry`数组。
+pub async fn fetch_indexes(db_path: String, key: Option<String>, table_name: Option<String>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let entries = run_blocking(move || list_indexes(&conn, table_name.as_deref())).await?;
+    Ok(ApiResp::success(serde_json::json!(entries)))
+}
 
-    /*
-    查询数据，若目标是表则附加rowid字段，以便后续修改操作。
-     */
-    let sql = format!("select {} * from {} limit ?", if is_table { "rowid," } else { "" }, table_name);
-    let rows: Vec<HashMap<String, Value>> = rb.fetch_decode(sql.as_str(), vec![to_value!(limit)]).await?;
-    Ok(ApiResp::success(serde_json::json!(TableData { cols, rows })))
+/// 创建索引，支持唯一索引、部分索引和表达式索引。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `spec`: 索引定义。
+/// * `execute`: 为`true`时建立索引，否则只校验并返回语句。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`CREATE INDEX`语句。
+pub async fn create_db_index(db_path: String, key: Option<String>, spec: IndexSpec, execute: bool) -> DaoResult {
+    let mut conn = open_registered_connection(&db_path, &key)?;
+    let sql = run_blocking(move || {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        create_index(&mut conn, &spec, execute)
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(sql)))
 }
 
+/// 删除索引。
+pub async fn drop_db_index(db_path: String, key: Option<String>, index_name: String) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    run_blocking(move || {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        drop_index(&conn, &index_name)
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(true)))
+}
 
-/// 执行用户输入的SQL语句。
+/// 分析语句对索引的使用情况，找出未被使用的索引和可以通过索引避免的全表扫描。
 ///
 /// # Arguments
 ///
 /// * `db_path`: 数据库文件路径。
-/// * `sql`: 用户SQL。
 /// * `key`: 可选的密钥。
+/// * `queries`: 要分析的语句，为`None`时分析该数据库最近执行过的语句。
 ///
-/// returns: Result<ApiResp, Box<dyn Error, Global>> 若是查询语句(或explain语句)返回`Vec<HashMap<String, Value>>`结构的数据，否则返回更新行数信息。
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`IndexUsageReport`。
 ///
 /// # Examples
 ///
 /// ```
-/// fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
-/// let query_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "select rowid, * from my_table", Some("123456".to_string())).await;
-/// if let Err(e) = query_result {
-///     assert!(false, "查询数据库失败: {}", e);
-/// } else {
-///     println!("查询到数据 {:?}", query_result.to_json_str("查询出错"));
-/// }
-///
-/// let insert_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "insert into my_table(id,name) values(2,'lisi')", Some("123456".to_string())).await;
-/// if let Err(e) = insert_result {
-///     assert!(false, "更新数据库失败: {}", e);
-/// } else {
-///     println!("本次操作结果 {:?}", insert_result.to_json_str("操作出错"));
+/// let result = analyze_db_index_usage("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), None).await;
+/// if let Ok(resp) = result {
+///     println!("{}", resp.to_json());
 /// }
 /// ```
-pub async fn exec_sql(db_path: String, sql: &str, key: Option<String>) -> DaoResult {
-    let conn = open_db_connections(&db_path, &key)?;
-    let rb = conn.deref();
+pub async fn analyze_db_index_usage(db_path: String, key: Option<String>, queries: Option<Vec<String>>) -> DaoResult {
+    let queries = match queries {
+        Some(queries) => queries,
+        None => recent_queries(&db_path)?,
+    };
+    let conn = open_registered_connection(&db_path, &key)?;
+    let report = run_blocking(move || analyze_index_usage(&conn, &queries)).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
+
+/// 列出触发器及其所属的表、触发时机和事件。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `table_name`: 只列出该表或视图上的触发器，为`None`时列出全部。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`TriggerEntry`数组。
+pub async fn fetch_triggers(db_path: String, key: Option<String>, table_name: Option<String>) -> DaoResult {
+    let conn = open_registered_connection(&db_path, &key)?;
+    let triggers = run_blocking(move || list_triggers(&conn, table_name.as_deref())).await?;
+    Ok(ApiResp::success(serde_json::json!(triggers)))
+}
+
+/// 在一个事务中用新的定义替换视图或触发器。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `name`: 要替换的视图或触发器。
+/// * `sql`: 新的`CREATE VIEW`或`CREATE TRIGGER`语句。
+/// * `force`: 有视图或触发器因此失效时是否仍然提交。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`ReplaceReport`。
+pub async fn replace_db_object(db_path: String, key: Option<String>, name: String, sql: String, force: bool) -> DaoResult {
+    let mut conn = open_registered_connection(&db_path, &key)?;
+    let report = run_blocking(move || {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        replace_object(&mut conn, &name, &sql, force)
+    }).await?;
+    Ok(ApiResp::success(serde_json::json!(report)))
+}
 
-    match sql.trim().to_lowercase().as_str() {
-        s if s.starts_with("select") || s.starts_with("pragma") || s.starts_with("explain") => {
-            let rows: Vec<HashMap<String, Value>> = rb.fetch_decode(s, vec![]).await?;
-            Ok(ApiResp::success(serde_json::json!(rows)))
+/// 修改`user_version`、`application_id`、`journal_mode`或`foreign_keys`，返回修改后的数据库属性。
+///
+/// 修改日志模式或外键约束前丢弃缓存的连接池并关闭该库的游标，这两项设置保存到关闭数据库为止，之后打开的连接池均按此设置。
+///
+/// # Arguments
+///
+/// * `db_path`: 数据库文件路径。
+/// * `key`: 可选的密钥。
+/// * `update`: 要修改的PRAGMA及其新值。
+///
+/// returns: Result<ApiResp, Box<dyn Error, Global>> 返回`DbInfo`。
+pub async fn update_db_pragma(db_path: String, key: Option<String>, update: PragmaUpdate) -> DaoResult {
+    match &update {
+        PragmaUpdate::ForeignKeys(enabled) => {
+            DB_FOREIGN_KEYS.lock()?.insert(db_path.clone(), *enabled);
+            OPENED_DBS.lock()?.remove(&db_path);
+            close_db_cursors(&db_path)?;
         }
-        _ => {
-            let result = rb.exec(sql, vec![]).await?;
-            Ok(ApiResp::success(serde_json::json!(result)))
+        PragmaUpdate::JournalMode(_) => {
+            OPENED_DBS.lock()?.remove(&db_path);
+            close_db_cursors(&db_path)?;
         }
+        _ => {}
+    }
+    let conn = open_registered_connection(&db_path, &key)?;
+    let journal_mode = match &update {
+        PragmaUpdate::JournalMode(mode) => Some(*mode),
+        _ => None,
+    };
+    let info = run_blocking(move || {
+        conn.busy_timeout(Duration::from_secs(5))?;
+        apply_pragma(&conn, &update)?;
+        read_db_info(&conn)
+    }).await?;
+    if let Some(mode) = journal_mode {
+        DB_JOURNAL_MODES.lock()?.insert(db_path, mode);
     }
+    Ok(ApiResp::success(serde_json::json!(info)))
 }
 
 /// 编辑目标表的数据，包括删除、更新和新增。
@@ -343,16 +1825,21 @@ pub async fn exec_sql(db_path: String, sql: &str, key: Option<String>) -> DaoRes
 pub async fn edit_data(db_path: String, table_name: String, key: Option<String>, new_rows: Option<serde_json::Value>, update_rows: Option<serde_json::Value>, del_rows: Option<Vec<String>>) -> DaoResult {
     let conn = open_db_connections(&db_path, &key)?;
     let rb = conn.deref();
+    let table = quote_ident(&table_name);
     let mut tx = rb.acquire_begin().await?;
 
     /*
     删除数据
      */
     if let Some(del_rows) = del_rows {
-        let rowids = del_rows.join(",");
-        let sql = format!("delete from {} where rowid in ({})", table_name, rowids);
-        let result = tx.exec(sql.as_str(), vec![]).await;
-        rollback!(result, tx, -1);
+        let rowids: Result<Vec<rbs::Value>, _> = del_rows.iter().map(|r| parse_rowid(r).map(rbs::Value::I64)).collect();
+        rollback!(&rowids, tx, -1);
+        let rowids = rowids?;
+        if !rowids.is_empty() {
+            let sql = format!("delete from {} where rowid in ({})", table, vec!["?"; rowids.len()].join(","));
+            let result = tx.exec(sql.as_str(), rowids).await;
+            rollback!(result, tx, -1);
+        }
     }
 
     /*
@@ -365,6 +1852,11 @@ pub async fn edit_data(db_path: String, table_name: String, key: Option<String>,
             for (rowid, row) in rows_map {
                 // 每一行更新数据是map类型的
                 if let serde_json::Value::Object(col_map) = row {
+                    let rowid = parse_rowid(&rowid);
+                    rollback!(&rowid, tx, -1);
+                    if col_map.is_empty() {
+                        continue;
+                    }
                     let mut fields: Vec<String> = vec![];
                     let mut args: Vec<rbs::Value> = vec![];
 
@@ -373,12 +1865,13 @@ pub async fn edit_data(db_path: String, table_name: String, key: Option<String>,
                     同时也将json类型的数值转换为rbatis定义的数值类型。
                      */
                     for (col, new_val) in col_map {
-                        fields.push(format!("{}=?", col));
+                        fields.push(format!("{}=?", quote_ident(&col)));
                         args.push(rbs::to_value!(new_val));
                     }
                     let fields_part = fields.join(",");
 
-                    let sql = format!("update {} set {} where rowid = {}", table_name, fields_part, rowid);
+                    args.push(rbs::Value::I64(rowid?));
+                    let sql = format!("update {} set {} where rowid = ?", table, fields_part);
                     let result = tx.exec(sql.as_str(), args).await;
                     rollback!(result, tx, -1);
                 }
@@ -398,12 +1891,16 @@ pub async fn edit_data(db_path: String, table_name: String, key: Option<String>,
                     let mut params: Vec<String> = vec![];
 
                     for (col, val) in row_map {
-                        cols.push(col);
+                        cols.push(quote_ident(&col));
                         args.push(rbs::to_value!(val));
                         params.push("?".to_string());
                     }
 
-                    let sql = format!("insert into {} ({}) values ({})", table_name, cols.join(","), params.join(","));
+                    let sql = if cols.is_empty() {
+                        format!("insert into {} default values", table)
+                    } else {
+                        format!("insert into {} ({}) values ({})", table, cols.join(","), params.join(","))
+                    };
 
                     let result = tx.exec(sql.as_str(), args).await;
                     rollback!(result, tx, -1);
@@ -423,6 +1920,8 @@ pub async fn edit_data(db_path: String, table_name: String, key: Option<String>,
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
     use api_resp::TransformResult;
     use names::{Generator, Name};
     use rand::prelude::*;
@@ -479,10 +1978,340 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    pub async fn test_open_db_with_cipher() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-v3.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+
+        {
+            let conn = Connection::open(&db_path).unwrap();
+            conn.pragma_update(None, "key", "123456").unwrap();
+            conn.pragma_update(None, "cipher_compatibility", 3).unwrap();
+            conn.execute_batch("create table v3_table (id integer, name text); insert into v3_table values (1, 'a');").unwrap();
+        }
+
+        let key = Some("123456".to_string());
+        let cipher = Some(CipherProfile { preset: CipherPreset::Sqlcipher3, ..Default::default() });
+        let result = load_tables(db_path.clone(), key.clone(), cipher).await;
+        match result {
+            Err(e) => panic!("按SQLCipher 3参数打开数据库失败 {}", e),
+            Ok(metas) => assert_eq!(metas.table_names, Some(vec!["v3_table".to_string()])),
+        }
+
+        // 后续调用沿用已记录的加密参数。
+        let rb = open_db_connections(&db_path, &key).unwrap();
+        let count: i64 = rb.deref().fetch_decode("select count(*) from v3_table", vec![]).await.unwrap();
+        assert_eq!(count, 1);
+        remove_db_connection(&db_path).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_open_db_errors() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-open-errors.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+        let key = Some("123456".to_string());
+        let code_of = |result: Result<Arc<Rbatis>, Box<dyn Error>>| result.err().map(|e| error_code(e.as_ref()));
+
+        assert_eq!(code_of(open_db_connections(&db_path, &key)), Some(1002), "文件不存在时应返回 FileMissing");
+        assert!(!path.exists(), "打开时不应自动创建文件");
+
+        {
+            let conn = open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table probe_table (id integer);").unwrap();
+        }
+        assert_eq!(code_of(open_db_connections(&db_path, &Some("654321".to_string()))), Some(1001), "密钥错误时应返回 WrongKey");
+        assert!(open_db_connections(&db_path, &key).is_ok());
+
+        // 已有连接池时，错误的密钥不影响原连接池。
+        assert_eq!(code_of(open_db_connections(&db_path, &Some("654321".to_string()))), Some(1001));
+        let rb = open_db_connections(&db_path, &None).unwrap();
+        let count: i64 = rb.deref().fetch_decode("select count(*) from probe_table", vec![]).await.unwrap();
+        assert_eq!(count, 0);
+        drop(rb);
+
+        // 在阻塞线程池中执行的操作同样返回错误代码。
+        let checked = check_db_file(db_path.clone(), Some("654321".to_string()), None, None, None).await;
+        assert_eq!(checked.err().map(|e| error_code(e.as_ref())), Some(1001));
+        let locked = run_blocking(|| -> Result<(), Box<dyn Error>> { Err(VaultLocked.into()) }).await;
+        assert_eq!(locked.err().map(|e| error_code(e.as_ref())), Some(1005));
+        remove_db_connection(&db_path).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(code_of(open_db_connections(&db_path, &key)), Some(1002));
+        remove_db_connection(&db_path).unwrap();
+
+        // 其它连接持有排它锁。
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-locked.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+        let conn = open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap();
+        conn.execute_batch("create table probe_table (id integer); begin exclusive; insert into probe_table values (1);").unwrap();
+        assert_eq!(code_of(open_db_connections(&db_path, &key)), Some(1004), "数据库被锁定时应返回 Locked");
+        conn.execute_batch("rollback").unwrap();
+        assert!(open_db_connections(&db_path, &key).is_ok());
+        remove_db_connection(&db_path).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_rekey_db() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-rekey.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+
+        let old_key = Some("123456".to_string());
+        let new_key = Some("it's new".to_string());
+        create_db_file(&db_path, &old_key, &None).unwrap();
+        let rb = open_db_connections(&db_path, &old_key).unwrap();
+        rb.deref().exec("create table rekey_table (id integer)", vec![]).await.unwrap();
+        rb.deref().exec("insert into rekey_table values (1)", vec![]).await.unwrap();
+        drop(rb);
+
+        // 丢弃的连接池在异步任务中关闭连接，与`change_db_key`一样在阻塞线程池中修改密钥，使这些任务得以执行。
+        let rekey = |key: &Option<String>, new_key: &Option<String>| {
+            let (db_path, key, new_key) = (db_path.clone(), key.clone(), new_key.clone());
+            run_blocking(move || rekey_db(&db_path, &key, &new_key))
+        };
+        let count_rows = |key: &Option<String>| -> rusqlite::Result<i64> {
+            let conn = open_raw_connection(&db_path, key, &CipherProfile::default()).unwrap();
+            conn.query_row("select count(*) from rekey_table", [], |r| r.get(0))
+        };
+
+        rekey(&old_key, &new_key).await.unwrap();
+        assert!(count_rows(&old_key).is_err(), "旧密钥应已失效");
+        assert_eq!(count_rows(&new_key).unwrap(), 1);
+
+        // 原密钥错误时保留连接池。
+        open_db_connections(&db_path, &new_key).unwrap();
+        assert!(rekey(&Some("wrong".to_string()), &None).await.is_err());
+        assert!(OPENED_DBS.lock().unwrap().contains_key(&db_path), "原密钥错误时不应丢弃连接池");
+
+        // 其它连接仍在使用WAL模式的数据库时不替换文件。
+        let other = open_raw_connection(&db_path, &new_key, &CipherProfile::default()).unwrap();
+        other.execute_batch("pragma journal_mode = wal; pragma wal_autocheckpoint = 0; insert into rekey_table values (2);").unwrap();
+        assert!(rekey(&new_key, &None).await.is_err(), "有其它连接时应拒绝替换文件");
+        assert_eq!(count_rows(&new_key).unwrap(), 2);
+        drop(other);
+        rekey(&new_key, &None).await.unwrap();
+        assert_eq!(count_rows(&None).unwrap(), 2);
+        let conn = open_raw_connection(&db_path, &None, &CipherProfile::default()).unwrap();
+        let journal_mode: String = conn.pragma_query_value(None, "journal_mode", |r| r.get(0)).unwrap();
+        assert_eq!(journal_mode, "wal", "替换后应保留原有的日志模式");
+        drop(conn);
+
+        rekey(&None, &old_key).await.unwrap();
+        assert!(count_rows(&None).is_err(), "数据库应已加密");
+        assert_eq!(count_rows(&old_key).unwrap(), 2);
+
+        let rb = open_db_connections(&db_path, &old_key).unwrap();
+        let count: i64 = rb.deref().fetch_decode("select count(*) from rekey_table", vec![]).await.unwrap();
+        assert_eq!(count, 2);
+        remove_db_connection(&db_path).unwrap();
+    }
+
+    /// 返回临时目录中的文件路径，文件已存在时先删除。
+    fn temp_path(name: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(name);
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    pub async fn test_export_to_file() {
+        let db_path = temp_path("sqlcipher-front-export-src.db");
+        let plain_path = temp_path("sqlcipher-front-export-plain.db");
+        let v3_path = temp_path("sqlcipher-front-export-v3.db");
+
+        let key = Some("123456".to_string());
+        {
+            let conn = open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table export_table (id integer); insert into export_table values (1), (2); pragma user_version = 7;").unwrap();
+        }
+
+        let reports = Arc::new(AtomicUsize::new(0));
+        let last = Arc::new(Mutex::new(None));
+        let (counter, last_report) = (reports.clone(), last.clone());
+        let progress: ExportProgressFn = Box::new(move |p| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            *last_report.lock().unwrap() = Some((p.written_bytes, p.total_bytes));
+        });
+        let exported = export_to_file(db_path.clone(), key.clone(), plain_path.clone(), None, None, Some(progress)).await.unwrap();
+        assert_eq!(exported.name, "sqlcipher-front-export-plain.db");
+        assert_eq!(exported.key, None);
+        assert!(reports.load(Ordering::SeqCst) >= 1, "导出期间应至少报告一次进度");
+        let file_size = fs::metadata(&plain_path).unwrap().len();
+        assert_eq!(*last.lock().unwrap(), Some((file_size, file_size)), "最后一次报告应为100%");
+
+        let conn = open_raw_connection(&plain_path, &None, &CipherProfile::default()).unwrap();
+        let count: i64 = conn.query_row("select count(*) from export_table", [], |r| r.get(0)).unwrap();
+        let user_version: i64 = conn.query_row("pragma user_version", [], |r| r.get(0)).unwrap();
+        assert_eq!((count, user_version), (2, 7));
+
+        let v3 = CipherProfile { preset: CipherPreset::Sqlcipher3, ..Default::default() };
+        let new_key = Some("654321".to_string());
+        export_to_file(db_path.clone(), key.clone(), v3_path.clone(), new_key.clone(), Some(v3.clone()), None).await.unwrap();
+        let conn = open_raw_connection(&v3_path, &new_key, &CipherProfile::default()).unwrap();
+        assert!(conn.query_row("select count(*) from export_table", [], |r| r.get::<_, i64>(0)).is_err(), "应无法以默认参数打开SQLCipher 3格式文件");
+        let conn = open_raw_connection(&v3_path, &new_key, &v3).unwrap();
+        let count: i64 = conn.query_row("select count(*) from export_table", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+
+        assert!(export_to_file(db_path, key, v3_path, None, None, None).await.is_err(), "目标文件已存在时应拒绝导出");
+    }
+
+    #[tokio::test]
+    pub async fn test_backup_and_restore() {
+        let db_path = temp_path("sqlcipher-front-backup-src.db");
+        let backup_path = temp_path("sqlcipher-front-backup.db");
+        let plain_path = temp_path("sqlcipher-front-backup-plain.db");
+
+        let key = Some("123456".to_string());
+        {
+            let conn = open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table backup_table (id integer); insert into backup_table values (1), (2);").unwrap();
+        }
+        open_db_connections(&db_path, &key).unwrap();
+
+        let pages = Arc::new(Mutex::new(vec![]));
+        let recorded = pages.clone();
+        let progress: BackupProgressFn = Box::new(move |p| recorded.lock().unwrap().push((p.copied_pages, p.total_pages)));
+        let new_key = Some("654321".to_string());
+        let backup = backup_to_file(db_path.clone(), key.clone(), backup_path.clone(), new_key.clone(), Some(progress)).await.unwrap();
+        assert_eq!(backup.key, new_key);
+        let (copied, total) = *pages.lock().unwrap().last().unwrap();
+        assert!(total > 0 && copied == total, "{:?}", pages);
+
+        let conn = open_raw_connection(&backup_path, &new_key, &CipherProfile::default()).unwrap();
+        let count: i64 = conn.query_row("select count(*) from backup_table", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+        assert!(backup_to_file(db_path.clone(), key.clone(), backup_path.clone(), None, None).await.is_err(), "目标文件已存在时应拒绝备份");
+        open_raw_connection(&plain_path, &None, &CipherProfile::default()).unwrap().execute_batch("create table t (v)").unwrap();
+        let encrypted_path = temp_path("sqlcipher-front-backup-encrypted.db");
+        assert!(backup_to_file(plain_path, None, encrypted_path.clone(), key.clone(), None).await.is_err(), "不能在加密和未加密之间转换");
+        assert!(!Path::new(&encrypted_path).exists());
+
+        {
+            let conn = open_registered_connection(&db_path, &key).unwrap();
+            conn.execute_batch("delete from backup_table; create table after_backup (v);").unwrap();
+        }
+        assert!(restore_from_backup(db_path.clone(), key.clone(), backup_path.clone(), Some("wrong".to_string()), None).await.is_err());
+        assert!(OPENED_DBS.lock().unwrap().contains_key(&db_path), "备份文件无法读取时不应丢弃连接池");
+        restore_from_backup(db_path.clone(), key.clone(), backup_path.clone(), new_key, None).await.unwrap();
+        assert!(!OPENED_DBS.lock().unwrap().contains_key(&db_path), "恢复后应丢弃缓存的连接池");
+
+        let conn = open_registered_connection(&db_path, &key).unwrap();
+        let count: i64 = conn.query_row("select count(*) from backup_table", [], |r| r.get(0)).unwrap();
+        let tables: i64 = conn.query_row("select count(*) from sqlite_master where name = 'after_backup'", [], |r| r.get(0)).unwrap();
+        assert_eq!((count, tables), (2, 0));
+    }
+
+    #[tokio::test]
+    pub async fn test_maintain_db() {
+        let db_path = temp_path("sqlcipher-front-maintain.db");
+        let target_path = temp_path("sqlcipher-front-maintain-compact.db");
+        let key = Some("123456".to_string());
+        {
+            let conn = open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table t (v); insert into t values (1), (2);").unwrap();
+        }
+
+        let plain = MaintenanceOp::VacuumInto { target_path: target_path.clone(), target_key: None };
+        assert!(maintain_db(db_path.clone(), None, plain).await.is_err(), "密钥错误时应无法打开");
+        let new_key = Some("654321".to_string());
+        let op = MaintenanceOp::VacuumInto { target_path: target_path.clone(), target_key: new_key.clone() };
+        let resp = maintain_db(db_path.clone(), key.clone(), op).await.unwrap();
+        let target_size = resp.get_data().clone().unwrap()["target"]["file_size"].as_u64().unwrap();
+        assert_eq!(target_size, fs::metadata(&target_path).unwrap().len(), "应统计修改密钥后的目标文件");
+        let conn = open_verified_connection(&target_path, &new_key, &CipherProfile::default()).unwrap();
+        let count: i64 = conn.query_row("select count(*) from t", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2);
+        maintain_db(db_path, key, MaintenanceOp::Analyze).await.unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_update_db_pragma() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-pragma.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+        let key = Some("123456".to_string());
+        open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap().execute_batch("create table t (v)").unwrap();
+        open_db_connections(&db_path, &key).unwrap();
+
+        update_db_pragma(db_path.clone(), key.clone(), PragmaUpdate::ForeignKeys(false)).await.unwrap();
+        assert!(!OPENED_DBS.lock().unwrap().contains_key(&db_path), "修改外键约束后应丢弃连接池");
+        let conn = open_registered_connection(&db_path, &key).unwrap();
+        assert!(!read_db_info(&conn).unwrap().foreign_keys, "之后打开的连接应沿用外键约束的设置");
+        update_db_pragma(db_path.clone(), key.clone(), PragmaUpdate::UserVersion(3)).await.unwrap();
+        assert_eq!(read_db_info(&conn).unwrap().user_version, 3);
+        drop(conn);
+
+        // 连接池默认使用WAL模式，重建后应保持修改后的日志模式。
+        let rb = open_db_connections(&db_path, &key).unwrap();
+        let mode: String = rb.deref().fetch_decode("pragma journal_mode", vec![]).await.unwrap();
+        assert_eq!(mode, "wal");
+        drop(rb);
+        update_db_pragma(db_path.clone(), key.clone(), PragmaUpdate::JournalMode(JournalMode::Delete)).await.unwrap();
+        let rb = open_db_connections(&db_path, &key).unwrap();
+        let mode: String = rb.deref().fetch_decode("pragma journal_mode", vec![]).await.unwrap();
+        assert_eq!(mode, "delete", "重建的连接池不应还原日志模式");
+        drop(rb);
+
+        remove_db_connection(&db_path).unwrap();
+        assert!(read_db_info(&open_registered_connection(&db_path, &key).unwrap()).unwrap().foreign_keys, "关闭数据库后应恢复默认设置");
+    }
+
+    #[tokio::test]
+    pub async fn test_migrate_db() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-legacy.db");
+        let db_path = path.to_str().unwrap().to_string();
+        let target_path = default_migrate_path(&db_path);
+        assert!(target_path.ends_with("sqlcipher-front-legacy-sqlcipher4.db"));
+        let _ = std::fs::remove_file(&db_path);
+        let _ = std::fs::remove_file(&target_path);
+
+        let key = Some("123456".to_string());
+        let v3 = CipherProfile { preset: CipherPreset::Sqlcipher3, ..Default::default() };
+        {
+            let conn = open_raw_connection(&db_path, &key, &v3).unwrap();
+            conn.execute_batch("create table legacy_table (id integer); insert into legacy_table values (1);").unwrap();
+        }
+        // 保持连接打开且不自动检查点，使新插入的行只存在于WAL文件中。
+        let wal_conn = open_raw_connection(&db_path, &key, &v3).unwrap();
+        wal_conn.execute_batch("pragma journal_mode = wal; pragma wal_autocheckpoint = 0; insert into legacy_table values (2);").unwrap();
+
+        let detected = detect_cipher(db_path.clone(), "123456".to_string()).await.unwrap().unwrap();
+        assert_eq!(detected.preset, CipherPreset::Sqlcipher3);
+        assert!(detected.legacy);
+        assert!(detect_cipher(db_path.clone(), "wrong".to_string()).await.unwrap().is_none());
+
+        let migrated = migrate_db(db_path.clone(), "123456".to_string(), None, false).await.unwrap();
+        assert_eq!(migrated.target.path, target_path);
+        drop(wal_conn);
+
+        // 源文件保持不变，副本可按默认参数读取。
+        let conn = open_raw_connection(&db_path, &key, &CipherProfile::default()).unwrap();
+        assert!(probe_connection(&conn).is_err());
+        let conn = open_raw_connection(&target_path, &key, &CipherProfile::default()).unwrap();
+        let count: i64 = conn.query_row("select count(*) from legacy_table", [], |r| r.get(0)).unwrap();
+        assert_eq!(count, 2, "WAL中的内容应随主文件一并迁移");
+
+        open_db_connections_with_cipher(&target_path, &key, &None).unwrap();
+        assert!(migrate_db(target_path.clone(), "123456".to_string(), None, true).await.is_err(), "当前格式的数据库不应再次迁移");
+        assert!(OPENED_DBS.lock().unwrap().contains_key(&target_path), "无需迁移时不应丢弃连接池");
+    }
+
     #[tokio::test]
     pub async fn test_load_tables() {
         fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
-        let load_result = load_tables("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string())).await;
+        let load_result = load_tables("/home/liuning/tmp/sqlite/my.db".to_string(), Some("123456".to_string()), None).await;
         match load_result {
             Err(e) => assert!(false, "查询表名失败 {}", e),
             Ok(metas) => {
@@ -494,7 +2323,8 @@ mod tests {
     #[tokio::test]
     pub async fn test_fetch_rows() {
         fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
-        let result = fetch_rows("/home/liuning/tmp/sqlite/my.db".to_string(), "v_my_table".to_string(), 10, Some("123456".to_string())).await;
+        let query = TableQuery { limit: 10, ..Default::default() };
+        let result = fetch_rows("/home/liuning/tmp/sqlite/my.db".to_string(), "v_my_table".to_string(), query, Some("123456".to_string())).await;
         if let Err(e) = result {
             assert!(false, "查询数据库失败 {}", e);
         } else {
@@ -502,11 +2332,162 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    pub async fn test_fetch_rows_page() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-page.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+        {
+            let conn = open_raw_connection(&db_path, &None, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table page_table (id integer, name text);\
+                with recursive c(x) as (select 1 union all select x + 1 from c limit 100) insert into page_table select x, 'n' || (x % 10) from c;").unwrap();
+        }
+
+        let fetch = |query: TableQuery| {
+            let db_path = db_path.clone();
+            async move {
+                let resp = fetch_rows(db_path, "page_table".to_string(), query, None).await.unwrap();
+                let data = resp.get_data().clone().unwrap();
+                let ids: Vec<i64> = data["rows"].as_array().unwrap().iter().map(|r| r[1].as_i64().unwrap()).collect();
+                (ids, data["total"].as_u64())
+            }
+        };
+
+        let (ids, total) = fetch(TableQuery { limit: 3, offset: Some(10), ..Default::default() }).await;
+        assert_eq!((ids, total), (vec![11, 12, 13], None));
+
+        let filters = Some(vec![
+            FilterSpec { column: "name".to_string(), op: FilterOp::Eq, value: Some(serde_json::json!("n3")) },
+            FilterSpec { column: "id".to_string(), op: FilterOp::Gt, value: Some(serde_json::json!(20)) },
+        ]);
+        let order_by = Some(vec![SortSpec { column: "id".to_string(), desc: true }]);
+        let (ids, total) = fetch(TableQuery { limit: 2, order_by, filters, with_total: true, ..Default::default() }).await;
+        assert_eq!((ids, total), (vec![93, 83], Some(8)));
+
+        let (ids, _) = fetch(TableQuery { limit: 3, after_rowid: Some(97), ..Default::default() }).await;
+        assert_eq!(ids, vec![98, 99, 100]);
+        let order_by = Some(vec![SortSpec { column: "rowid".to_string(), desc: true }]);
+        let (ids, _) = fetch(TableQuery { limit: 2, after_rowid: Some(3), order_by, ..Default::default() }).await;
+        assert_eq!(ids, vec![2, 1]);
+
+        let filters = Some(vec![FilterSpec { column: "id".to_string(), op: FilterOp::In, value: Some(serde_json::json!([5, 7])) }]);
+        let (ids, total) = fetch(TableQuery { limit: 10, filters, with_total: true, ..Default::default() }).await;
+        assert_eq!((ids, total), (vec![5, 7], Some(2)));
+
+        // 不存在的字段和注入尝试应被拒绝。
+        let filters = Some(vec![FilterSpec { column: "id = 1 or 1".to_string(), op: FilterOp::IsNull, value: None }]);
+        assert!(fetch_rows(db_path.clone(), "page_table".to_string(), TableQuery { limit: 10, filters, ..Default::default() }, None).await.is_err());
+        let order_by = Some(vec![SortSpec { column: "name".to_string(), desc: false }]);
+        assert!(fetch_rows(db_path.clone(), "page_table".to_string(), TableQuery { limit: 10, after_rowid: Some(1), order_by, ..Default::default() }, None).await.is_err());
+
+        // 字段占用rowid别名时改用其它别名，别名都被占用时退回按偏移量分页。
+        {
+            let conn = open_raw_connection(&db_path, &None, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table shadow_table (rowid text, v integer); create table full_table (rowid, _rowid_, oid, v);\
+                insert into shadow_table values ('c', 1), ('b', 2), ('a', 3); insert into full_table values ('x', 'y', 'z', 1), ('x', 'y', 'z', 2);").unwrap();
+        }
+        let query = TableQuery { limit: 10, after_rowid: Some(1), order_by: Some(vec![SortSpec { column: "_rowid_".to_string(), desc: false }]), ..Default::default() };
+        let data = fetch_rows(db_path.clone(), "shadow_table".to_string(), query, None).await.unwrap().get_data().clone().unwrap();
+        assert_eq!(data["rows"], serde_json::json!([[2, "b", 2], [3, "a", 3]]), "应按真实的rowid分页");
+        let query = TableQuery { limit: 10, after_rowid: Some(1), order_by: Some(vec![SortSpec { column: "rowid".to_string(), desc: false }]), ..Default::default() };
+        assert!(fetch_rows(db_path.clone(), "shadow_table".to_string(), query, None).await.is_err(), "rowid字段不是真实的rowid");
+        let query = TableQuery { limit: 10, offset: Some(1), after_rowid: Some(1), ..Default::default() };
+        let data = fetch_rows(db_path.clone(), "full_table".to_string(), query, None).await.unwrap().get_data().clone().unwrap();
+        assert_eq!(data["rows"].as_array().unwrap().len(), 1);
+        let query = TableQuery { limit: 10, after_rowid: Some(1), ..Default::default() };
+        assert!(fetch_rows(db_path.clone(), "full_table".to_string(), query, None).await.is_err());
+
+        // 游标按相同的过滤条件返回全部数据，首列为rowid。
+        let filters = Some(vec![FilterSpec { column: "name".to_string(), op: FilterOp::Eq, value: Some(serde_json::json!("n3")) }]);
+        let resp = open_table_cursor(db_path.clone(), "page_table".to_string(), TableQuery { filters, ..Default::default() }, None).await.unwrap();
+        let info = resp.get_data().clone().unwrap();
+        let names: Vec<&str> = info["columns"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["rowid", "id", "name"]);
+        let batch = serde_json::json!(crate::support::cursor::fetch_next(info["cursor_id"].as_str().unwrap(), 100).unwrap());
+        assert_eq!(batch["rows"].as_array().unwrap().len(), 10);
+        assert_eq!(batch["done"], serde_json::json!(true));
+
+        // 导出表数据时不附加rowid。
+        let mut csv_path = std::env::temp_dir();
+        csv_path.push("sqlcipher-front-page.csv");
+        let csv_path = csv_path.to_str().unwrap().to_string();
+        let filters = Some(vec![FilterSpec { column: "id".to_string(), op: FilterOp::Le, value: Some(serde_json::json!(2)) }]);
+        let source = ExportSource::Table { table_name: "page_table".to_string(), order_by: None, filters };
+        export_rows_to_file(db_path.clone(), None, source, csv_path.clone(), ExportOptions::default(), None).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&csv_path).unwrap().lines().collect::<Vec<_>>(), vec!["id,name", "1,n1", "2,n2"]);
+
+        {
+            let conn = open_raw_connection(&db_path, &None, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table blob_table (data blob); insert into blob_table values (zeroblob(1000));").unwrap();
+        }
+        let resp = fetch_rows(db_path.clone(), "blob_table".to_string(), TableQuery { limit: 1, ..Default::default() }, None).await.unwrap();
+        assert_eq!(resp.get_data().clone().unwrap()["rows"][0][1], serde_json::json!({"blob_size": 1000}));
+        remove_db_connection(&db_path).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn test_hostile_identifiers() {
+        let mut path = std::env::temp_dir();
+        path.push("sqlcipher-front-hostile.db");
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_str().unwrap().to_string();
+        let tables = ["order", "my \"quoted\" table", "t); drop table victim; --"];
+        {
+            let conn = open_raw_connection(&db_path, &None, &CipherProfile::default()).unwrap();
+            conn.execute_batch("create table victim (id integer)").unwrap();
+            for table in tables {
+                conn.execute_batch(format!("create table {} (\"select\" text, \"col with space\" integer, \"q\"\"uote\" text)", quote_ident(table)).as_str()).unwrap();
+            }
+        }
+
+        for table in tables {
+            let new_rows = serde_json::json!([
+                {"select": "a", "col with space": 1, "q\"uote": "x"},
+                {"select": "b", "col with space": 2},
+                {}
+            ]);
+            let resp = edit_data(db_path.clone(), table.to_string(), None, Some(new_rows), None, None).await.unwrap();
+            assert!(resp.is_success(), "{} 新增数据失败 {}", table, resp.get_message());
+
+            let update_rows = serde_json::json!({"1": {"select": "a'b", "q\"uote": "y"}});
+            let resp = edit_data(db_path.clone(), table.to_string(), None, None, Some(update_rows), Some(vec!["2".to_string()])).await.unwrap();
+            assert!(resp.is_success(), "{} 修改数据失败 {}", table, resp.get_message());
+
+            let order_by = Some(vec![SortSpec { column: "col with space".to_string(), desc: false }]);
+            let filters = Some(vec![FilterSpec { column: "q\"uote".to_string(), op: FilterOp::Eq, value: Some(serde_json::json!("y")) }]);
+            let query = TableQuery { limit: 10, order_by, filters, with_total: true, ..Default::default() };
+            let resp = fetch_rows(db_path.clone(), table.to_string(), query, None).await.unwrap();
+            let data = resp.get_data().clone().unwrap();
+            assert_eq!(data["total"], serde_json::json!(1), "{}", table);
+            assert_eq!(data["rows"][0][1], serde_json::json!("a'b"));
+            assert_eq!(data["columns"].as_array().unwrap().len(), 4);
+            assert_eq!(data["columns"][1]["origin_column"], serde_json::json!("select"));
+            assert_eq!(data["has_rowid"], serde_json::json!(true));
+        }
+
+        // 伪造的rowid和字段名不能注入语句。
+        let table = tables[0].to_string();
+        let resp = edit_data(db_path.clone(), table.clone(), None, None, None, Some(vec!["1) or (1=1".to_string()])).await.unwrap();
+        assert!(!resp.is_success());
+        let update_rows = serde_json::json!({"1 or 1=1": {"select": "z"}});
+        assert!(!edit_data(db_path.clone(), table.clone(), None, None, Some(update_rows), None).await.unwrap().is_success());
+        let update_rows = serde_json::json!({"1": {"select\"=1, \"col with space": "z"}});
+        assert!(!edit_data(db_path.clone(), table.clone(), None, None, Some(update_rows), None).await.unwrap().is_success());
+
+        let rb = open_db_connections(&db_path, &None).unwrap();
+        let count: i64 = rb.deref().fetch_decode("select count(*) from sqlite_master where name = 'victim'", vec![]).await.unwrap();
+        assert_eq!(count, 1);
+        let count: i64 = rb.deref().fetch_decode("select count(*) from \"order\"", vec![]).await.unwrap();
+        assert_eq!(count, 2);
+        remove_db_connection(&db_path).unwrap();
+    }
+
     #[tokio::test]
     pub async fn test_exec_sql() {
         fast_log::init(fast_log::Config::new().console()).expect("rbatis init fail");
         // let query_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "select rowid, * from my_table", Some("123456".to_string())).await;
-        let query_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "pragma table_info(my_table)", Some("123456".to_string())).await;
+        let query_result = exec_sql("/home/liuning/tmp/sqlite/my.db".to_string(), "pragma table_info(my_table)", Some("123456".to_string()), None, None).await;
         if let Err(e) = query_result {
             assert!(false, "查询数据库失败: {}", e);
         } else {
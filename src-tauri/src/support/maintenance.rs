@@ -0,0 +1,160 @@
+//! 数据库维护：VACUUM、VACUUM INTO、ANALYZE、`PRAGMA optimize`和增量VACUUM，并报告维护前后的文件大小和空闲页数。
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+/// 维护操作。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum MaintenanceOp {
+    /// 重建数据库文件，回收空闲页。没有`INTEGER PRIMARY KEY`的表的rowid可能改变。
+    Vacuum,
+    /// 将整理后的数据库写入新文件，源数据库不变。目标文件默认使用源数据库的密钥和加密参数。
+    VacuumInto {
+        target_path: String,
+        /// 目标文件的密钥，为`None`时与源数据库相同。
+        target_key: Option<String>,
+    },
+    Analyze,
+    Optimize,
+    /// 回收至多`pages`个空闲页，为`None`时回收全部。仅在`auto_vacuum`为`incremental`时可用。
+    IncrementalVacuum {
+        pages: Option<u32>,
+    },
+}
+
+/// 数据库文件的空间占用。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpaceUsage {
+    /// 主文件的字节数，不含WAL文件；内存数据库为0。
+    pub file_size: u64,
+    pub page_size: i64,
+    pub page_count: i64,
+    pub freelist_count: i64,
+}
+
+/// 维护结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MaintenanceReport {
+    pub before: SpaceUsage,
+    pub after: SpaceUsage,
+    /// `VACUUM INTO`生成的文件的空间占用，按文件大小和页大小计算。
+    pub target: Option<SpaceUsage>,
+}
+
+/// 查询数据库的页大小、页数和空闲页数，以及主文件的字节数。
+pub fn space_usage(conn: &Connection) -> Result<SpaceUsage, Box<dyn Error>> {
+    let pragma = |name: &str| conn.pragma_query_value(None, name, |r| r.get::<_, i64>(0));
+    // 内存数据库没有对应的文件。
+    let file_size = conn.path().and_then(|p| fs::metadata(p).ok()).map_or(0, |m| m.len());
+    Ok(SpaceUsage { file_size, page_size: pragma("page_size")?, page_count: pragma("page_count")?, freelist_count: pragma("freelist_count")? })
+}
+
+/// WAL模式下将VACUUM写入WAL文件的内容合并回主文件，使主文件的大小反映维护结果。
+fn checkpoint_wal(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    let journal_mode: String = conn.pragma_query_value(None, "journal_mode", |r| r.get(0))?;
+    if journal_mode.eq_ignore_ascii_case("wal") {
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    }
+    Ok(())
+}
+
+/// 执行维护操作，并报告操作前后的空间占用。
+///
+/// `VacuumInto`的`target_key`由调用方处理，本函数生成的目标文件使用源数据库的密钥。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接，执行`VACUUM`时不能有其他连接正在写入。
+/// * `op`: 维护操作。
+///
+/// returns: Result<MaintenanceReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let report = run_maintenance(&conn, &MaintenanceOp::Vacuum).unwrap();
+/// println!("回收了 {} 字节", report.before.file_size - report.after.file_size);
+/// ```
+pub fn run_maintenance(conn: &Connection, op: &MaintenanceOp) -> Result<MaintenanceReport, Box<dyn Error>> {
+    let before = space_usage(conn)?;
+    let mut target = None;
+    match op {
+        MaintenanceOp::Vacuum => {
+            conn.execute_batch("VACUUM")?;
+            checkpoint_wal(conn)?;
+        }
+        MaintenanceOp::VacuumInto { target_path, .. } => {
+            if Path::new(target_path).exists() {
+                return Err(format!("目标文件 {} 已存在", target_path).into());
+            }
+            conn.execute("VACUUM INTO ?1", [target_path])?;
+            let file_size = fs::metadata(target_path)?.len();
+            let page_count = file_size as i64 / before.page_size;
+            target = Some(SpaceUsage { file_size, page_size: before.page_size, page_count, freelist_count: 0 });
+        }
+        MaintenanceOp::Analyze => conn.execute_batch("ANALYZE")?,
+        MaintenanceOp::Optimize => conn.execute_batch("PRAGMA optimize")?,
+        MaintenanceOp::IncrementalVacuum { pages } => {
+            let auto_vacuum: i64 = conn.pragma_query_value(None, "auto_vacuum", |r| r.get(0))?;
+            if auto_vacuum != 2 {
+                return Err("增量VACUUM要求auto_vacuum为incremental，请先设置auto_vacuum并执行一次VACUUM".into());
+            }
+            // incremental_vacuum每步回收一页，须读取完所有结果行才会执行完毕。
+            let mut stmt = conn.prepare(&format!("PRAGMA incremental_vacuum({})", pages.unwrap_or(0)))?;
+            let mut rows = stmt.raw_query();
+            while rows.next()?.is_some() {}
+            checkpoint_wal(conn)?;
+        }
+    }
+    Ok(MaintenanceReport { before, after: space_usage(conn)?, target })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_maintenance() {
+        let mut dir = std::env::temp_dir();
+        dir.push("sqlcipher-front-maintenance");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let conn = Connection::open(dir.join("my.db")).unwrap();
+        conn.pragma_update(None, "key", "123456").unwrap();
+        conn.execute_batch("create table t (v text);
+            with recursive c(x) as (select 1 union all select x + 1 from c where x < 200) insert into t select hex(randomblob(1000)) from c;
+            delete from t where rowid > 20;").unwrap();
+        assert!(run_maintenance(&conn, &MaintenanceOp::IncrementalVacuum { pages: None }).is_err(), "auto_vacuum为none时应拒绝增量VACUUM");
+
+        let target_path = dir.join("vacuumed.db").to_str().unwrap().to_string();
+        let report = run_maintenance(&conn, &MaintenanceOp::VacuumInto { target_path: target_path.clone(), target_key: None }).unwrap();
+        let target = report.target.unwrap();
+        assert_eq!(report.before, report.after, "VACUUM INTO不应修改源数据库");
+        assert!(target.file_size < report.before.file_size && target.page_count > 0, "{:?}", target);
+        assert!(run_maintenance(&conn, &MaintenanceOp::VacuumInto { target_path, target_key: None }).is_err(), "目标文件已存在时应拒绝");
+
+        let report = run_maintenance(&conn, &MaintenanceOp::Vacuum).unwrap();
+        assert!(report.before.freelist_count > 0);
+        assert_eq!(report.after.freelist_count, 0);
+        assert!(report.after.file_size < report.before.file_size);
+
+        conn.execute_batch("pragma auto_vacuum = incremental; vacuum;
+            with recursive c(x) as (select 1 union all select x + 1 from c where x < 100) insert into t select hex(randomblob(1000)) from c;
+            delete from t;").unwrap();
+        let report = run_maintenance(&conn, &MaintenanceOp::IncrementalVacuum { pages: Some(10) }).unwrap();
+        assert_eq!(report.before.freelist_count - report.after.freelist_count, 10);
+        let report = run_maintenance(&conn, &MaintenanceOp::IncrementalVacuum { pages: None }).unwrap();
+        assert_eq!(report.after.freelist_count, 0);
+
+        run_maintenance(&conn, &MaintenanceOp::Analyze).unwrap();
+        let stats: i64 = conn.query_row("select count(*) from sqlite_master where name = 'sqlite_stat1'", [], |r| r.get(0)).unwrap();
+        assert_eq!(stats, 1);
+        run_maintenance(&conn, &MaintenanceOp::Optimize).unwrap();
+    }
+}
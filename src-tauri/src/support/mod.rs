@@ -1,2 +1,20 @@
+pub mod alter;
+pub mod blob;
+pub mod cipher;
+pub mod csv;
+pub mod cursor;
+pub mod db_info;
+pub mod ddl;
+pub mod dump;
+pub mod executor;
+pub mod export;
+pub mod health;
 pub mod history;
-pub mod load_db;
\ No newline at end of file
+pub mod ident;
+pub mod import;
+pub mod index;
+pub mod load_db;
+pub mod maintenance;
+pub mod objects;
+pub mod schema;
+pub mod vault;
\ No newline at end of file
@@ -0,0 +1,330 @@
+//! 视图和触发器的编辑：列出触发器，在一个事务中用新的定义替换视图或触发器，并报告因此失效的其他对象。
+use std::error::Error;
+
+use rusqlite::{Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+
+use crate::support::ddl::{create_header, restore_double_quoted_strings, set_double_quoted_strings, sql_words, trigger_header};
+use crate::support::executor::execute_single;
+use crate::support::ident::quote_ident;
+
+/// 触发器。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriggerEntry {
+    pub name: String,
+    /// 触发器所属的表或视图。
+    pub table_name: String,
+    /// `BEFORE`、`AFTER`或`INSTEAD OF`。
+    pub timing: String,
+    /// `INSERT`、`UPDATE`或`DELETE`。
+    pub event: String,
+    /// `UPDATE OF`指定的字段，为空时任一字段更新都会触发。
+    pub update_columns: Vec<String>,
+    pub sql: String,
+}
+
+/// 替换视图或触发器后失效的对象。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BrokenObject {
+    pub obj_type: String,
+    pub name: String,
+    pub error: String,
+}
+
+/// 替换视图或触发器的结果。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplaceReport {
+    /// `view`或`trigger`。
+    pub obj_type: String,
+    /// 替换后的名称，新的定义可以改名。
+    pub name: String,
+    /// 替换前可以使用、替换后失效的对象。
+    pub broken: Vec<BrokenObject>,
+    /// 是否已提交。有失效的对象且未强制替换时回滚。
+    pub applied: bool,
+}
+
+/// 列出触发器。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `table_name`: 只列出该表或视图上的触发器，为`None`时列出全部。
+///
+/// returns: Result<Vec<TriggerEntry, Global>, Box<dyn Error, Global>>
+pub fn list_triggers(conn: &Connection, table_name: Option<&str>) -> Result<Vec<TriggerEntry>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select name, tbl_name, sql from sqlite_master where type = 'trigger' and (?1 is null or tbl_name = ?1 collate nocase) order by tbl_name, name")?;
+    let rows = stmt.query_map([table_name], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, String>(2)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(rows.into_iter().map(|(name, table_name, sql)| {
+        let header = trigger_header(&sql);
+        TriggerEntry {
+            name,
+            table_name,
+            timing: header.as_ref().map(|h| h.timing.clone()).unwrap_or_default(),
+            event: header.as_ref().map(|h| h.event.clone()).unwrap_or_default(),
+            update_columns: header.map(|h| h.columns).unwrap_or_default(),
+            sql,
+        }
+    }).collect())
+}
+
+/// 检查所有视图能否编译，返回无法编译的视图名及错误信息。
+fn broken_views(conn: &Connection) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select name from sqlite_master where type = 'view' order by name")?;
+    let views = stmt.query_map([], |r| r.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(views.into_iter()
+        .filter_map(|v| conn.prepare(&format!("select * from {}", quote_ident(&v))).err().map(|e| (v, e.to_string())))
+        .collect())
+}
+
+/// 会触发该触发器的语句。触发器体中的语句在编译触发它的语句时才会编译，只编译不执行即可检查触发器能否使用。
+fn trigger_probe(conn: &Connection, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let (table_name, sql): (String, String) = conn.query_row("select tbl_name, sql from sqlite_master where type = 'trigger' and name = ?1", [name], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    let header = match trigger_header(&sql) {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    let table = quote_ident(&table_name);
+    Ok(match header.event.as_str() {
+        "INSERT" => Some(format!("INSERT INTO {} DEFAULT VALUES", table)),
+        "DELETE" => Some(format!("DELETE FROM {}", table)),
+        _ => {
+            let column = match header.columns.into_iter().next() {
+                Some(column) => Some(column),
+                None => conn.query_row("select name from pragma_table_info(?1) order by cid", [&table_name], |r| r.get::<_, String>(0)).optional()?,
+            };
+            column.map(|c| format!("UPDATE {} SET {} = {}", table, quote_ident(&c), quote_ident(&c)))
+        }
+    })
+}
+
+/// 检查触发器能否编译，返回错误信息。
+fn trigger_error(conn: &Connection, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(trigger_probe(conn, name)?.and_then(|probe| conn.prepare(&probe).err().map(|e| e.to_string())))
+}
+
+/// 定义中引用了指定名称的触发器，不含被替换的触发器本身。
+fn referencing_triggers(conn: &Connection, names: &[&str], except: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select name, sql from sqlite_master where type = 'trigger' and name <> ?1 order by name")?;
+    let triggers = stmt.query_map([except], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(triggers.into_iter()
+        .filter(|(_, sql)| sql_words(sql).iter().any(|w| names.iter().any(|n| w.eq_ignore_ascii_case(n))))
+        .map(|(name, _)| name)
+        .collect())
+}
+
+/// 在事务中删除旧对象、创建新对象，并重建随视图一起删除的`INSTEAD OF`触发器。返回无法重建的触发器。
+///
+/// `check_trigger`为`true`时检查新的触发器能否编译，原触发器本就无法编译时不检查。
+fn swap_object(tx: &Transaction, obj_type: &str, name: &str, new_name: &str, sql: &str, check_trigger: bool) -> Result<Vec<BrokenObject>, Box<dyn Error>> {
+    let mut stmt = tx.prepare("select name, sql from sqlite_master where type = 'trigger' and tbl_name = ?1 and ?2 = 'view'")?;
+    let triggers = stmt.query_map([name, obj_type], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    tx.execute_batch(&format!("DROP {} {}", obj_type.to_uppercase(), quote_ident(name)))?;
+    // 先编译新的定义，语法错误或引用的表不存在时在此报错。
+    execute_single(tx, sql)?;
+    // CREATE VIEW不检查引用的字段，查询一次视图才能发现。
+    if obj_type == "view" {
+        tx.prepare(&format!("select * from {}", quote_ident(new_name)))?;
+    }
+
+    // 新的触发器须能够编译。
+    if check_trigger {
+        if let Some(error) = trigger_error(tx, new_name)? {
+            return Err(error.into());
+        }
+    }
+
+    // 按原样重建保存的触发器，允许其中的双引号字符串。
+    let mut broken = vec![];
+    let dqs = set_double_quoted_strings(tx, true)?;
+    for (trigger, trigger_sql) in triggers {
+        // 视图改名后原触发器仍按旧名称重建，会失败并作为失效对象报告。
+        if let Err(e) = tx.execute_batch(&trigger_sql) {
+            broken.push(BrokenObject { obj_type: "trigger".to_string(), name: trigger, error: e.to_string() });
+        }
+    }
+    restore_double_quoted_strings(tx, dqs)?;
+    Ok(broken)
+}
+
+/// 用新的定义替换视图或触发器。
+///
+/// 删除和创建在同一个事务中完成：先删除旧对象，再编译并执行新的`CREATE`语句，出错时回滚。
+/// 替换视图时会重建该视图上的`INSTEAD OF`触发器，并检查其它视图以及定义中引用了该视图的触发器能否编译，替换前可以使用、替换后失效的对象作为结果返回。
+/// 触发器通过编译会触发它的语句来检查，同一张表上同一事件的多个触发器中有一个失效时，其余引用了该视图的触发器也会被报告。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接，不能处于事务中。
+/// * `name`: 要替换的视图或触发器。
+/// * `sql`: 新的`CREATE VIEW`或`CREATE TRIGGER`语句，类型须与原对象相同，可以改名。
+/// * `force`: 有对象失效时是否仍然提交。
+///
+/// returns: Result<ReplaceReport, Box<dyn Error, Global>>
+///
+/// # Examples
+///
+/// ```
+/// let mut conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let report = replace_object(&mut conn, "v_users", "create view v_users as select id, name from users", false).unwrap();
+/// if !report.applied {
+///     println!("以下对象将失效: {:?}", report.broken);
+/// }
+/// ```
+pub fn replace_object(conn: &mut Connection, name: &str, sql: &str, force: bool) -> Result<ReplaceReport, Box<dyn Error>> {
+    let found: Option<(String, String)> = conn.query_row(
+        "select type, name from sqlite_master where type in ('view', 'trigger') and name = ?1 collate nocase",
+        [name], |r| Ok((r.get(0)?, r.get(1)?))).optional()?;
+    let (obj_type, name) = found.ok_or_else(|| format!("视图或触发器 {} 不存在", name))?;
+    let header = create_header(sql).filter(|h| h.obj_type == obj_type && !h.temp)
+        .ok_or_else(|| format!("新的定义须为CREATE {}语句", obj_type.to_uppercase()))?;
+    if !header.name.eq_ignore_ascii_case(&name) {
+        let taken: i64 = conn.query_row("select count(*) from sqlite_master where name = ?1 collate nocase", [&header.name], |r| r.get(0))?;
+        if taken > 0 {
+            return Err(format!("{} 已存在", header.name).into());
+        }
+    }
+
+    // 替换前后都在关闭双引号字符串的情况下检查，只报告替换前可以编译的对象。
+    let dqs = set_double_quoted_strings(conn, false)?;
+    let result = conn.transaction().map_err(|e| e.into()).and_then(|tx| -> Result<ReplaceReport, Box<dyn Error>> {
+        let broken_before: Vec<String> = broken_views(&tx)?.into_iter().map(|(v, _)| v).collect();
+        let check_trigger = obj_type == "trigger" && trigger_error(&tx, &name)?.is_none();
+        let mut triggers = vec![];
+        if obj_type == "view" {
+            for trigger in referencing_triggers(&tx, &[&name, &header.name], &name)? {
+                if trigger_error(&tx, &trigger)?.is_none() {
+                    triggers.push(trigger);
+                }
+            }
+        }
+
+        let mut broken = swap_object(&tx, &obj_type, &name, &header.name, sql, check_trigger)?;
+        for (view, error) in broken_views(&tx)? {
+            if !broken_before.contains(&view) {
+                broken.push(BrokenObject { obj_type: "view".to_string(), name: view, error });
+            }
+        }
+        for trigger in triggers {
+            if broken.iter().any(|b| b.name == trigger) {
+                continue;
+            }
+            if let Some(error) = trigger_error(&tx, &trigger)? {
+                broken.push(BrokenObject { obj_type: "trigger".to_string(), name: trigger, error });
+            }
+        }
+        let applied = broken.is_empty() || force;
+        if applied {
+            tx.commit()?;
+        }
+        Ok(ReplaceReport { obj_type: obj_type.clone(), name: header.name.clone(), broken, applied })
+    });
+    let restored = restore_double_quoted_strings(conn, dqs);
+    let report = result?;
+    restored?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_test_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key, name text, age int);
+            create table logs (msg text);
+            create view v_users as select id, name, age from users;
+            create view v_adults as select name from v_users where age >= 18;
+            create trigger trg_v_users instead of insert on v_users begin insert into users (name, age) values (new.name, new.age); end;
+            create trigger trg_users_age before update of age, name on users begin insert into logs values (old.name); end;
+            create trigger trg_users_del delete on users begin insert into logs values ('d'); end;
+        ").unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_list_triggers() {
+        let conn = open_test_db();
+        let triggers = list_triggers(&conn, None).unwrap();
+        let summary: Vec<(&str, &str, &str, &str)> = triggers.iter().map(|t| (t.name.as_str(), t.table_name.as_str(), t.timing.as_str(), t.event.as_str())).collect();
+        assert_eq!(summary, vec![
+            ("trg_users_age", "users", "BEFORE", "UPDATE"),
+            ("trg_users_del", "users", "AFTER", "DELETE"),
+            ("trg_v_users", "v_users", "INSTEAD OF", "INSERT"),
+        ]);
+        assert_eq!(triggers[0].update_columns, vec!["age", "name"]);
+        assert_eq!(list_triggers(&conn, Some("V_USERS")).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_replace_object() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key, name text, age int);
+            create view v_users as select id, name, age from users;
+            create view v_adults as select name from v_users where age >= 18;
+            create trigger trg_v_users instead of insert on v_users begin insert into users (name, age) values (new.name, new.age); end;
+        ").unwrap();
+
+        // 去掉被其它视图和INSTEAD OF触发器引用的字段，默认回滚。
+        let report = replace_object(&mut conn, "v_users", "create view v_users as select id, name from users", false).unwrap();
+        assert!(!report.applied);
+        assert_eq!(report.broken.iter().map(|b| b.name.as_str()).collect::<Vec<_>>(), vec!["v_adults", "trg_v_users"]);
+        let sql: String = conn.query_row("select sql from sqlite_master where name = 'v_users'", [], |r| r.get(0)).unwrap();
+        assert!(sql.contains("age"), "未提交时应保留原视图");
+
+        let report = replace_object(&mut conn, "V_USERS", "create view v_users (id, name, age) as select id, upper(name), age from users", false).unwrap();
+        assert!(report.applied && report.broken.is_empty(), "{:?}", report.broken);
+        conn.execute("insert into v_users (name, age) values ('a', 20)", []).unwrap();
+        let name: String = conn.query_row("select name from v_adults", [], |r| r.get(0)).unwrap();
+        assert_eq!(name, "A", "INSTEAD OF触发器应随视图重建");
+
+        // 改名后原视图上的触发器无法重建。
+        let report = replace_object(&mut conn, "v_users", "create view v_people as select id, name, age from users", true).unwrap();
+        assert!(report.applied);
+        let broken: Vec<(&str, &str)> = report.broken.iter().map(|b| (b.obj_type.as_str(), b.name.as_str())).collect();
+        assert_eq!(broken, vec![("trigger", "trg_v_users"), ("view", "v_adults")]);
+
+        assert!(replace_object(&mut conn, "v_people", "create view v_people as select \"missing\" from users", true).is_err(), "引用不存在的字段应报错");
+        assert!(replace_object(&mut conn, "v_people", "create table v_people (a)", true).is_err());
+        assert!(replace_object(&mut conn, "v_people", "create view v_x as select 1; drop table users;", true).is_err());
+        assert!(replace_object(&mut conn, "v_people", "create view users as select 1", true).unwrap_err().to_string().contains("已存在"));
+        let users: i64 = conn.query_row("select count(*) from users", [], |r| r.get(0)).unwrap();
+        assert_eq!(users, 1);
+
+        conn.execute_batch("create table logs (msg text); create trigger trg_log after insert on users begin insert into logs values (new.name); end;").unwrap();
+        replace_object(&mut conn, "trg_log", "create trigger trg_log before insert on users begin insert into logs values ('x' || new.name); end", false).unwrap();
+        conn.execute("insert into users (name) values ('b')", []).unwrap();
+        let msg: String = conn.query_row("select msg from logs", [], |r| r.get(0)).unwrap();
+        assert_eq!(msg, "xb");
+        assert_eq!(list_triggers(&conn, Some("users")).unwrap()[0].timing, "BEFORE");
+        assert!(replace_object(&mut conn, "trg_log", "create trigger trg_log after insert on users begin insert into missing values (1); end", true).is_err(), "新的触发器引用了不存在的表");
+    }
+
+    #[test]
+    fn test_replace_object_dependents() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key, name text, age int);
+            create table audit (who text);
+            create view v_users as select id, name, age from users;
+            create view v_legacy as select \"literal\" as x from users;
+            create trigger trg_v_legacy instead of delete on v_users begin insert into audit values (\"gone\"); end;
+            create trigger trg_audit after insert on audit begin delete from users where age in (select v.age from v_users v); end;
+        ").unwrap();
+
+        // 使用双引号字符串的视图和触发器不影响替换。
+        let report = replace_object(&mut conn, "v_users", "create view v_users as select id, name, age + 0 as age from users", false).unwrap();
+        assert!(report.applied && report.broken.is_empty(), "{:?}", report.broken);
+        assert_eq!(list_triggers(&conn, Some("v_users")).unwrap().len(), 1);
+
+        // 其它表上引用了该视图的触发器失效。
+        let report = replace_object(&mut conn, "v_users", "create view v_users as select id, name from users", false).unwrap();
+        assert!(!report.applied);
+        let broken: Vec<(&str, &str)> = report.broken.iter().map(|b| (b.obj_type.as_str(), b.name.as_str())).collect();
+        assert_eq!(broken, vec![("trigger", "trg_audit")]);
+    }
+}
@@ -0,0 +1,242 @@
+//! 表和视图的结构模型：字段、索引、外键、触发器等，由`table_xinfo`、`index_list`、`index_xinfo`、`foreign_key_list`和`table_list`汇总而成。
+use std::error::Error;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::support::ident::quote_ident;
+
+/// 字段定义。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnInfo {
+    pub cid: i64,
+    pub name: String,
+    /// 声明的类型，可能为空字符串。
+    pub decl_type: String,
+    pub not_null: bool,
+    /// 默认值的SQL表达式。
+    pub default_value: Option<String>,
+    /// 在主键中的位置，从1开始，不属于主键时为0。
+    pub pk: i64,
+    /// 0为普通字段，1为虚拟表的隐藏字段，2为VIRTUAL生成列，3为STORED生成列。
+    pub hidden: i64,
+}
+
+/// 索引中的一个字段。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexColumn {
+    /// 表达式索引的字段为`None`。
+    pub name: Option<String>,
+    pub desc: bool,
+    pub collation: Option<String>,
+}
+
+/// 索引定义。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexInfo {
+    pub name: String,
+    pub unique: bool,
+    /// 创建方式：`c`为`CREATE INDEX`，`u`为UNIQUE约束，`pk`为主键。
+    pub origin: String,
+    pub partial: bool,
+    pub columns: Vec<IndexColumn>,
+    /// 由约束自动创建的索引没有SQL。
+    pub sql: Option<String>,
+}
+
+/// 外键定义，多字段外键的字段按顺序排列。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForeignKeyInfo {
+    pub id: i64,
+    /// 被引用的表。
+    pub table: String,
+    pub from: Vec<String>,
+    /// 被引用的字段，省略时引用被引用表的主键，为`None`。
+    pub to: Vec<Option<String>>,
+    pub on_update: String,
+    pub on_delete: String,
+}
+
+/// 触发器定义。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TriggerInfo {
+    pub name: String,
+    pub sql: Option<String>,
+}
+
+/// 表或视图的完整结构。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectModel {
+    pub name: String,
+    /// `table`、`view`或`virtual`。
+    pub obj_type: String,
+    pub sql: Option<String>,
+    pub columns: Vec<ColumnInfo>,
+    pub indexes: Vec<IndexInfo>,
+    pub foreign_keys: Vec<ForeignKeyInfo>,
+    pub triggers: Vec<TriggerInfo>,
+    pub without_rowid: bool,
+    pub strict: bool,
+    /// 表的总行数，视图和虚拟表不统计，为`None`。
+    pub row_count: Option<u64>,
+}
+
+fn load_columns(conn: &Connection, name: &str) -> Result<Vec<ColumnInfo>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select cid, name, type, \"notnull\", dflt_value, pk, hidden from pragma_table_xinfo(?1)")?;
+    let columns = stmt.query_map([name], |r| Ok(ColumnInfo {
+        cid: r.get(0)?,
+        name: r.get(1)?,
+        decl_type: r.get(2)?,
+        not_null: r.get(3)?,
+        default_value: r.get(4)?,
+        pk: r.get(5)?,
+        hidden: r.get(6)?,
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(columns)
+}
+
+pub(crate) fn load_indexes(conn: &Connection, name: &str) -> Result<Vec<IndexInfo>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select l.name, l.\"unique\", l.origin, l.partial, m.sql from pragma_index_list(?1) l
+        left join sqlite_master m on m.type = 'index' and m.name = l.name order by l.seq desc")?;
+    let mut indexes = stmt.query_map([name], |r| Ok(IndexInfo {
+        name: r.get(0)?,
+        unique: r.get(1)?,
+        origin: r.get(2)?,
+        partial: r.get(3)?,
+        columns: vec![],
+        sql: r.get(4)?,
+    }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // 只取索引的键字段，不含附带的rowid或主键字段。
+    let mut stmt = conn.prepare("select name, \"desc\", coll from pragma_index_xinfo(?1) where key = 1 order by seqno")?;
+    for index in indexes.iter_mut() {
+        index.columns = stmt.query_map([&index.name], |r| Ok(IndexColumn { name: r.get(0)?, desc: r.get(1)?, collation: r.get(2)? }))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+    }
+    Ok(indexes)
+}
+
+fn load_foreign_keys(conn: &Connection, name: &str) -> Result<Vec<ForeignKeyInfo>, Box<dyn Error>> {
+    let mut stmt = conn.prepare("select id, \"table\", \"from\", \"to\", on_update, on_delete from pragma_foreign_key_list(?1) order by id, seq")?;
+    let mut rows = stmt.query([name])?;
+    let mut foreign_keys: Vec<ForeignKeyInfo> = vec![];
+    while let Some(r) = rows.next()? {
+        let id: i64 = r.get(0)?;
+        if foreign_keys.last().map_or(true, |fk| fk.id != id) {
+            foreign_keys.push(ForeignKeyInfo { id, table: r.get(1)?, from: vec![], to: vec![], on_update: r.get(4)?, on_delete: r.get(5)? });
+        }
+        if let Some(fk) = foreign_keys.last_mut() {
+            fk.from.push(r.get(2)?);
+            fk.to.push(r.get(3)?);
+        }
+    }
+    Ok(foreign_keys)
+}
+
+/// 查询表或视图的完整结构。
+///
+/// # Arguments
+///
+/// * `conn`: 已设置密钥的连接。
+/// * `name`: 表名或视图名。
+///
+/// returns: Result<ObjectModel, Box<dyn Error, Global>> 对象不存在时返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let conn = Connection::open("/home/foo/tmp/sqlite/my.db").unwrap();
+/// let model = describe_object(&conn, "users").unwrap();
+/// for column in model.columns {
+///     println!("{} {} {}", column.name, column.decl_type, column.pk);
+/// }
+/// ```
+pub fn describe_object(conn: &Connection, name: &str) -> Result<ObjectModel, Box<dyn Error>> {
+    let listed: Option<(String, String, bool, bool)> = conn.query_row(
+        "select name, type, wr, strict from pragma_table_list where schema = 'main' and name = ?1 collate nocase",
+        [name], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?))).optional()?;
+    let (name, obj_type, without_rowid, strict) = listed.ok_or_else(|| format!("表或视图 {} 不存在", name))?;
+    let sql: Option<String> = conn.query_row("select sql from sqlite_master where name = ?1", [&name], |r| r.get(0)).optional()?.flatten();
+
+    let mut stmt = conn.prepare("select name, sql from sqlite_master where type = 'trigger' and tbl_name = ?1 order by name")?;
+    let triggers = stmt.query_map([&name], |r| Ok(TriggerInfo { name: r.get(0)?, sql: r.get(1)? }))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let row_count = if obj_type == "table" {
+        let count: i64 = conn.query_row(&format!("select count(*) from {}", quote_ident(&name)), [], |r| r.get(0))?;
+        Some(count as u64)
+    } else {
+        None
+    };
+
+    Ok(ObjectModel {
+        columns: load_columns(conn, &name)?,
+        indexes: load_indexes(conn, &name)?,
+        foreign_keys: load_foreign_keys(conn, &name)?,
+        triggers,
+        without_rowid,
+        strict,
+        row_count,
+        name,
+        obj_type,
+        sql,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_object() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("
+            create table users (id integer primary key, email text not null unique collate nocase, name text default 'anon', unique (id, email));
+            create table \"order items\" (
+                order_id integer, line integer, user_id integer, email text, total real, doubled real as (total * 2),
+                primary key (order_id, line),
+                foreign key (user_id, email) references users (id, email) on delete cascade,
+                foreign key (order_id) references users
+            ) without rowid;
+            create index idx_items_total on \"order items\" (total desc, lower(email)) where total > 0;
+            create trigger trg_items after insert on \"order items\" begin select 1; end;
+            create table strict_t (v integer) strict;
+            create view v_users as select id, email from users;
+            insert into users (email) values ('a'), ('b');
+        ").unwrap();
+
+        let model = describe_object(&conn, "ORDER ITEMS").unwrap();
+        assert_eq!((model.name.as_str(), model.obj_type.as_str(), model.without_rowid, model.strict), ("order items", "table", true, false));
+        assert_eq!(model.row_count, Some(0));
+        let pk: Vec<(&str, i64)> = model.columns.iter().filter(|c| c.pk > 0).map(|c| (c.name.as_str(), c.pk)).collect();
+        assert_eq!(pk, vec![("order_id", 1), ("line", 2)]);
+        assert_eq!(model.columns.iter().find(|c| c.name == "doubled").unwrap().hidden, 2);
+
+        let index = model.indexes.iter().find(|i| i.name == "idx_items_total").unwrap();
+        assert!(index.partial && !index.unique);
+        assert_eq!(index.columns, vec![
+            IndexColumn { name: Some("total".to_string()), desc: true, collation: Some("BINARY".to_string()) },
+            IndexColumn { name: None, desc: false, collation: Some("BINARY".to_string()) },
+        ]);
+        assert!(model.indexes.iter().any(|i| i.origin == "pk" && i.sql.is_none()));
+
+        assert_eq!(model.foreign_keys.len(), 2);
+        let fk = model.foreign_keys.iter().find(|f| f.from.len() == 2).unwrap();
+        assert_eq!((fk.table.as_str(), fk.on_delete.as_str()), ("users", "CASCADE"));
+        assert_eq!(fk.to, vec![Some("id".to_string()), Some("email".to_string())]);
+        assert_eq!(model.foreign_keys.iter().find(|f| f.from.len() == 1).unwrap().to, vec![None]);
+        assert_eq!(model.triggers.len(), 1);
+
+        let users = describe_object(&conn, "users").unwrap();
+        assert_eq!(users.row_count, Some(2));
+        let email = &users.columns[1];
+        assert!(email.not_null);
+        assert_eq!(users.columns[2].default_value.as_deref(), Some("'anon'"));
+        let unique = users.indexes.iter().find(|i| i.origin == "u" && i.columns.len() == 1).unwrap();
+        assert_eq!(unique.columns[0].collation.as_deref(), Some("nocase"), "排序规则保留声明时的大小写");
+
+        assert!(describe_object(&conn, "strict_t").unwrap().strict);
+        let view = describe_object(&conn, "v_users").unwrap();
+        assert_eq!((view.obj_type.as_str(), view.row_count, view.columns.len()), ("view", None, 2));
+        assert!(describe_object(&conn, "missing").is_err());
+    }
+}
@@ -0,0 +1,288 @@
+//! 数据库密钥库。密钥保存在一个单独的SQLCipher加密库中，历史记录里只保存密钥的引用编号。
+//!
+//! 首次使用时由用户选择使用主密码加密，或使用本地随机生成的密钥文件加密。
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// 已解锁的密钥库连接。
+static VAULT: Lazy<Mutex<Option<Connection>>> = Lazy::new(|| Mutex::new(None));
+
+const VAULT_FILE: &str = "vault.db";
+const VAULT_KEY_FILE: &str = "vault.key";
+
+#[derive(Debug)]
+pub struct VaultLocked;
+
+impl Display for VaultLocked {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "密钥库尚未解锁")
+    }
+}
+
+impl VaultLocked {
+    /// 接口响应的错误代码，前端据此提示用户先解锁密钥库。
+    pub fn code(&self) -> i32 {
+        1005
+    }
+}
+
+impl Error for VaultLocked {}
+
+/// 密钥库状态。
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VaultStatus {
+    /// 密钥库文件是否已创建。
+    exists: bool,
+    /// 是否使用本地密钥文件加密，密钥库已创建且为`false`时需要主密码解锁。
+    uses_key_file: bool,
+    unlocked: bool,
+}
+
+/// 查询密钥库状态。
+///
+/// # Arguments
+///
+/// * `data_path`: 密钥库保存目录，与历史记录保存目录相同。
+///
+/// returns: Result<VaultStatus, Box<dyn Error, Global>>
+pub fn vault_status(data_path: PathBuf) -> Result<VaultStatus, Box<dyn Error>> {
+    let exists = data_path.join(VAULT_FILE).exists();
+    let uses_key_file = data_path.join(VAULT_KEY_FILE).exists();
+    let unlocked = VAULT.lock()?.is_some();
+    Ok(VaultStatus { exists, uses_key_file, unlocked })
+}
+
+/// 创建密钥库并解锁。
+///
+/// # Arguments
+///
+/// * `data_path`: 密钥库保存目录，与历史记录保存目录相同。
+/// * `master_password`: 主密码。为`None`时生成本地密钥文件，使用该文件加密密钥库。
+///
+/// returns: Result<(), Box<dyn Error, Global>> 密钥库已存在时返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let data_path = PathBuf::from("/home/john/tmp");
+/// if let Err(e) = create_vault(data_path, None) {
+///     assert!(false, "创建密钥库失败 {}", e);
+/// }
+/// ```
+pub fn create_vault(data_path: PathBuf, master_password: Option<String>) -> Result<(), Box<dyn Error>> {
+    if data_path.join(VAULT_FILE).exists() {
+        return Err("密钥库已存在".into());
+    }
+    let vault_key = match master_password {
+        Some(password) => password,
+        None => format!("x'{}'", read_or_create_key_file(data_path.join(VAULT_KEY_FILE))?),
+    };
+    open_vault(data_path, vault_key)
+}
+
+/// 解锁已创建的密钥库。
+///
+/// # Arguments
+///
+/// * `data_path`: 密钥库保存目录，与历史记录保存目录相同。
+/// * `master_password`: 主密码。为`None`时使用本地密钥文件。
+///
+/// returns: Result<(), Box<dyn Error, Global>> 密钥库尚未创建或主密码错误时返回错误。
+///
+/// # Examples
+///
+/// ```
+/// let data_path = PathBuf::from("/home/john/tmp");
+/// if let Err(e) = unlock_vault(data_path, Some("master".to_string())) {
+///     assert!(false, "解锁密钥库失败 {}", e);
+/// }
+/// ```
+pub fn unlock_vault(data_path: PathBuf, master_password: Option<String>) -> Result<(), Box<dyn Error>> {
+    if !data_path.join(VAULT_FILE).exists() {
+        return Err("密钥库尚未创建，请先选择使用主密码或本地密钥文件".into());
+    }
+    let vault_key = match master_password {
+        Some(password) => password,
+        None => {
+            let key_file = data_path.join(VAULT_KEY_FILE);
+            if !key_file.exists() {
+                return Err("密钥库已设置主密码，请输入主密码解锁".into());
+            }
+            format!("x'{}'", read_or_create_key_file(key_file)?)
+        }
+    };
+    open_vault(data_path, vault_key)
+}
+
+/// 以密钥打开密钥库文件，文件不存在时创建，校验通过后保存为已解锁的连接。
+fn open_vault(data_path: PathBuf, vault_key: String) -> Result<(), Box<dyn Error>> {
+    let conn = Connection::open(data_path.join(VAULT_FILE))?;
+    conn.pragma_update(None, "key", vault_key)?;
+    if conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(())).is_err() {
+        return Err("主密码错误，无法解锁密钥库".into());
+    }
+    conn.execute("CREATE TABLE IF NOT EXISTS db_key (id TEXT PRIMARY KEY, secret TEXT NOT NULL)", [])?;
+
+    VAULT.lock()?.replace(conn);
+    Ok(())
+}
+
+/// 确认密钥库已解锁，在修改密钥等需要同步更新密钥库的操作前调用。
+pub fn ensure_unlocked() -> Result<(), Box<dyn Error>> {
+    if VAULT.lock()?.is_none() {
+        return Err(VaultLocked.into());
+    }
+    Ok(())
+}
+
+/// 锁定密钥库，之后读取或保存密钥前须重新解锁。
+pub fn lock_vault() -> Result<(), Box<dyn Error>> {
+    VAULT.lock()?.take();
+    Ok(())
+}
+
+/// 读取本地密钥文件，不存在时生成一个随机的256位密钥并保存。
+fn read_or_create_key_file(key_file: PathBuf) -> Result<String, Box<dyn Error>> {
+    if key_file.exists() {
+        let mut hex = String::new();
+        File::open(key_file)?.read_to_string(&mut hex)?;
+        let hex = hex.trim().to_string();
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("密钥文件已损坏".into());
+        }
+        return Ok(hex);
+    }
+
+    let hex = random_hex(32)?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(key_file)?;
+    file.write_all(hex.as_bytes())?;
+    file.flush()?;
+    Ok(hex)
+}
+
+/// 使用SQLCipher的随机数生成器生成指定字节数的随机值，以十六进制字符串返回。
+fn random_hex(bytes: u32) -> Result<String, Box<dyn Error>> {
+    let conn = Connection::open_in_memory()?;
+    Ok(conn.query_row("SELECT hex(randomblob(?1))", [bytes], |r| r.get(0))?)
+}
+
+/// 将密钥保存到密钥库。
+///
+/// # Arguments
+///
+/// * `secret`: 数据库密钥。
+///
+/// returns: Result<String, Box<dyn Error, Global>> 返回密钥的引用编号，用于保存在历史记录中。
+pub fn store_key(secret: &str) -> Result<String, Box<dyn Error>> {
+    let vault = VAULT.lock()?;
+    let conn = vault.as_ref().ok_or(VaultLocked)?;
+    let key_ref = random_hex(16)?;
+    conn.execute("INSERT INTO db_key (id, secret) VALUES (?1, ?2)", [&key_ref, secret])?;
+    Ok(key_ref)
+}
+
+/// 按引用编号读取密钥，引用不存在时返回`None`。
+pub fn fetch_key(key_ref: &str) -> Result<Option<String>, Box<dyn Error>> {
+    let vault = VAULT.lock()?;
+    let conn = vault.as_ref().ok_or(VaultLocked)?;
+    Ok(conn.query_row("SELECT secret FROM db_key WHERE id = ?1", [key_ref], |r| r.get(0)).optional()?)
+}
+
+/// 从密钥库中删除密钥。
+pub fn forget_key(key_ref: &str) -> Result<(), Box<dyn Error>> {
+    let vault = VAULT.lock()?;
+    let conn = vault.as_ref().ok_or(VaultLocked)?;
+    conn.execute("DELETE FROM db_key WHERE id = ?1", [key_ref])?;
+    Ok(())
+}
+
+/// 测试用例共用的密钥库。密钥库是全局状态，使用该锁避免并行执行的测试用例相互干扰。
+#[cfg(test)]
+pub(crate) fn unlock_test_vault() -> std::sync::MutexGuard<'static, ()> {
+    static TEST_VAULT_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+    let guard = TEST_VAULT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut data_path = std::env::temp_dir();
+    data_path.push("sqlcipher-front-test-vault");
+    fs::create_dir_all(&data_path).unwrap();
+    if data_path.join(VAULT_FILE).exists() {
+        unlock_vault(data_path, None).unwrap();
+    } else {
+        create_vault(data_path, None).unwrap();
+    }
+    guard
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_vault() {
+        let _guard = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-vault");
+        let _ = fs::remove_dir_all(&data_path);
+        fs::create_dir_all(&data_path).unwrap();
+
+        let status = vault_status(data_path.clone()).unwrap();
+        assert!(!status.exists && !status.uses_key_file);
+        assert!(unlock_vault(data_path.clone(), None).is_err(), "密钥库须先创建");
+        assert!(!data_path.join(VAULT_KEY_FILE).exists(), "解锁失败时不应生成密钥文件");
+
+        create_vault(data_path.clone(), None).unwrap();
+        assert!(vault_status(data_path.clone()).unwrap().uses_key_file);
+        assert!(create_vault(data_path.clone(), None).is_err(), "密钥库已存在时不能重复创建");
+        ensure_unlocked().unwrap();
+        let key_ref = store_key("123456").unwrap();
+        assert_eq!(fetch_key(&key_ref).unwrap(), Some("123456".to_string()));
+
+        // 重新解锁后密钥仍可读取。
+        lock_vault().unwrap();
+        assert!(fetch_key(&key_ref).is_err(), "锁定后不应能读取密钥");
+        assert!(ensure_unlocked().unwrap_err().is::<VaultLocked>());
+        unlock_vault(data_path.clone(), None).unwrap();
+        assert_eq!(fetch_key(&key_ref).unwrap(), Some("123456".to_string()));
+
+        forget_key(&key_ref).unwrap();
+        assert_eq!(fetch_key(&key_ref).unwrap(), None);
+
+        // 密钥库文件不应包含明文密钥。
+        let mut content = vec![];
+        File::open(data_path.join(VAULT_FILE)).unwrap().read_to_end(&mut content).unwrap();
+        assert!(!String::from_utf8_lossy(&content).contains("db_key"));
+    }
+
+    #[test]
+    fn test_vault_master_password() {
+        let _guard = unlock_test_vault();
+        let mut data_path = env::temp_dir();
+        data_path.push("sqlcipher-front-vault-master");
+        let _ = fs::remove_dir_all(&data_path);
+        fs::create_dir_all(&data_path).unwrap();
+
+        create_vault(data_path.clone(), Some("master".to_string())).unwrap();
+        assert!(!vault_status(data_path.clone()).unwrap().uses_key_file);
+        lock_vault().unwrap();
+        assert!(unlock_vault(data_path.clone(), Some("wrong".to_string())).is_err());
+        assert!(unlock_vault(data_path.clone(), None).is_err(), "主密码加密的密钥库不能用密钥文件解锁");
+        assert!(!data_path.join(VAULT_KEY_FILE).exists());
+    }
+}
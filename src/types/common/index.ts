@@ -28,6 +28,15 @@ export declare type ApiResp<T = any> = {
     data: T;
 }
 
+// 打开数据库失败时的错误代码，对应后端的`OpenDbError`和`VaultLocked`
+export const enum OpenDbErrorCode {
+    WrongKey = 1001,
+    FileMissing = 1002,
+    PermissionDenied = 1003,
+    Locked = 1004,
+    VaultLocked = 1005,
+}
+
 // 类型
 export const emitter = mitt();
 
@@ -53,20 +62,383 @@ export declare type CurrentDbAndTable = {
     data?: any
 }
 
+/**
+ * 表数据，对应后端的`TableData`。`has_rowid`为`true`时每行的第一列为`rowid`。
+ */
 export declare type TableData = {
-    cols?: ColumnMeta[],
-    rows?: object[]
+    columns: ColumnDesc[],
+    rows: any[][],
+    has_rowid: boolean,
+    total?: number
+}
+
+/**
+ * 表数据中BLOB值的占位对象，内容通过`fetch_blob`单独读取。
+ */
+export declare type BlobRef = {
+    blob_size: number,
+}
+
+/**
+ * BLOB内容预览，对应后端的`BlobPreview`。
+ */
+export declare type BlobPreview =
+    { kind: 'image', mime: string }
+    | { kind: 'text', text: string }
+    | { kind: 'json', value: any }
+    | { kind: 'msgpack', value: any }
+    | { kind: 'binary' };
+
+/**
+ * BLOB单元格的内容，对应后端的`BlobCell`。
+ */
+export declare type BlobCell = {
+    size: number,
+    encoding: 'hex' | 'base64',
+    data: string,
+    truncated: boolean,
+    preview: BlobPreview,
+}
+
+export declare type SortSpec = {
+    column: string,
+    desc?: boolean
 }
 
-export declare type ColumnMeta = {
+/**
+ * 过滤条件，对应后端的`FilterSpec`。
+ */
+export declare type FilterSpec = {
+    column: string,
+    op: string,
+    value?: any
+}
+
+/**
+ * 结果集字段描述，对应后端的`ColumnDesc`。表达式等非表字段没有声明类型和来源。
+ */
+export declare type ColumnDesc = {
     name: string,
-    type: string,
+    decl_type?: string,
+    origin_table?: string,
+    origin_column?: string,
+    nullable: boolean,
 }
 
 export declare type ExecParam = {
     dbPath: string,
     sql: string,
-    key?: string
+    key?: string,
+    /**
+     * 数组按顺序绑定到`?`参数，对象按名称绑定到`:name`等命名参数。
+     */
+    params?: any[] | { [k: string]: any },
+    /**
+     * 查询编号，用于取消查询。
+     */
+    queryId?: string
+}
+
+/**
+ * 脚本中单条语句的执行结果，对应后端的`StatementResult`。
+ */
+export declare type StatementResult = {
+    sql: string,
+    line: number,
+    columns?: ColumnDesc[],
+    rows?: any[][],
+    changes: number,
+    elapsed_ms: number,
+}
+
+/**
+ * 可取消的脚本执行结果，对应后端的`ScriptOutcome`。
+ */
+export declare type ScriptOutcome = {
+    query_id: string,
+    status: 'completed' | 'interrupted' | 'timed_out',
+    results: StatementResult[],
+}
+
+/**
+ * 结果集游标的基本信息，对应后端的`CursorInfo`。
+ */
+export declare type CursorInfo = {
+    cursor_id: string,
+    columns: ColumnDesc[],
+}
+
+/**
+ * 游标读取的一批数据，对应后端的`RowBatch`。`done`为`true`时游标已自动关闭。
+ */
+export declare type RowBatch = {
+    rows: any[][],
+    done: boolean,
+}
+
+/**
+ * CSV格式选项，对应后端的`CsvFormat`。
+ */
+export declare type CsvFormat = {
+    delimiter: string,
+    quote: string,
+    has_header: boolean,
+    quote_style?: 'necessary' | 'always' | 'non_numeric',
+}
+
+/**
+ * CSV文件预览，对应后端的`CsvPreview`。`inferred_type`为`INTEGER`、`REAL`或`TEXT`。
+ */
+export declare type CsvPreview = {
+    format: CsvFormat,
+    columns: { index: number, name: string, inferred_type: string }[],
+    rows: string[][],
+}
+
+/**
+ * CSV导入结果，对应后端的`CsvImportReport`。
+ */
+export declare type CsvImportReport = {
+    inserted: number,
+    rejected: { line: number, reason: string }[],
+    rejected_count: number,
+    created_table: boolean,
+    rolled_back: boolean,
+}
+
+/**
+ * 导出的数据来源，对应后端的`ExportSource`。
+ */
+export declare type ExportSource =
+    { kind: 'table', table_name: string, order_by?: { column: string, desc: boolean }[], filters?: any[] }
+    | { kind: 'query', sql: string, params?: any }
+
+/**
+ * 数据导出选项，对应后端的`ExportOptions`。
+ */
+export declare type ExportOptions = {
+    format?: 'csv' | 'json' | 'ndjson',
+    csv?: CsvFormat,
+    blob_encoding?: 'hex' | 'base64',
+}
+
+/**
+ * `data-export-progress`事件的内容，对应后端的`DataExportProgress`。
+ */
+export declare type DataExportProgress = {
+    target_path: string,
+    rows: number,
+    total_rows?: number,
+}
+
+/**
+ * SQL脚本导出选项，对应后端的`DumpOptions`。`objects`为空时导出全部对象。
+ */
+export declare type DumpOptions = {
+    objects?: string[],
+    schema_only?: boolean,
+}
+
+/**
+ * `backup-progress`事件的内容，对应后端的`BackupProgress`。
+ */
+export declare type BackupProgress = {
+    target_path: string,
+    copied_pages: number,
+    total_pages: number,
+}
+
+/**
+ * 健康检查项，对应后端的`CheckKind`。
+ */
+export declare type CheckKind = 'integrity' | 'quick' | 'foreign_key' | 'cipher'
+
+/**
+ * 健康检查报告，对应后端的`HealthReport`。
+ */
+export declare type HealthReport = {
+    ok: boolean,
+    checks: {
+        check: CheckKind,
+        findings: { object?: string, page?: number, rowid?: number, message: string }[],
+        error?: string,
+        skipped?: string,
+    }[],
+}
+
+/**
+ * 维护操作，对应后端的`MaintenanceOp`。
+ */
+export declare type MaintenanceOp =
+    { op: 'vacuum' } | { op: 'analyze' } | { op: 'optimize' }
+    | { op: 'vacuum_into', target_path: string, target_key?: string }
+    | { op: 'incremental_vacuum', pages?: number }
+
+export declare type SpaceUsage = {
+    file_size: number,
+    page_size: number,
+    page_count: number,
+    freelist_count: number,
+}
+
+/**
+ * 维护结果，对应后端的`MaintenanceReport`。
+ */
+export declare type MaintenanceReport = {
+    before: SpaceUsage,
+    after: SpaceUsage,
+    target?: SpaceUsage,
+}
+
+/**
+ * 数据库属性，对应后端的`DbInfo`。
+ */
+export declare type DbInfo = SpaceUsage & {
+    encoding: string,
+    journal_mode: string,
+    auto_vacuum: 'none' | 'full' | 'incremental',
+    user_version: number,
+    application_id: number,
+    schema_version: number,
+    foreign_keys: boolean,
+    cipher_version?: string,
+    cipher_provider?: string,
+    cipher_settings: string[],
+}
+
+/**
+ * 可写的PRAGMA，对应后端的`PragmaUpdate`。
+ */
+export declare type PragmaUpdate =
+    { pragma: 'user_version' | 'application_id', value: number }
+    | { pragma: 'journal_mode', value: 'delete' | 'wal' }
+    | { pragma: 'foreign_keys', value: boolean }
+
+/**
+ * 表或视图的完整结构，对应后端的`ObjectModel`。
+ */
+export declare type ObjectModel = {
+    name: string,
+    obj_type: 'table' | 'view' | 'virtual',
+    sql?: string,
+    columns: {
+        cid: number,
+        name: string,
+        decl_type: string,
+        not_null: boolean,
+        default_value?: string,
+        pk: number,
+        hidden: number,
+    }[],
+    indexes: {
+        name: string,
+        unique: boolean,
+        origin: 'c' | 'u' | 'pk',
+        partial: boolean,
+        columns: { name?: string, desc: boolean, collation?: string }[],
+        sql?: string,
+    }[],
+    foreign_keys: {
+        id: number,
+        table: string,
+        from: string[],
+        to: (string | null)[],
+        on_update: string,
+        on_delete: string,
+    }[],
+    triggers: { name: string, sql?: string }[],
+    without_rowid: boolean,
+    strict: boolean,
+    row_count?: number,
+}
+
+/**
+ * 表定义，对应后端的`TableSpec`。修改表结构时，保留的字段须设置`original_name`。
+ */
+export declare type TableSpec = {
+    name: string,
+    columns: {
+        original_name?: string,
+        name: string,
+        decl_type: string,
+        not_null: boolean,
+        default_value?: string,
+        collation?: string,
+        generated?: string,
+        stored: boolean,
+    }[],
+    primary_key: string[],
+    autoincrement: boolean,
+    unique: string[][],
+    checks: string[],
+    foreign_keys: {
+        columns: string[],
+        ref_table: string,
+        ref_columns: string[],
+        on_update?: string,
+        on_delete?: string,
+    }[],
+    without_rowid: boolean,
+    strict: boolean,
+}
+
+/**
+ * 修改表结构的SQL语句，对应后端的`AlterPlan`。
+ */
+export declare type AlterPlan = {
+    strategy: 'native' | 'rebuild',
+    statements: string[],
+    applied: boolean,
+}
+
+/**
+ * 索引，对应后端的`IndexEntry`。
+ */
+export declare type IndexEntry = ObjectModel['indexes'][number] & {
+    table_name: string,
+}
+
+/**
+ * 新建索引的定义，对应后端的`IndexSpec`。每个键须且只能设置`column`和`expression`之一。
+ */
+export declare type IndexSpec = {
+    name: string,
+    table_name: string,
+    keys: { column?: string, expression?: string, collation?: string, desc?: boolean }[],
+    unique?: boolean,
+    where_clause?: string,
+}
+
+/**
+ * 索引使用情况的分析结果，对应后端的`IndexUsageReport`。`query_count`为0的索引未被使用。
+ */
+export declare type IndexUsageReport = {
+    queries: number,
+    indexes: { index_name: string, table_name: string, query_count: number }[],
+    full_scans: { query: string, table_name: string, detail: string, candidate_columns: string[], automatic_index: boolean }[],
+    errors: { query: string, message: string }[],
+}
+
+/**
+ * 触发器，对应后端的`TriggerEntry`。`update_columns`为`UPDATE OF`指定的字段。
+ */
+export declare type TriggerEntry = {
+    name: string,
+    table_name: string,
+    timing: 'BEFORE' | 'AFTER' | 'INSTEAD OF',
+    event: 'INSERT' | 'UPDATE' | 'DELETE',
+    update_columns: string[],
+    sql: string,
+}
+
+/**
+ * 替换视图或触发器的结果，对应后端的`ReplaceReport`。`applied`为`false`时已回滚。
+ */
+export declare type ReplaceReport = {
+    obj_type: 'view' | 'trigger',
+    name: string,
+    broken: { obj_type: 'view' | 'trigger', name: string, error: string }[],
+    applied: boolean,
 }
 
 export declare type SqlSelection = {
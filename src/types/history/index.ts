@@ -1,5 +1,31 @@
+export declare type CipherPreset = 'default' | 'sqlcipher1' | 'sqlcipher2' | 'sqlcipher3' | 'sqlcipher4' | 'custom';
+
+/**
+ * SQLCipher加密参数，对应后端的`CipherProfile`。
+ */
+export declare type CipherProfile = {
+    preset: CipherPreset,
+    kdf_iter?: number,
+    cipher_page_size?: number,
+    hmac_algorithm?: string,
+    kdf_algorithm?: string,
+    use_hmac?: boolean,
+    plaintext_header_size?: number,
+    salt?: string,
+}
+
 export declare type History = {
     name: string,
     path: string,
-    key?: string
+    /**
+     * 是否在密钥库中保存了密钥，密钥本身不会返回给前端。
+     */
+    encrypted?: boolean,
+    cipher?: CipherProfile
+}
+
+export declare type VaultStatus = {
+    exists: boolean,
+    uses_key_file: boolean,
+    unlocked: boolean,
 }